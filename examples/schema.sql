@@ -0,0 +1,14 @@
+CREATE TABLE users (
+    id SERIAL PRIMARY KEY,
+    uuid UUID NOT NULL UNIQUE,
+    first_name VARCHAR(50) NOT NULL,
+    last_name VARCHAR(50) NOT NULL,
+    gender VARCHAR(6),
+    age INT NOT NULL CHECK (age BETWEEN 18 AND 99),
+    country CHAR(2) NOT NULL,
+    phone VARCHAR(20),
+    status TEXT NOT NULL CHECK (status IN ('active', 'inactive', 'banned')),
+    verified BOOLEAN NOT NULL DEFAULT false,
+    born DATE NOT NULL CHECK (born >= '1950-01-01' AND born <= '2005-12-31'),
+    created_at TIMESTAMP NOT NULL DEFAULT now()
+);
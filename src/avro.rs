@@ -0,0 +1,728 @@
+use std::convert::TryFrom;
+use std::io::Write;
+use std::path::Path;
+
+use chrono::Datelike;
+use flate2::write::DeflateEncoder;
+use rand::Rng;
+use serde_json::Value as Json;
+
+use crate::columnar::Compression;
+use crate::generust::{
+    Context, Error, FieldDef, Kind, Result, Value, DECIMAL_PRECISION, DECIMAL_SCALE,
+    EPOCH_DAYS_FROM_CE,
+};
+use crate::writer::Writer;
+
+const MAGIC: &[u8] = b"Obj\x01";
+const SINGLE_OBJECT: &[u8] = &[0xc3, 0x01];
+const EMPTY: u64 = 0xc15d_213a_a4d7_a795;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Type {
+    Null,
+    Boolean,
+    Int,
+    Long,
+    Float,
+    Double,
+    Bytes,
+    String,
+    Enum(String, Vec<String>),
+    Decimal(u32, u32),
+    Uuid,
+    Date,
+    TimestampMillis,
+    TimestampMicros,
+    Union(Vec<Type>),
+}
+
+struct Schema {
+    name: String,
+    fields: Vec<(String, Type)>,
+}
+
+fn invalid<T>(msg: String) -> Result<T> {
+    Err(Error::Schema(format!("avro: {}", msg)))
+}
+
+fn full_name(json: &Json, namespace: Option<&str>) -> Result<String> {
+    let name = match json.get("name").and_then(|n| n.as_str()) {
+        Some(name) => name,
+        None => return invalid(format!("name is required in {}", json)),
+    };
+    let namespace = json
+        .get("namespace")
+        .and_then(|n| n.as_str())
+        .or(namespace)
+        .filter(|n| !n.is_empty() && !name.contains('.'));
+    Ok(match namespace {
+        Some(namespace) => format!("{}.{}", namespace, name),
+        None => name.to_string(),
+    })
+}
+
+fn parse_type(json: &Json, namespace: Option<&str>) -> Result<Type> {
+    match json {
+        Json::String(name) => match name.as_str() {
+            "null" => Ok(Type::Null),
+            "boolean" => Ok(Type::Boolean),
+            "int" => Ok(Type::Int),
+            "long" => Ok(Type::Long),
+            "float" => Ok(Type::Float),
+            "double" => Ok(Type::Double),
+            "bytes" => Ok(Type::Bytes),
+            "string" => Ok(Type::String),
+            _ => invalid(format!("unsupported type {}", name)),
+        },
+        Json::Array(types) => Ok(Type::Union(
+            types
+                .iter()
+                .map(|t| parse_type(t, namespace))
+                .collect::<Result<Vec<Type>>>()?,
+        )),
+        Json::Object(object) => {
+            let logical = object.get("logicalType").and_then(|t| t.as_str());
+            match (object.get("type").and_then(|t| t.as_str()), logical) {
+                (Some("bytes"), Some("decimal")) => {
+                    let attr = |name: &str| object.get(name).and_then(|v| v.as_u64());
+                    match (attr("precision"), attr("scale").unwrap_or(0)) {
+                        (Some(precision), scale) if precision > 0 && scale <= precision => {
+                            Ok(Type::Decimal(precision as u32, scale as u32))
+                        }
+                        _ => invalid(format!("invalid precision or scale in {}", json)),
+                    }
+                }
+                (Some("string"), Some("uuid")) => Ok(Type::Uuid),
+                (Some("int"), Some("date")) => Ok(Type::Date),
+                (Some("long"), Some("timestamp-millis")) => Ok(Type::TimestampMillis),
+                (Some("long"), Some("timestamp-micros")) => Ok(Type::TimestampMicros),
+                (Some("enum"), _) => {
+                    let symbols = match object.get("symbols").and_then(|s| s.as_array()) {
+                        Some(symbols) => symbols
+                            .iter()
+                            .filter_map(|s| s.as_str())
+                            .map(|s| s.to_string())
+                            .collect(),
+                        None => return invalid(format!("symbols are required in {}", json)),
+                    };
+                    Ok(Type::Enum(full_name(json, namespace)?, symbols))
+                }
+                (Some(_), _) => parse_type(&object["type"], namespace),
+                (None, _) => invalid(format!("type is required in {}", json)),
+            }
+        }
+        _ => invalid(format!("invalid type {}", json)),
+    }
+}
+
+impl Schema {
+    fn parse(json: &Json) -> Result<Schema> {
+        if json.get("type").and_then(|t| t.as_str()) != Some("record") {
+            return invalid("schema must be a record".to_string());
+        }
+        let name = full_name(json, None)?;
+        let namespace = name.rfind('.').map(|i| &name[..i]);
+        let fields = match json.get("fields").and_then(|f| f.as_array()) {
+            Some(fields) => fields,
+            None => return invalid("fields are required".to_string()),
+        };
+        let fields = fields
+            .iter()
+            .map(
+                |f| match (f.get("name").and_then(|n| n.as_str()), f.get("type")) {
+                    (Some(name), Some(ty)) => Ok((name.to_string(), parse_type(ty, namespace)?)),
+                    _ => invalid(format!("name and type are required in {}", f)),
+                },
+            )
+            .collect::<Result<Vec<(String, Type)>>>()?;
+        Ok(Schema {
+            name: name.to_string(),
+            fields,
+        })
+    }
+
+    fn infer(name: &str, fields: &[FieldDef]) -> Result<Schema> {
+        for name in std::iter::once(name).chain(fields.iter().map(|f| f.name.as_str())) {
+            let mut chars = name.chars();
+            let valid = matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+                && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !valid {
+                return invalid(format!(
+                    "{} is not a valid name, expected [A-Za-z_][A-Za-z0-9_]*",
+                    name
+                ));
+            }
+        }
+        let fields = fields
+            .iter()
+            .map(|f| {
+                let ty = match f.kind {
+                    Kind::String => Type::String,
+                    Kind::Int => Type::Long,
+                    Kind::Float => Type::Double,
+                    Kind::Bool => Type::Boolean,
+                    Kind::Date => Type::Date,
+                    Kind::Timestamp => Type::TimestampMicros,
+                    Kind::Decimal => Type::Decimal(DECIMAL_PRECISION, DECIMAL_SCALE),
+                    Kind::Uuid => Type::Uuid,
+                };
+                (f.name.clone(), Type::Union(vec![Type::Null, ty]))
+            })
+            .collect();
+        Ok(Schema {
+            name: name.to_string(),
+            fields,
+        })
+    }
+
+    fn json(&self) -> String {
+        let fields = self
+            .fields
+            .iter()
+            .map(|(name, ty)| {
+                let default = match ty {
+                    Type::Union(types) if types.first() == Some(&Type::Null) => ",\"default\":null",
+                    _ => "",
+                };
+                format!(
+                    "{{\"name\":{},\"type\":{}{}}}",
+                    Json::from(name.as_str()),
+                    ty.json(false),
+                    default
+                )
+            })
+            .collect::<Vec<String>>();
+        format!(
+            "{{\"type\":\"record\",\"name\":{},\"fields\":[{}]}}",
+            Json::from(self.name.as_str()),
+            fields.join(",")
+        )
+    }
+
+    fn canonical(&self) -> String {
+        let fields = self
+            .fields
+            .iter()
+            .map(|(name, ty)| {
+                format!(
+                    "{{\"name\":{},\"type\":{}}}",
+                    Json::from(name.as_str()),
+                    ty.json(true)
+                )
+            })
+            .collect::<Vec<String>>();
+        format!(
+            "{{\"name\":{},\"type\":\"record\",\"fields\":[{}]}}",
+            Json::from(self.name.as_str()),
+            fields.join(",")
+        )
+    }
+}
+
+impl Type {
+    fn json(&self, canonical: bool) -> String {
+        let logical = |ty: &str, logical: &str| {
+            if canonical {
+                format!("\"{}\"", ty)
+            } else {
+                format!("{{\"type\":\"{}\",\"logicalType\":\"{}\"}}", ty, logical)
+            }
+        };
+        match self {
+            Type::Null => "\"null\"".to_string(),
+            Type::Boolean => "\"boolean\"".to_string(),
+            Type::Int => "\"int\"".to_string(),
+            Type::Long => "\"long\"".to_string(),
+            Type::Float => "\"float\"".to_string(),
+            Type::Double => "\"double\"".to_string(),
+            Type::Bytes => "\"bytes\"".to_string(),
+            Type::String => "\"string\"".to_string(),
+            Type::Enum(name, symbols) => {
+                let symbols = symbols
+                    .iter()
+                    .map(|s| Json::from(s.as_str()).to_string())
+                    .collect::<Vec<String>>();
+                if canonical {
+                    format!(
+                        "{{\"name\":{},\"type\":\"enum\",\"symbols\":[{}]}}",
+                        Json::from(name.as_str()),
+                        symbols.join(",")
+                    )
+                } else {
+                    format!(
+                        "{{\"type\":\"enum\",\"name\":{},\"symbols\":[{}]}}",
+                        Json::from(name.as_str()),
+                        symbols.join(",")
+                    )
+                }
+            }
+            Type::Decimal(_, _) if canonical => "\"bytes\"".to_string(),
+            Type::Decimal(precision, scale) => format!(
+                "{{\"type\":\"bytes\",\"logicalType\":\"decimal\",\"precision\":{},\"scale\":{}}}",
+                precision, scale
+            ),
+            Type::Uuid => logical("string", "uuid"),
+            Type::Date => logical("int", "date"),
+            Type::TimestampMillis => logical("long", "timestamp-millis"),
+            Type::TimestampMicros => logical("long", "timestamp-micros"),
+            Type::Union(types) => {
+                let types = types
+                    .iter()
+                    .map(|t| t.json(canonical))
+                    .collect::<Vec<String>>();
+                format!("[{}]", types.join(","))
+            }
+        }
+    }
+
+    fn encode(&self, value: Option<(&str, Option<&Value>)>, buf: &mut Vec<u8>) -> bool {
+        let (text, value) = match (self, value) {
+            (Type::Null, None) => return true,
+            (Type::Union(types), _) => {
+                for (i, ty) in types.iter().enumerate() {
+                    let len = buf.len();
+                    write_long(i as i64, buf);
+                    if ty.encode(value, buf) {
+                        return true;
+                    }
+                    buf.truncate(len);
+                }
+                return false;
+            }
+            (Type::Null, Some((_, Some(Value::Null)))) => return true,
+            (_, None) | (Type::Null, _) | (_, Some((_, Some(Value::Null)))) => return false,
+            (_, Some(value)) => value,
+        };
+        match (self, value) {
+            (Type::Boolean, Some(Value::Bool(v))) => buf.push(*v as u8),
+            (Type::Int, Some(Value::Int(v))) if i32::try_from(*v).is_ok() => write_long(*v, buf),
+            (Type::Long, Some(Value::Int(v))) => write_long(*v, buf),
+            (Type::Float, Some(v @ Value::Int(_)))
+            | (Type::Float, Some(v @ Value::Float(_)))
+            | (Type::Float, Some(v @ Value::Decimal(..))) => {
+                buf.extend_from_slice(&(v.as_f64().unwrap_or(f64::NAN) as f32).to_le_bytes())
+            }
+            (Type::Double, Some(v @ Value::Int(_)))
+            | (Type::Double, Some(v @ Value::Float(_)))
+            | (Type::Double, Some(v @ Value::Decimal(..))) => {
+                buf.extend_from_slice(&v.as_f64().unwrap_or(f64::NAN).to_le_bytes())
+            }
+            (Type::Decimal(precision, scale), Some(v @ Value::Int(_)))
+            | (Type::Decimal(precision, scale), Some(v @ Value::Decimal(..))) => {
+                match v.as_units(*scale) {
+                    Some(units) if units.unsigned_abs() < 10u128.pow((*precision).min(38)) => {
+                        write_bytes(&decimal_bytes(units), buf)
+                    }
+                    _ => return false,
+                }
+            }
+            (Type::Uuid, Some(Value::Uuid(_))) => write_bytes(text.as_bytes(), buf),
+            (Type::Bytes, _) | (Type::String, _) => write_bytes(text.as_bytes(), buf),
+            (Type::Enum(_, symbols), _) => match symbols.iter().position(|s| s == text) {
+                Some(i) => write_long(i as i64, buf),
+                None => return false,
+            },
+            (Type::Date, Some(Value::Date(v))) => {
+                write_long(i64::from(v.num_days_from_ce() - EPOCH_DAYS_FROM_CE), buf)
+            }
+            (Type::TimestampMillis, Some(Value::Timestamp(v))) => {
+                write_long(v.timestamp_millis(), buf)
+            }
+            (Type::TimestampMicros, Some(Value::Timestamp(v))) => write_long(
+                v.timestamp() * 1_000_000 + i64::from(v.timestamp_subsec_micros()),
+                buf,
+            ),
+            (_, Some(Value::Str(_))) | (_, None) => {
+                let kind = match self {
+                    Type::Boolean => Kind::Bool,
+                    Type::Int | Type::Long => Kind::Int,
+                    Type::Float | Type::Double => Kind::Float,
+                    Type::Decimal(_, _) => Kind::Decimal,
+                    Type::Uuid => Kind::Uuid,
+                    Type::Date => Kind::Date,
+                    Type::TimestampMillis | Type::TimestampMicros => Kind::Timestamp,
+                    _ => return false,
+                };
+                return match Value::parse(kind, text) {
+                    Some(value) => self.encode(Some((text, Some(&value))), buf),
+                    None => false,
+                };
+            }
+            _ => return false,
+        }
+        true
+    }
+}
+
+fn write_long(v: i64, buf: &mut Vec<u8>) {
+    let mut n = ((v << 1) ^ (v >> 63)) as u64;
+    while n >= 0x80 {
+        buf.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+// The shortest big-endian two's complement bytes of a decimal's unscaled value.
+fn decimal_bytes(units: i128) -> Vec<u8> {
+    let bytes = units.to_be_bytes();
+    let sign = if units < 0 { 0xff } else { 0 };
+    let start = (0..bytes.len() - 1)
+        .find(|&i| bytes[i] != sign || (bytes[i + 1] ^ sign) & 0x80 != 0)
+        .unwrap_or(bytes.len() - 1);
+    bytes[start..].to_vec()
+}
+
+fn write_bytes(v: &[u8], buf: &mut Vec<u8>) {
+    write_long(v.len() as i64, buf);
+    buf.extend_from_slice(v);
+}
+
+fn fingerprint(text: &str) -> u64 {
+    let mut table = [0u64; 256];
+    for (i, fp) in table.iter_mut().enumerate() {
+        *fp = i as u64;
+        for _ in 0..8 {
+            *fp = (*fp >> 1) ^ (EMPTY & (*fp & 1).wrapping_neg());
+        }
+    }
+    let mut fp = EMPTY;
+    for b in text.bytes() {
+        fp = (fp >> 8) ^ table[((fp ^ u64::from(b)) & 0xff) as usize];
+    }
+    fp
+}
+
+fn encode_record(schema: &Schema, ctx: &Context, buf: &mut Vec<u8>) -> Result<()> {
+    for (name, ty) in &schema.fields {
+        let value = ctx.field(name).map(|text| (text, ctx.value(name)));
+        if !ty.encode(value, buf) {
+            return Err(Error::Template(format!(
+                "{}: {} does not match avro type {}",
+                name,
+                value.map(|(text, _)| text).unwrap_or("null"),
+                ty.json(false)
+            )));
+        }
+    }
+    Ok(())
+}
+
+struct Container {
+    schema: Schema,
+    codec: Compression,
+    block_size: usize,
+    sync: [u8; 16],
+    started: bool,
+    count: usize,
+    block: Vec<u8>,
+    buf: Vec<u8>,
+}
+
+impl Container {
+    fn start(&mut self, w: &mut dyn Write) -> Result<()> {
+        if self.started {
+            return Ok(());
+        }
+        self.started = true;
+        let codec = match self.codec {
+            Compression::Deflate => "deflate",
+            Compression::Snappy => "snappy",
+            _ => "null",
+        };
+        let mut header = MAGIC.to_vec();
+        write_long(2, &mut header);
+        write_bytes(b"avro.schema", &mut header);
+        write_bytes(self.schema.json().as_bytes(), &mut header);
+        write_bytes(b"avro.codec", &mut header);
+        write_bytes(codec.as_bytes(), &mut header);
+        write_long(0, &mut header);
+        header.extend_from_slice(&self.sync);
+        Ok(w.write_all(&header)?)
+    }
+
+    fn flush(&mut self, w: &mut dyn Write) -> Result<()> {
+        self.buf.clear();
+        match self.codec {
+            Compression::Deflate => {
+                let mut encoder =
+                    DeflateEncoder::new(&mut self.buf, flate2::Compression::default());
+                encoder.write_all(&self.block)?;
+                encoder.finish()?;
+            }
+            Compression::Snappy => {
+                let mut encoder = snap::raw::Encoder::new();
+                let data = encoder
+                    .compress_vec(&self.block)
+                    .map_err(|e| Error::Template(e.to_string()))?;
+                self.buf.extend_from_slice(&data);
+                self.buf
+                    .extend_from_slice(&crc32fast::hash(&self.block).to_be_bytes());
+            }
+            _ => self.buf.extend_from_slice(&self.block),
+        }
+        let mut head = Vec::with_capacity(20);
+        write_long(self.count as i64, &mut head);
+        write_long(self.buf.len() as i64, &mut head);
+        w.write_all(&head)?;
+        w.write_all(&self.buf)?;
+        w.write_all(&self.sync)?;
+        self.block.clear();
+        self.count = 0;
+        Ok(())
+    }
+}
+
+impl Writer for Container {
+    fn binary(&self) -> bool {
+        true
+    }
+
+    fn separated(&self) -> bool {
+        false
+    }
+
+    fn write(&mut self, ctx: &Context, _text: &[u8], w: &mut dyn Write) -> Result<()> {
+        self.start(w)?;
+        encode_record(&self.schema, ctx, &mut self.block)?;
+        self.count += 1;
+        if self.count == self.block_size {
+            self.flush(w)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, w: &mut dyn Write) -> Result<()> {
+        self.start(w)?;
+        if self.count > 0 {
+            self.flush(w)?;
+        }
+        Ok(())
+    }
+}
+
+struct SingleObject {
+    schema: Schema,
+    header: Vec<u8>,
+    buf: Vec<u8>,
+}
+
+impl Writer for SingleObject {
+    fn binary(&self) -> bool {
+        true
+    }
+
+    fn write(&mut self, ctx: &Context, _text: &[u8], w: &mut dyn Write) -> Result<()> {
+        self.buf.clear();
+        self.buf.extend_from_slice(&self.header);
+        encode_record(&self.schema, ctx, &mut self.buf)?;
+        Ok(w.write_all(&self.buf)?)
+    }
+}
+
+fn load(path: Option<&Path>, table: Option<&str>, fields: &[FieldDef]) -> Result<Schema> {
+    match path {
+        Some(path) => {
+            let text = std::fs::read_to_string(path)?;
+            let json = serde_json::from_str(&text)
+                .map_err(|e| Error::Schema(format!("{}: {}", path.display(), e)))?;
+            Schema::parse(&json)
+        }
+        None if fields.is_empty() => invalid("named fields are required".to_string()),
+        None => Schema::infer(table.unwrap_or("Record"), fields),
+    }
+}
+
+pub fn container(
+    path: Option<&Path>,
+    table: Option<&str>,
+    fields: &[FieldDef],
+    codec: Compression,
+    block_size: usize,
+) -> Result<Box<dyn Writer>> {
+    match codec {
+        Compression::None | Compression::Deflate | Compression::Snappy => {}
+        _ => return invalid(format!("{:?} codec is not supported", codec)),
+    }
+    Ok(Box::new(Container {
+        schema: load(path, table, fields)?,
+        codec,
+        block_size: block_size.max(1),
+        sync: rand::thread_rng().gen(),
+        started: false,
+        count: 0,
+        block: Vec::with_capacity(64 * 1024),
+        buf: Vec::with_capacity(64 * 1024),
+    }))
+}
+
+pub fn single_object(
+    path: Option<&Path>,
+    table: Option<&str>,
+    fields: &[FieldDef],
+) -> Result<Box<dyn Writer>> {
+    let schema = load(path, table, fields)?;
+    let mut header = SINGLE_OBJECT.to_vec();
+    header.extend_from_slice(&fingerprint(&schema.canonical()).to_le_bytes());
+    Ok(Box::new(SingleObject {
+        schema,
+        header,
+        buf: Vec::with_capacity(256),
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::NaiveDate;
+    use serde_json::json;
+
+    use crate::avro::{fingerprint, Schema, Type, Value};
+    use crate::columnar::Compression;
+    use crate::generust::{Context, FieldDef, Kind};
+
+    fn context() -> Context {
+        let mut ctx = Context::new(0);
+        ctx.set_field("id", "-3".to_string(), Value::Int(-3));
+        ctx.set_field("name", "ab".to_string(), Value::Str("ab".to_string()));
+        let born = NaiveDate::from_ymd_opt(1970, 1, 11).unwrap();
+        ctx.set_field("born", born.to_string(), Value::Date(born));
+        ctx
+    }
+
+    fn fields() -> Vec<FieldDef> {
+        vec![
+            FieldDef {
+                name: "id".to_string(),
+                kind: Kind::Int,
+                sql_type: None,
+            },
+            FieldDef {
+                name: "name".to_string(),
+                kind: Kind::String,
+                sql_type: None,
+            },
+            FieldDef {
+                name: "born".to_string(),
+                kind: Kind::Date,
+                sql_type: None,
+            },
+            FieldDef {
+                name: "ok".to_string(),
+                kind: Kind::Bool,
+                sql_type: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_encode() {
+        let schema = Schema::parse(&json!({
+            "type": "record",
+            "name": "User",
+            "namespace": "com.example",
+            "fields": [
+                {"name": "id", "type": "int"},
+                {"name": "name", "type": {"type": "enum", "name": "Name", "symbols": ["x", "ab"]}},
+                {"name": "born", "type": {"type": "int", "logicalType": "date"}},
+                {"name": "ok", "type": ["null", "boolean"]}
+            ]
+        }))
+        .unwrap();
+        assert_eq!(
+            Type::Enum(
+                "com.example.Name".to_string(),
+                vec!["x".to_string(), "ab".to_string()]
+            ),
+            schema.fields[1].1
+        );
+        let mut buf = vec![];
+        assert!(super::encode_record(&schema, &context(), &mut buf).is_ok());
+        assert_eq!(vec![5, 2, 20, 0], buf);
+
+        let schema = Schema::infer("User", &fields()).unwrap();
+        let mut buf = vec![];
+        assert!(super::encode_record(&schema, &context(), &mut buf).is_ok());
+        assert_eq!(vec![2, 5, 2, 4, b'a', b'b', 2, 20, 0], buf);
+
+        let mut ctx = context();
+        ctx.fields[0].1 = "x".to_string();
+        ctx.values.clear();
+        assert!(super::encode_record(&schema, &ctx, &mut vec![]).is_err());
+    }
+
+    #[test]
+    fn test_logical() {
+        let schema = Schema::parse(&json!({
+            "type": "record",
+            "name": "Price",
+            "fields": [
+                {"name": "price", "type": {"type": "bytes", "logicalType": "decimal", "precision": 6, "scale": 2}},
+                {"name": "id", "type": {"type": "string", "logicalType": "uuid"}}
+            ]
+        }))
+        .unwrap();
+        assert_eq!(Type::Decimal(6, 2), schema.fields[0].1);
+        assert_eq!(Type::Uuid, schema.fields[1].1);
+        let id = uuid::Uuid::nil();
+        let mut ctx = Context::new(0);
+        ctx.set_field("price", "-1.5".to_string(), Value::Decimal(-15, 1));
+        ctx.set_field("id", id.to_string(), Value::Uuid(id));
+        let mut buf = vec![];
+        assert!(super::encode_record(&schema, &ctx, &mut buf).is_ok());
+        assert_eq!(&[4, 0xff, 0x6a, 72], &buf[..4]);
+        assert_eq!(id.to_string().as_bytes(), &buf[4..]);
+        ctx.set_field("price", "12345.6".to_string(), Value::Decimal(123_456, 1));
+        assert!(super::encode_record(&schema, &ctx, &mut vec![]).is_err());
+
+        assert_eq!(vec![0], super::decimal_bytes(0));
+        assert_eq!(vec![0x00, 0x80], super::decimal_bytes(128));
+        assert_eq!(vec![0x80], super::decimal_bytes(-128));
+        let fields = vec![FieldDef {
+            name: "price".to_string(),
+            kind: Kind::Decimal,
+            sql_type: None,
+        }];
+        assert_eq!(
+            r#"{"type":"record","name":"Price","fields":[{"name":"price","type":["null",{"type":"bytes","logicalType":"decimal","precision":38,"scale":18}],"default":null}]}"#,
+            Schema::infer("Price", &fields).unwrap().json()
+        );
+    }
+
+    #[test]
+    fn test_container() {
+        for codec in &[Compression::None, Compression::Deflate, Compression::Snappy] {
+            let mut writer = super::container(None, Some("User"), &fields(), *codec, 2).unwrap();
+            let mut buf = vec![];
+            for _ in 0..3 {
+                assert!(writer.write(&context(), b"", &mut buf).is_ok());
+            }
+            assert!(writer.finish(&mut buf).is_ok());
+            assert!(buf.starts_with(b"Obj\x01"));
+            let sync = &buf[buf.len() - 16..];
+            assert_eq!(3, buf.windows(16).filter(|w| *w == sync).count());
+        }
+        assert!(super::container(None, None, &fields(), Compression::Zstd, 2).is_err());
+    }
+
+    #[test]
+    fn test_single_object() {
+        let schema = Schema::infer("User", &fields()).unwrap();
+        assert_eq!(
+            r#"{"name":"User","type":"record","fields":[{"name":"id","type":["null","long"]},{"name":"name","type":["null","string"]},{"name":"born","type":["null","int"]},{"name":"ok","type":["null","boolean"]}]}"#,
+            schema.canonical()
+        );
+        let mut writer = super::single_object(None, Some("User"), &fields()).unwrap();
+        let mut buf = vec![];
+        assert!(writer.write(&context(), b"", &mut buf).is_ok());
+        assert_eq!(&[0xc3, 0x01], &buf[..2]);
+        assert_eq!(&fingerprint(&schema.canonical()).to_le_bytes(), &buf[2..10]);
+        assert_eq!(&[2, 5, 2, 4, b'a', b'b', 2, 20, 0], &buf[10..]);
+        assert_eq!(8_247_732_601_305_521_295, fingerprint("\"int\""));
+        assert!(super::single_object(None, Some("public.users"), &fields()).is_err());
+        assert!(Schema::infer("_9", &fields()).is_ok());
+        let mut fields = fields();
+        fields[1].name = "e-mail".to_string();
+        assert!(Schema::infer("User", &fields).is_err());
+    }
+}
@@ -0,0 +1,196 @@
+use std::io::Write;
+
+use crate::generust::{Context, FieldDef, Result, Value};
+use crate::writer::Writer;
+
+const UINT: u8 = 0;
+const NEGATIVE: u8 = 1;
+const BYTES: u8 = 2;
+const TEXT: u8 = 3;
+const ARRAY: u8 = 4;
+const MAP: u8 = 5;
+const TAG: u8 = 6;
+
+const TAG_DATE_TIME: u64 = 0;
+const TAG_DECIMAL: u64 = 4;
+const TAG_UUID: u64 = 37;
+const TAG_DATE: u64 = 1004;
+
+pub struct Cbor {
+    fields: Vec<FieldDef>,
+    buf: Vec<u8>,
+}
+
+impl Cbor {
+    pub fn new(fields: Vec<FieldDef>) -> Cbor {
+        Cbor {
+            fields,
+            buf: Vec::with_capacity(256),
+        }
+    }
+}
+
+fn write_head(major: u8, v: u64, buf: &mut Vec<u8>) {
+    let major = major << 5;
+    if v < 24 {
+        buf.push(major | v as u8);
+    } else if v <= u64::from(u8::MAX) {
+        buf.extend_from_slice(&[major | 24, v as u8]);
+    } else if v <= u64::from(u16::MAX) {
+        buf.push(major | 25);
+        buf.extend_from_slice(&(v as u16).to_be_bytes());
+    } else if v <= u64::from(u32::MAX) {
+        buf.push(major | 26);
+        buf.extend_from_slice(&(v as u32).to_be_bytes());
+    } else {
+        buf.push(major | 27);
+        buf.extend_from_slice(&v.to_be_bytes());
+    }
+}
+
+fn write_text(v: &str, buf: &mut Vec<u8>) {
+    write_head(TEXT, v.len() as u64, buf);
+    buf.extend_from_slice(v.as_bytes());
+}
+
+fn write_value(value: &Value, buf: &mut Vec<u8>) {
+    match value {
+        Value::Null => buf.push(0xf6),
+        Value::Bool(false) => buf.push(0xf4),
+        Value::Bool(true) => buf.push(0xf5),
+        Value::Int(v) if *v >= 0 => write_head(UINT, *v as u64, buf),
+        Value::Int(v) => write_head(NEGATIVE, !*v as u64, buf),
+        Value::Float(v) => {
+            buf.push(0xfb);
+            buf.extend_from_slice(&v.to_be_bytes());
+        }
+        Value::Decimal(units, scale) => {
+            write_head(TAG, TAG_DECIMAL, buf);
+            write_head(ARRAY, 2, buf);
+            write_value(&Value::Int(-i64::from(*scale)), buf);
+            write_value(&Value::Int(*units), buf);
+        }
+        Value::Str(v) => write_text(v, buf),
+        Value::Date(v) => {
+            write_head(TAG, TAG_DATE, buf);
+            write_text(&v.to_string(), buf);
+        }
+        Value::Timestamp(_) => {
+            write_head(TAG, TAG_DATE_TIME, buf);
+            write_text(&value.to_string(), buf);
+        }
+        Value::Uuid(v) => {
+            write_head(TAG, TAG_UUID, buf);
+            write_head(BYTES, 16, buf);
+            buf.extend_from_slice(v.as_bytes());
+        }
+        Value::List(vs) => {
+            write_head(ARRAY, vs.len() as u64, buf);
+            for v in vs {
+                write_value(v, buf);
+            }
+        }
+        Value::Map(vs) => {
+            write_head(MAP, vs.len() as u64, buf);
+            for (k, v) in vs {
+                write_text(k, buf);
+                write_value(v, buf);
+            }
+        }
+    }
+}
+
+impl Writer for Cbor {
+    fn binary(&self) -> bool {
+        true
+    }
+
+    fn write(&mut self, ctx: &Context, _text: &[u8], w: &mut dyn Write) -> Result<()> {
+        self.buf.clear();
+        write_head(MAP, self.fields.len() as u64, &mut self.buf);
+        for f in &self.fields {
+            write_text(&f.name, &mut self.buf);
+            write_value(&ctx.typed(f).unwrap_or(Value::Null), &mut self.buf);
+        }
+        Ok(w.write_all(&self.buf)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{NaiveDate, TimeZone, Utc};
+
+    use crate::cbor::{write_value, Cbor};
+    use crate::generust::{Context, FieldDef, Kind, Value};
+    use crate::writer::Writer;
+
+    fn encode(value: Value) -> Vec<u8> {
+        let mut buf = vec![];
+        write_value(&value, &mut buf);
+        buf
+    }
+
+    #[test]
+    fn test_values() {
+        assert_eq!(vec![0x17], encode(Value::Int(23)));
+        assert_eq!(vec![0x18, 0x18], encode(Value::Int(24)));
+        assert_eq!(vec![0x19, 0x03, 0xe8], encode(Value::Int(1000)));
+        assert_eq!(vec![0x20], encode(Value::Int(-1)));
+        assert_eq!(vec![0x38, 0x63], encode(Value::Int(-100)));
+        assert_eq!(
+            vec![0x3b, 0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+            encode(Value::Int(i64::MIN))
+        );
+        assert_eq!(vec![0xf5], encode(Value::Bool(true)));
+        assert_eq!(
+            vec![0xfb, 0x3f, 0xf1, 0x99, 0x99, 0x99, 0x99, 0x99, 0x9a],
+            encode(Value::Float(1.1))
+        );
+        assert_eq!(
+            b"\xd9\x03\xec\x6a2020-01-02".to_vec(),
+            encode(Value::Date(NaiveDate::from_ymd_opt(2020, 1, 2).unwrap()))
+        );
+        assert_eq!(
+            b"\xc0\x78\x192013-03-21T20:04:00+00:00".to_vec(),
+            encode(Value::Timestamp(
+                Utc.timestamp_opt(1_363_896_240, 0).unwrap()
+            ))
+        );
+        assert_eq!(
+            vec![0xc4, 0x82, 0x21, 0x19, 0x6a, 0xb3],
+            encode(Value::Decimal(27315, 2))
+        );
+        assert_eq!(
+            vec![0x82, 0xa1, 0x61, b'a', 0xf6, 0x80],
+            encode(Value::List(vec![
+                Value::Map(vec![("a".to_string(), Value::Null)]),
+                Value::List(vec![]),
+            ]))
+        );
+        let uuid = encode(Value::Uuid(uuid::Uuid::nil()));
+        assert_eq!(vec![0xd8, 0x25, 0x50], uuid[..3].to_vec());
+        assert_eq!(19, uuid.len());
+    }
+
+    #[test]
+    fn test_cbor() {
+        let fields = vec![
+            FieldDef {
+                name: "a".to_string(),
+                kind: Kind::Int,
+                sql_type: None,
+            },
+            FieldDef {
+                name: "b".to_string(),
+                kind: Kind::String,
+                sql_type: None,
+            },
+        ];
+        let mut writer = Cbor::new(fields);
+        let mut ctx = Context::new(0);
+        ctx.set_field("a", "1".to_string(), Value::Int(1));
+        let mut buf = vec![];
+        assert!(writer.write(&ctx, b"", &mut buf).is_ok());
+        assert_eq!(b"\xa2\x61a\x01\x61b\xf6".to_vec(), buf);
+    }
+}
@@ -0,0 +1,509 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use arrow::array::{
+    ArrayBuilder, ArrayRef, BooleanBuilder, Date32Builder, Decimal128Builder, Float64Builder,
+    Int64Builder, StringBuilder, TimestampMicrosecondBuilder,
+};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use arrow::ipc::writer::{FileWriter, StreamWriter};
+use arrow::record_batch::RecordBatch;
+use chrono::Datelike;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{GzipLevel, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+
+use crate::generust::{
+    Context, Error, FieldDef, Kind, Result, Value, DECIMAL_PRECISION, DECIMAL_SCALE,
+    EPOCH_DAYS_FROM_CE,
+};
+use crate::output::Output;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    None,
+    Deflate,
+    Snappy,
+    Gzip,
+    Zstd,
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Compression::None),
+            "deflate" => Ok(Compression::Deflate),
+            "snappy" => Ok(Compression::Snappy),
+            "gzip" => Ok(Compression::Gzip),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(format!("unknown compression: {}", s)),
+        }
+    }
+}
+
+enum Column {
+    Int(Int64Builder),
+    Float(Float64Builder),
+    Decimal(Decimal128Builder),
+    Bool(BooleanBuilder),
+    Date(Date32Builder),
+    Timestamp(TimestampMicrosecondBuilder),
+    String(StringBuilder),
+}
+
+const TIME_ZONE: &str = "UTC";
+
+impl Column {
+    fn new(kind: Kind) -> Column {
+        match kind {
+            Kind::Int => Column::Int(Int64Builder::new()),
+            Kind::Float => Column::Float(Float64Builder::new()),
+            Kind::Decimal => {
+                Column::Decimal(Decimal128Builder::new().with_data_type(Column::data_type(kind)))
+            }
+            Kind::Bool => Column::Bool(BooleanBuilder::new()),
+            Kind::Date => Column::Date(Date32Builder::new()),
+            Kind::Timestamp => {
+                Column::Timestamp(TimestampMicrosecondBuilder::new().with_timezone(TIME_ZONE))
+            }
+            Kind::String | Kind::Uuid => Column::String(StringBuilder::new()),
+        }
+    }
+
+    fn data_type(kind: Kind) -> DataType {
+        match kind {
+            Kind::Int => DataType::Int64,
+            Kind::Float => DataType::Float64,
+            Kind::Decimal => DataType::Decimal128(DECIMAL_PRECISION as u8, DECIMAL_SCALE as i8),
+            Kind::Bool => DataType::Boolean,
+            Kind::Date => DataType::Date32,
+            Kind::Timestamp => DataType::Timestamp(TimeUnit::Microsecond, Some(TIME_ZONE.into())),
+            Kind::String | Kind::Uuid => DataType::Utf8,
+        }
+    }
+
+    fn append(&mut self, f: &FieldDef, ctx: &Context) -> Result<()> {
+        let value = match ctx.value(&f.name) {
+            Some(Value::Null) | None => {
+                self.append_null();
+                return Ok(());
+            }
+            Some(value) => value,
+        };
+        let mismatch =
+            || Error::Template(format!("{}: {} is not a valid {:?}", f.name, value, f.kind));
+        match (self, value) {
+            (Column::Int(b), Value::Int(v)) => b.append_value(*v),
+            (Column::Float(b), Value::Float(v)) => b.append_value(*v),
+            (Column::Float(b), v @ Value::Int(_)) | (Column::Float(b), v @ Value::Decimal(..)) => {
+                b.append_value(v.as_f64().unwrap_or(f64::NAN))
+            }
+            (Column::Decimal(b), v @ Value::Int(_))
+            | (Column::Decimal(b), v @ Value::Decimal(..)) => {
+                b.append_value(v.as_units(DECIMAL_SCALE).ok_or_else(mismatch)?)
+            }
+            (Column::Bool(b), Value::Bool(v)) => b.append_value(*v),
+            (Column::Date(b), Value::Date(v)) => {
+                b.append_value(v.num_days_from_ce() - EPOCH_DAYS_FROM_CE)
+            }
+            (Column::Timestamp(b), Value::Timestamp(v)) => {
+                b.append_value(v.timestamp() * 1_000_000 + i64::from(v.timestamp_subsec_micros()))
+            }
+            (Column::String(b), Value::Str(v)) => b.append_value(v),
+            (Column::String(b), v @ Value::Uuid(_)) => b.append_value(v.to_string()),
+            _ => return Err(mismatch()),
+        }
+        Ok(())
+    }
+
+    fn append_null(&mut self) {
+        match self {
+            Column::Int(b) => b.append_null(),
+            Column::Float(b) => b.append_null(),
+            Column::Decimal(b) => b.append_null(),
+            Column::Bool(b) => b.append_null(),
+            Column::Date(b) => b.append_null(),
+            Column::Timestamp(b) => b.append_null(),
+            Column::String(b) => b.append_null(),
+        }
+    }
+
+    fn finish(&mut self) -> ArrayRef {
+        match self {
+            Column::Int(b) => Arc::new(b.finish()),
+            Column::Float(b) => Arc::new(b.finish()),
+            Column::Decimal(b) => Arc::new(b.finish()),
+            Column::Bool(b) => Arc::new(b.finish()),
+            Column::Date(b) => Arc::new(b.finish()),
+            Column::Timestamp(b) => Arc::new(b.finish()),
+            Column::String(b) => Arc::new(b.finish()),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Column::Int(b) => b.len(),
+            Column::Float(b) => b.len(),
+            Column::Decimal(b) => b.len(),
+            Column::Bool(b) => b.len(),
+            Column::Date(b) => b.len(),
+            Column::Timestamp(b) => b.len(),
+            Column::String(b) => b.len(),
+        }
+    }
+}
+
+pub struct Columns {
+    fields: Vec<FieldDef>,
+    schema: SchemaRef,
+    columns: Vec<Column>,
+}
+
+impl Columns {
+    pub fn new(fields: &[FieldDef]) -> Result<Columns> {
+        if fields.is_empty() {
+            return Err(Error::Template(
+                "columnar output requires named fields".to_string(),
+            ));
+        }
+        let schema = Schema::new(
+            fields
+                .iter()
+                .map(|f| Field::new(&f.name, Column::data_type(f.kind), true))
+                .collect::<Vec<Field>>(),
+        );
+        Ok(Columns {
+            fields: fields.to_vec(),
+            schema: Arc::new(schema),
+            columns: fields.iter().map(|f| Column::new(f.kind)).collect(),
+        })
+    }
+
+    pub fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.columns[0].len()
+    }
+
+    pub fn append(&mut self, ctx: &Context) -> Result<()> {
+        for (f, column) in self.fields.iter().zip(self.columns.iter_mut()) {
+            column.append(f, ctx)?;
+        }
+        Ok(())
+    }
+
+    pub fn batch(&mut self) -> Result<RecordBatch> {
+        let arrays = self.columns.iter_mut().map(|c| c.finish()).collect();
+        Ok(RecordBatch::try_new(self.schema.clone(), arrays)?)
+    }
+}
+
+pub struct Parquet {
+    columns: Columns,
+    writer: Option<ArrowWriter<File>>,
+    row_group_size: usize,
+}
+
+impl Parquet {
+    pub fn new(
+        path: &str,
+        fields: &[FieldDef],
+        row_group_size: usize,
+        compression: Compression,
+    ) -> Result<Parquet> {
+        let columns = Columns::new(fields)?;
+        let compression = match compression {
+            Compression::None => parquet::basic::Compression::UNCOMPRESSED,
+            Compression::Snappy => parquet::basic::Compression::SNAPPY,
+            Compression::Gzip => parquet::basic::Compression::GZIP(GzipLevel::default()),
+            Compression::Zstd => parquet::basic::Compression::ZSTD(ZstdLevel::default()),
+            Compression::Deflate => {
+                return Err(Error::Template(
+                    "parquet output does not support deflate compression".to_string(),
+                ))
+            }
+        };
+        let props = WriterProperties::builder()
+            .set_compression(compression)
+            .set_max_row_group_size(row_group_size.max(1))
+            .build();
+        let writer = ArrowWriter::try_new(File::create(path)?, columns.schema(), Some(props))?;
+        Ok(Parquet {
+            columns,
+            writer: Some(writer),
+            row_group_size: row_group_size.max(1),
+        })
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if let Some(writer) = &mut self.writer {
+            writer.write(&self.columns.batch()?)?;
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl Output for Parquet {
+    fn write(&mut self, ctx: &Context, _text: &[u8]) -> Result<()> {
+        self.columns.append(ctx)?;
+        if self.columns.len() == self.row_group_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.columns.len() > 0 {
+            self.flush()?;
+        }
+        if let Some(writer) = self.writer.take() {
+            writer.close()?;
+        }
+        Ok(())
+    }
+}
+
+enum IpcWriter {
+    File(FileWriter<BufWriter<File>>),
+    Stream(StreamWriter<BufWriter<File>>),
+}
+
+pub struct Ipc {
+    columns: Columns,
+    writer: IpcWriter,
+    batch_size: usize,
+}
+
+impl Ipc {
+    pub fn new(path: &str, fields: &[FieldDef], batch_size: usize, stream: bool) -> Result<Ipc> {
+        let columns = Columns::new(fields)?;
+        let out = BufWriter::new(File::create(path)?);
+        let writer = if stream {
+            IpcWriter::Stream(StreamWriter::try_new(out, &columns.schema())?)
+        } else {
+            IpcWriter::File(FileWriter::try_new(out, &columns.schema())?)
+        };
+        Ok(Ipc {
+            columns,
+            writer,
+            batch_size: batch_size.max(1),
+        })
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        let batch = self.columns.batch()?;
+        match &mut self.writer {
+            IpcWriter::File(writer) => writer.write(&batch)?,
+            IpcWriter::Stream(writer) => writer.write(&batch)?,
+        }
+        Ok(())
+    }
+}
+
+impl Output for Ipc {
+    fn write(&mut self, ctx: &Context, _text: &[u8]) -> Result<()> {
+        self.columns.append(ctx)?;
+        if self.columns.len() == self.batch_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.columns.len() > 0 {
+            self.flush()?;
+        }
+        match &mut self.writer {
+            IpcWriter::File(writer) => writer.finish()?,
+            IpcWriter::Stream(writer) => writer.finish()?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use arrow::array::{
+        Array, BooleanArray, Date32Array, Decimal128Array, Int64Array, StringArray,
+    };
+    use arrow::ipc::reader::{FileReader, StreamReader};
+    use chrono::NaiveDate;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    use crate::columnar::{Columns, Compression, Ipc, Parquet};
+    use crate::escape::Escape;
+    use crate::generust::{Context, FieldDef, Generust, Kind, Parser, Value};
+    use crate::output::Output;
+
+    fn fields() -> Vec<FieldDef> {
+        vec![
+            FieldDef {
+                name: "id".to_string(),
+                kind: Kind::Int,
+                sql_type: None,
+            },
+            FieldDef {
+                name: "name".to_string(),
+                kind: Kind::String,
+                sql_type: None,
+            },
+            FieldDef {
+                name: "active".to_string(),
+                kind: Kind::Bool,
+                sql_type: None,
+            },
+            FieldDef {
+                name: "born".to_string(),
+                kind: Kind::Date,
+                sql_type: None,
+            },
+        ]
+    }
+
+    fn context(i: i32) -> Context {
+        let mut ctx = Context::new(i);
+        ctx.set_field("id", i.to_string(), Value::Int(i64::from(i)));
+        if i % 2 == 0 {
+            let name = format!("name {}", i);
+            ctx.set_field("name", name.clone(), Value::Str(name));
+        }
+        ctx.set_field("active", "true".to_string(), Value::Bool(true));
+        let born = NaiveDate::from_ymd_opt(1970, 1, 11).unwrap();
+        ctx.set_field("born", born.to_string(), Value::Date(born));
+        ctx
+    }
+
+    #[test]
+    fn test_columns() {
+        let mut columns = Columns::new(&fields()).unwrap();
+        for i in 0..3 {
+            assert!(columns.append(&context(i)).is_ok());
+        }
+        assert_eq!(3, columns.len());
+        let batch = columns.batch().unwrap();
+        assert_eq!(0, columns.len());
+        let ids = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(
+            vec![Some(0), Some(1), Some(2)],
+            ids.iter().collect::<Vec<_>>()
+        );
+        let names = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(1, names.null_count());
+        let active = batch
+            .column(2)
+            .as_any()
+            .downcast_ref::<BooleanArray>()
+            .unwrap();
+        assert!(active.value(0));
+        let born = batch
+            .column(3)
+            .as_any()
+            .downcast_ref::<Date32Array>()
+            .unwrap();
+        assert_eq!(10, born.value(0));
+
+        let mut ctx = Context::new(0);
+        ctx.set_field("id", "x".to_string(), Value::Str("x".to_string()));
+        assert!(columns.append(&ctx).is_err());
+        let mut ctx = Context::new(0);
+        ctx.set_field("name", "1".to_string(), Value::Int(1));
+        assert!(columns.append(&ctx).is_err());
+        assert!(Columns::new(&[]).is_err());
+
+        let price = FieldDef {
+            name: "price".to_string(),
+            kind: Kind::Decimal,
+            sql_type: None,
+        };
+        let mut columns = Columns::new(&[price]).unwrap();
+        let mut ctx = Context::new(0);
+        ctx.set_field("price", "0.10".to_string(), Value::Decimal(10, 2));
+        assert!(columns.append(&ctx).is_ok());
+        let batch = columns.batch().unwrap();
+        let prices = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<Decimal128Array>()
+            .unwrap();
+        assert_eq!("0.100000000000000000", prices.value_as_string(0));
+    }
+
+    #[test]
+    fn test_parquet() {
+        let path = std::env::temp_dir().join(format!("generust-{}.parquet", std::process::id()));
+        let mut sink =
+            Parquet::new(path.to_str().unwrap(), &fields(), 2, Compression::Zstd).unwrap();
+        for i in 0..5 {
+            assert!(sink.write(&context(i), b"").is_ok());
+        }
+        assert!(sink.finish().is_ok());
+        let reader =
+            ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(&path).unwrap()).unwrap();
+        assert_eq!(3, reader.metadata().num_row_groups());
+        assert_eq!(5, reader.metadata().file_metadata().num_rows());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_ipc() {
+        let parser = Parser::new("\\$", ",", Escape::None).unwrap();
+        let mut composite = parser
+            .parse("${id=ROW_NUM(1)}${born=DATE_SEQ(2020-01-01,2021-01-01)}${ok=BOOLEAN}")
+            .unwrap();
+        for stream in &[false, true] {
+            let path = std::env::temp_dir().join(format!(
+                "generust-{}-{}.arrow",
+                std::process::id(),
+                stream
+            ));
+            let mut sink = Ipc::new(path.to_str().unwrap(), &composite.fields, 2, *stream).unwrap();
+            for i in 0..5 {
+                let mut ctx = Context::new(i);
+                assert!(composite.generate(&mut ctx, &mut std::io::sink()).is_ok());
+                assert!(sink.write(&ctx, b"").is_ok());
+            }
+            assert!(sink.finish().is_ok());
+            let file = std::fs::File::open(&path).unwrap();
+            let batches = if *stream {
+                StreamReader::try_new(file, None)
+                    .unwrap()
+                    .collect::<Vec<_>>()
+            } else {
+                FileReader::try_new(file, None).unwrap().collect::<Vec<_>>()
+            };
+            let batches = batches.into_iter().map(|b| b.unwrap()).collect::<Vec<_>>();
+            assert_eq!(
+                vec![2, 2, 1],
+                batches.iter().map(|b| b.num_rows()).collect::<Vec<_>>()
+            );
+            let ids = batches[2]
+                .column(0)
+                .as_any()
+                .downcast_ref::<Int64Array>()
+                .unwrap();
+            assert_eq!(5, ids.value(0));
+            let born = batches[2]
+                .column(1)
+                .as_any()
+                .downcast_ref::<Date32Array>()
+                .unwrap();
+            assert_eq!(18_262 + 4, born.value(0));
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+}
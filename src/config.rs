@@ -0,0 +1,370 @@
+use std::io::Write;
+
+use crate::escape::Escape;
+use crate::generust::{Context, Error, FieldDef, Result, Value};
+use crate::writer::Writer;
+
+const RESERVED: &[&str] = &[
+    "null", "~", "true", "false", "yes", "no", "y", "n", "on", "off", "<<", "=",
+];
+
+fn write_quoted(v: &str, w: &mut dyn Write) -> Result<()> {
+    w.write_all(b"\"")?;
+    for (i, part) in v.split('\x7f').enumerate() {
+        if i > 0 {
+            w.write_all(b"\\u007F")?;
+        }
+        Escape::Json.write(part.as_bytes(), w)?;
+    }
+    w.write_all(b"\"")?;
+    Ok(())
+}
+
+fn float(v: f64) -> String {
+    let text = format!("{:?}", v);
+    let (mantissa, exp) = match text.find('e') {
+        Some(i) => (&text[..i], &text[i + 1..]),
+        None => (text.as_str(), ""),
+    };
+    let dot = if mantissa.contains('.') { "" } else { ".0" };
+    match exp {
+        "" => format!("{}{}", mantissa, dot),
+        exp if exp.starts_with('-') => format!("{}{}e{}", mantissa, dot, exp),
+        exp => format!("{}{}e+{}", mantissa, dot, exp),
+    }
+}
+
+fn plain(v: &str) -> bool {
+    let first = match v.chars().next() {
+        Some(c) => c,
+        None => return false,
+    };
+    !(first.is_ascii_digit()
+        || "-?:,[]{}#&*!|>'\"%@`.+ ".contains(first)
+        || v.ends_with(' ')
+        || v.ends_with(':')
+        || v.contains(": ")
+        || v.contains(" #")
+        || v.chars().any(char::is_control)
+        || RESERVED.contains(&v.to_lowercase().as_str()))
+}
+
+fn write_yaml_str(v: &str, w: &mut dyn Write) -> Result<()> {
+    if plain(v) {
+        Ok(w.write_all(v.as_bytes())?)
+    } else {
+        write_quoted(v, w)
+    }
+}
+
+fn write_yaml(value: &Value, w: &mut dyn Write) -> Result<()> {
+    match value {
+        Value::Null => w.write_all(b"null")?,
+        Value::Float(v) if v.is_nan() => w.write_all(b".nan")?,
+        Value::Float(v) if v.is_infinite() && *v > 0.0 => w.write_all(b".inf")?,
+        Value::Float(v) if v.is_infinite() => w.write_all(b"-.inf")?,
+        Value::Float(v) => w.write_all(float(*v).as_bytes())?,
+        Value::Str(v) => write_yaml_str(v, w)?,
+        Value::Uuid(_) => write_yaml_str(&value.to_string(), w)?,
+        Value::List(vs) => {
+            w.write_all(b"[")?;
+            for (i, v) in vs.iter().enumerate() {
+                if i > 0 {
+                    w.write_all(b", ")?;
+                }
+                write_yaml(v, w)?;
+            }
+            w.write_all(b"]")?;
+        }
+        Value::Map(vs) => {
+            w.write_all(b"{")?;
+            for (i, (k, v)) in vs.iter().enumerate() {
+                if i > 0 {
+                    w.write_all(b", ")?;
+                }
+                write_yaml_str(k, w)?;
+                w.write_all(b": ")?;
+                write_yaml(v, w)?;
+            }
+            w.write_all(b"}")?;
+        }
+        v => write!(w, "{}", v)?,
+    }
+    Ok(())
+}
+
+pub struct Yaml {
+    fields: Vec<FieldDef>,
+}
+
+impl Yaml {
+    pub fn new(fields: Vec<FieldDef>) -> Result<Yaml> {
+        if fields.is_empty() {
+            return Err(Error::Template(
+                "yaml format requires named fields".to_string(),
+            ));
+        }
+        Ok(Yaml { fields })
+    }
+}
+
+impl Writer for Yaml {
+    fn write(&mut self, ctx: &Context, _text: &[u8], w: &mut dyn Write) -> Result<()> {
+        w.write_all(b"---")?;
+        for f in &self.fields {
+            w.write_all(b"\n")?;
+            write_yaml_str(&f.name, w)?;
+            w.write_all(b": ")?;
+            write_yaml(&ctx.typed(f).unwrap_or(Value::Null), w)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_toml_key(v: &str, w: &mut dyn Write) -> Result<()> {
+    let bare = !v.is_empty()
+        && v.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if bare {
+        Ok(w.write_all(v.as_bytes())?)
+    } else {
+        write_quoted(v, w)
+    }
+}
+
+fn write_toml(value: &Value, w: &mut dyn Write) -> Result<()> {
+    match value {
+        Value::Float(v) if v.is_nan() => w.write_all(b"nan")?,
+        Value::Float(v) if v.is_infinite() && *v > 0.0 => w.write_all(b"inf")?,
+        Value::Float(v) if v.is_infinite() => w.write_all(b"-inf")?,
+        Value::Float(v) => w.write_all(float(*v).as_bytes())?,
+        Value::Decimal(_, 0) => write!(w, "{}.0", value)?,
+        Value::Str(v) => write_quoted(v, w)?,
+        Value::Null | Value::Uuid(_) => write_quoted(&value.to_string(), w)?,
+        Value::List(vs) => {
+            w.write_all(b"[")?;
+            for (i, v) in vs.iter().filter(|v| **v != Value::Null).enumerate() {
+                if i > 0 {
+                    w.write_all(b", ")?;
+                }
+                write_toml(v, w)?;
+            }
+            w.write_all(b"]")?;
+        }
+        Value::Map(vs) => {
+            w.write_all(b"{")?;
+            for (i, (k, v)) in vs.iter().filter(|(_, v)| *v != Value::Null).enumerate() {
+                w.write_all(if i > 0 { b", " } else { b" " })?;
+                write_toml_key(k, w)?;
+                w.write_all(b" = ")?;
+                write_toml(v, w)?;
+            }
+            w.write_all(b" }")?;
+        }
+        v => write!(w, "{}", v)?,
+    }
+    Ok(())
+}
+
+pub struct Toml {
+    fields: Vec<FieldDef>,
+    table: String,
+}
+
+impl Toml {
+    pub fn new(fields: Vec<FieldDef>, table: Option<&str>) -> Result<Toml> {
+        if fields.is_empty() {
+            return Err(Error::Template(
+                "toml format requires named fields".to_string(),
+            ));
+        }
+        Ok(Toml {
+            fields,
+            table: table.unwrap_or("records").to_string(),
+        })
+    }
+}
+
+impl Writer for Toml {
+    fn write(&mut self, ctx: &Context, _text: &[u8], w: &mut dyn Write) -> Result<()> {
+        w.write_all(b"[[")?;
+        write_toml_key(&self.table, w)?;
+        w.write_all(b"]]")?;
+        for f in &self.fields {
+            match ctx.typed(f) {
+                None | Some(Value::Null) => {}
+                Some(value) => {
+                    w.write_all(b"\n")?;
+                    write_toml_key(&f.name, w)?;
+                    w.write_all(b" = ")?;
+                    write_toml(&value, w)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{NaiveDate, TimeZone, Utc};
+
+    use crate::config::{float, write_toml, write_yaml, Toml, Yaml};
+    use crate::generust::{Context, FieldDef, Kind, Value};
+    use crate::writer::Writer;
+
+    fn yaml(value: Value) -> String {
+        let mut buf = vec![];
+        write_yaml(&value, &mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    fn toml(value: Value) -> String {
+        let mut buf = vec![];
+        write_toml(&value, &mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    fn fields() -> Vec<FieldDef> {
+        vec![
+            FieldDef {
+                name: "id".to_string(),
+                kind: Kind::Int,
+                sql_type: None,
+            },
+            FieldDef {
+                name: "flag".to_string(),
+                kind: Kind::String,
+                sql_type: None,
+            },
+            FieldDef {
+                name: "first name".to_string(),
+                kind: Kind::String,
+                sql_type: None,
+            },
+            FieldDef {
+                name: "active".to_string(),
+                kind: Kind::Bool,
+                sql_type: None,
+            },
+        ]
+    }
+
+    fn context() -> Context {
+        let mut ctx = Context::new(0);
+        ctx.set_field("id", "7".to_string(), Value::Int(7));
+        ctx.set_field("flag", "true".to_string(), Value::Str("true".to_string()));
+        ctx.set_field(
+            "first name",
+            "O'Brien".to_string(),
+            Value::Str("O'Brien".to_string()),
+        );
+        ctx
+    }
+
+    #[test]
+    fn test_float() {
+        assert_eq!("1.0", float(1.0));
+        assert_eq!("-0.5", float(-0.5));
+        assert_eq!("1.0e+300", float(1e300));
+        assert_eq!("1.5e-7", float(1.5e-7));
+    }
+
+    #[test]
+    fn test_yaml() {
+        assert_eq!("Zürich", yaml(Value::Str("Zürich".to_string())));
+        for v in &[
+            "",
+            "true",
+            "No",
+            "null",
+            "~",
+            "42",
+            "1e3",
+            ".5",
+            "0x1f",
+            "2020-01-02",
+            "-1",
+            "a: b",
+            "a #b",
+            "- a",
+            "'a'",
+            "a\nb",
+            " a",
+            "a ",
+            "*a",
+            "y",
+        ] {
+            let quoted = yaml(Value::Str(v.to_string()));
+            assert!(quoted.starts_with('"'), "{}", quoted);
+        }
+        assert_eq!("\"a\\nb\\u007F\"", yaml(Value::Str("a\nb\x7f".to_string())));
+        assert_eq!("1.0", yaml(Value::Float(1.0)));
+        assert_eq!(".nan", yaml(Value::Float(f64::NAN)));
+        assert_eq!("-.inf", yaml(Value::Float(f64::NEG_INFINITY)));
+        assert_eq!("12.30", yaml(Value::Decimal(1230, 2)));
+        assert_eq!(
+            "2013-03-21T20:04:00+00:00",
+            yaml(Value::Timestamp(
+                Utc.timestamp_opt(1_363_896_240, 0).unwrap()
+            ))
+        );
+        assert_eq!(
+            "[1, \"2\", {a: null, \"on\": [false]}]",
+            yaml(Value::List(vec![
+                Value::Int(1),
+                Value::Str("2".to_string()),
+                Value::Map(vec![
+                    ("a".to_string(), Value::Null),
+                    ("on".to_string(), Value::List(vec![Value::Bool(false)])),
+                ]),
+            ]))
+        );
+
+        let mut writer = Yaml::new(fields()).unwrap();
+        let mut buf = vec![];
+        assert!(writer.write(&context(), b"", &mut buf).is_ok());
+        assert_eq!(
+            "---\nid: 7\nflag: \"true\"\nfirst name: O'Brien\nactive: null",
+            String::from_utf8(buf).unwrap()
+        );
+        assert!(Yaml::new(vec![]).is_err());
+    }
+
+    #[test]
+    fn test_toml() {
+        assert_eq!("\"true\"", toml(Value::Str("true".to_string())));
+        assert_eq!(
+            "\"a\\\"b\\u007F\"",
+            toml(Value::Str("a\"b\x7f".to_string()))
+        );
+        assert_eq!("1.0", toml(Value::Float(1.0)));
+        assert_eq!("-inf", toml(Value::Float(f64::NEG_INFINITY)));
+        assert_eq!("12.0", toml(Value::Decimal(12, 0)));
+        assert_eq!(
+            "2020-01-02",
+            toml(Value::Date(NaiveDate::from_ymd_opt(2020, 1, 2).unwrap()))
+        );
+        assert_eq!(
+            "[1, { a = \"x\", \"b c\" = [] }]",
+            toml(Value::List(vec![
+                Value::Null,
+                Value::Int(1),
+                Value::Map(vec![
+                    ("a".to_string(), Value::Str("x".to_string())),
+                    ("z".to_string(), Value::Null),
+                    ("b c".to_string(), Value::List(vec![])),
+                ]),
+            ]))
+        );
+
+        let mut writer = Toml::new(fields(), Some("users")).unwrap();
+        let mut buf = vec![];
+        assert!(writer.write(&context(), b"", &mut buf).is_ok());
+        assert_eq!(
+            "[[users]]\nid = 7\nflag = \"true\"\n\"first name\" = \"O'Brien\"",
+            String::from_utf8(buf).unwrap()
+        );
+        assert!(Toml::new(vec![], None).is_err());
+    }
+}
@@ -0,0 +1,812 @@
+use chrono::Local;
+
+use crate::generust::{Error, Kind, Result, Value, DECIMAL_SCALE};
+use crate::schema;
+use crate::schema::{FieldSchema, Scalar, Schema};
+
+const NULL_CHANCE: f64 = 0.1;
+const UUID_LENGTH: usize = 36;
+// Digits of a DECIMAL_RND value, its unscaled value is 64-bit
+const DECIMAL_DIGITS: usize = 18;
+const DECIMAL_DEFAULT_SCALE: usize = 2;
+const FLOAT_LIMIT: f64 = 1_000_000.0;
+
+const KEYWORDS: &[&str] = &[
+    "NOT",
+    "NULL",
+    "PRIMARY",
+    "UNIQUE",
+    "CHECK",
+    "DEFAULT",
+    "REFERENCES",
+    "CONSTRAINT",
+    "AUTO_INCREMENT",
+    "AUTOINCREMENT",
+    "GENERATED",
+    "IDENTITY",
+    "COLLATE",
+    "COMMENT",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Ident(String),
+    Str(String),
+    Num(String),
+    Sym(String),
+}
+
+impl Token {
+    fn is(&self, word: &str) -> bool {
+        match self {
+            Token::Word(w) => w.eq_ignore_ascii_case(word),
+            _ => false,
+        }
+    }
+
+    fn is_sym(&self, sym: &str) -> bool {
+        matches!(self, Token::Sym(s) if s == sym)
+    }
+
+    fn name(&self) -> Option<&str> {
+        match self {
+            Token::Word(w) | Token::Ident(w) => Some(w),
+            _ => None,
+        }
+    }
+}
+
+fn tokenize(sql: &str) -> Result<Vec<Token>> {
+    let chars = sql.chars().collect::<Vec<char>>();
+    let at = |i: usize| chars.get(i).copied().unwrap_or('\0');
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '-' && at(i + 1) == '-' {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '/' && at(i + 1) == '*' {
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && at(i + 1) == '/') {
+                i += 1;
+            }
+            i += 2;
+        } else if c == '\'' || c == '"' || c == '`' || c == '[' {
+            let close = if c == '[' { ']' } else { c };
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err(Error::Schema("unterminated quote".to_string())),
+                    Some(&q) if q == close && at(i + 1) == close => {
+                        text.push(q);
+                        i += 2;
+                    }
+                    Some(&q) if q == close => {
+                        i += 1;
+                        break;
+                    }
+                    Some(&q) => {
+                        text.push(q);
+                        i += 1;
+                    }
+                }
+            }
+            tokens.push(match c {
+                '\'' => Token::Str(text),
+                _ => Token::Ident(text),
+            });
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            tokens.push(Token::Num(chars[start..i].iter().collect()));
+        } else if c.is_alphanumeric() || c == '_' {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '$')
+            {
+                i += 1;
+            }
+            tokens.push(Token::Word(chars[start..i].iter().collect()));
+        } else {
+            let pair: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            if [">=", "<=", "<>", "!=", "::"].contains(&pair.as_str()) {
+                tokens.push(Token::Sym(pair));
+                i += 2;
+            } else {
+                tokens.push(Token::Sym(c.to_string()));
+                i += 1;
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn closing(tokens: &[Token], open: usize) -> Result<usize> {
+    let mut depth = 0;
+    for (i, t) in tokens.iter().enumerate().skip(open) {
+        if t.is_sym("(") {
+            depth += 1;
+        } else if t.is_sym(")") {
+            depth -= 1;
+            if depth == 0 {
+                return Ok(i);
+            }
+        }
+    }
+    Err(Error::Schema("unbalanced parentheses".to_string()))
+}
+
+fn split(tokens: &[Token]) -> Vec<&[Token]> {
+    let mut items = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, t) in tokens.iter().enumerate() {
+        if t.is_sym("(") {
+            depth += 1;
+        } else if t.is_sym(")") {
+            depth -= 1;
+        } else if t.is_sym(",") && depth == 0 {
+            items.push(&tokens[start..i]);
+            start = i + 1;
+        }
+    }
+    if start < tokens.len() {
+        items.push(&tokens[start..]);
+    }
+    items
+}
+
+struct Table<'a> {
+    name: String,
+    body: &'a [Token],
+}
+
+fn tables(tokens: &[Token]) -> Result<Vec<Table<'_>>> {
+    let mut tables = vec![];
+    let mut i = 0;
+    while i < tokens.len() {
+        if !tokens[i].is("CREATE") {
+            i += 1;
+            continue;
+        }
+        let mut j = i + 1;
+        while j < tokens.len() && j < i + 4 && !tokens[j].is("TABLE") {
+            j += 1;
+        }
+        if j >= tokens.len() || !tokens[j].is("TABLE") {
+            i += 1;
+            continue;
+        }
+        j += 1;
+        if matches!(tokens.get(j), Some(t) if t.is("IF")) {
+            j += 3;
+        }
+        let mut name = String::new();
+        while let Some(t) = tokens.get(j) {
+            match t.name() {
+                Some(part) => name = part.to_string(),
+                None if t.is_sym(".") => {}
+                None => break,
+            }
+            j += 1;
+        }
+        if !matches!(tokens.get(j), Some(t) if t.is_sym("(")) {
+            return Err(Error::Schema(format!("{}: columns are expected", name)));
+        }
+        let end = closing(tokens, j)?;
+        tables.push(Table {
+            name,
+            body: &tokens[j + 1..end],
+        });
+        i = end + 1;
+    }
+    Ok(tables)
+}
+
+#[derive(Default)]
+struct Column {
+    name: String,
+    sql_type: String,
+    args: Vec<usize>,
+    not_null: bool,
+    unique: bool,
+    serial: bool,
+    min: Option<Scalar>,
+    max: Option<Scalar>,
+    min_open: bool,
+    max_open: bool,
+    values: Vec<Scalar>,
+}
+
+fn column(item: &[Token]) -> Result<Column> {
+    let name = match item.first().and_then(|t| t.name()) {
+        Some(name) => name.to_string(),
+        None => return Err(Error::Schema(format!("invalid column: {:?}", item))),
+    };
+    let mut col = Column {
+        name,
+        ..Default::default()
+    };
+    let mut i = 1;
+    let mut sql_type = vec![];
+    while let Some(t) = item.get(i) {
+        match t {
+            Token::Word(w) if !KEYWORDS.iter().any(|k| t.is(k)) => sql_type.push(w.to_uppercase()),
+            Token::Sym(s) if s == "(" && col.args.is_empty() => {
+                let end = closing(item, i)?;
+                col.args = item[i + 1..end]
+                    .iter()
+                    .filter_map(|t| match t {
+                        Token::Num(n) => n.parse().ok(),
+                        _ => None,
+                    })
+                    .collect();
+                i = end;
+            }
+            _ => break,
+        }
+        i += 1;
+    }
+    col.sql_type = sql_type.join(" ");
+    if col.sql_type.contains("SERIAL") {
+        col.serial = true;
+    }
+    while let Some(t) = item.get(i) {
+        if t.is("NOT") && matches!(item.get(i + 1), Some(t) if t.is("NULL")) {
+            col.not_null = true;
+        } else if t.is("PRIMARY") {
+            col.not_null = true;
+            col.unique = true;
+        } else if t.is("UNIQUE") {
+            col.unique = true;
+        } else if t.is("AUTO_INCREMENT") || t.is("AUTOINCREMENT") || t.is("IDENTITY") {
+            col.serial = true;
+        } else if t.is_sym("(") {
+            let end = closing(item, i)?;
+            if item[i - 1].is("CHECK") {
+                check(std::slice::from_mut(&mut col), &item[i + 1..end]);
+            }
+            i = end;
+        }
+        i += 1;
+    }
+    Ok(col)
+}
+
+fn scalar(tokens: &[Token], i: usize) -> Option<(Scalar, usize)> {
+    match (tokens.get(i), tokens.get(i + 1)) {
+        (Some(Token::Str(s)), _) => Some((Scalar::Str(s.clone()), i + 1)),
+        (Some(Token::Num(n)), _) => Some((number(n)?, i + 1)),
+        (Some(t), Some(Token::Num(n))) if t.is_sym("-") => {
+            Some((number(&format!("-{}", n))?, i + 2))
+        }
+        _ => None,
+    }
+}
+
+fn number(n: &str) -> Option<Scalar> {
+    match n.parse::<i64>() {
+        Ok(v) => Some(Scalar::Int(v)),
+        Err(_) => n.parse::<f64>().ok().map(Scalar::Float),
+    }
+}
+
+// A CHECK bound in units of 10^-scale, rounded inwards and moved by a unit if it is exclusive.
+fn bound(value: &Scalar, open: bool, scale: usize, lower: bool) -> Option<i128> {
+    let pow = 10_i128.pow(scale as u32);
+    let (units, exact) = match value {
+        Scalar::Int(v) => (i128::from(*v) * pow, true),
+        Scalar::Float(v) => {
+            let scaled = v * pow as f64;
+            let rounded = if lower { scaled.ceil() } else { scaled.floor() };
+            (rounded as i128, rounded == scaled)
+        }
+        _ => return None,
+    };
+    Some(match (open && exact, lower) {
+        (true, true) => units + 1,
+        (true, false) => units - 1,
+        _ => units,
+    })
+}
+
+// The CHECK range of a column in units of 10^-scale within the range of its type.
+fn range(col: &Column, scale: usize, low: i64, limit: i64) -> Result<(i64, i64)> {
+    let min = col
+        .min
+        .as_ref()
+        .and_then(|v| bound(v, col.min_open, scale, true))
+        .map_or(low, |v| v.max(i128::from(low)) as i64);
+    let max = col
+        .max
+        .as_ref()
+        .and_then(|v| bound(v, col.max_open, scale, false))
+        .map_or(limit, |v| v.min(i128::from(limit)) as i64);
+    if min > max {
+        return Err(Error::Schema(format!(
+            "{}: no {} value satisfies the CHECK constraints",
+            col.name, col.sql_type
+        )));
+    }
+    Ok((min, max))
+}
+
+fn check(cols: &mut [Column], check: &[Token]) {
+    let mut i = 0;
+    while i < check.len() {
+        let col = match check[i].name() {
+            Some(name) => cols.iter_mut().find(|c| c.name.eq_ignore_ascii_case(name)),
+            None => None,
+        };
+        let col = match col {
+            Some(col) => col,
+            None => {
+                i += 1;
+                continue;
+            }
+        };
+        let op = check.get(i + 1);
+        if matches!(op, Some(t) if t.is("BETWEEN")) {
+            if let Some((min, j)) = scalar(check, i + 2) {
+                if let Some((max, k)) = scalar(check, j + 1) {
+                    col.min = Some(min);
+                    col.max = Some(max);
+                    col.min_open = false;
+                    col.max_open = false;
+                    i = k;
+                    continue;
+                }
+            }
+        } else if matches!(op, Some(t) if t.is("IN"))
+            && matches!(check.get(i + 2), Some(t) if t.is_sym("("))
+        {
+            if let Ok(end) = closing(check, i + 2) {
+                col.values = split(&check[i + 3..end])
+                    .iter()
+                    .filter_map(|v| scalar(v, 0).map(|(s, _)| s))
+                    .collect();
+                i = end + 1;
+                continue;
+            }
+        } else if let Some(Token::Sym(op)) = op {
+            if let Some((value, j)) = scalar(check, i + 2) {
+                match op.as_str() {
+                    ">=" | ">" => {
+                        col.min = Some(value);
+                        col.min_open = op == ">";
+                    }
+                    "<=" | "<" => {
+                        col.max = Some(value);
+                        col.max_open = op == "<";
+                    }
+                    "=" => col.values = vec![value],
+                    _ => {}
+                }
+                i = j;
+                continue;
+            }
+        }
+        i += 1;
+    }
+}
+
+fn constraint(cols: &mut [Column], item: &[Token]) -> bool {
+    let item = match item.first() {
+        Some(t) if t.is("CONSTRAINT") => &item[2.min(item.len())..],
+        _ => item,
+    };
+    let first = match item.first() {
+        Some(t) => t,
+        None => return true,
+    };
+    let names = |item: &[Token]| -> Vec<String> {
+        match item.iter().position(|t| t.is_sym("(")) {
+            Some(open) => match closing(item, open) {
+                Ok(end) => item[open + 1..end]
+                    .iter()
+                    .filter_map(|t| t.name().map(|n| n.to_string()))
+                    .collect(),
+                Err(_) => vec![],
+            },
+            None => vec![],
+        }
+    };
+    if first.is("PRIMARY") || first.is("UNIQUE") {
+        let names = names(item);
+        for col in cols.iter_mut() {
+            if names.iter().any(|n| n.eq_ignore_ascii_case(&col.name)) {
+                col.unique |= names.len() == 1;
+                col.not_null |= first.is("PRIMARY");
+            }
+        }
+        true
+    } else if first.is("CHECK") {
+        if let Some(open) = item.iter().position(|t| t.is_sym("(")) {
+            if let Ok(end) = closing(item, open) {
+                check(cols, &item[open + 1..end]);
+            }
+        }
+        true
+    } else {
+        ["FOREIGN", "KEY", "INDEX", "EXCLUDE", "FULLTEXT", "SPATIAL"]
+            .iter()
+            .any(|k| first.is(k))
+    }
+}
+
+fn kind(col: &Column) -> Kind {
+    let ty = col.sql_type.as_str();
+    if ty.starts_with("INTERVAL") {
+        Kind::String
+    } else if ty.starts_with("UUID") || ty == "UNIQUEIDENTIFIER" {
+        Kind::Uuid
+    } else if ty.contains("INT") || ty.contains("SERIAL") {
+        Kind::Int
+    } else if ["DECIMAL", "NUMERIC", "NUMBER", "MONEY"]
+        .iter()
+        .any(|t| ty.starts_with(t))
+    {
+        Kind::Decimal
+    } else if ["REAL", "FLOAT", "DOUBLE"]
+        .iter()
+        .any(|t| ty.starts_with(t))
+    {
+        Kind::Float
+    } else if ty.starts_with("BOOL") {
+        Kind::Bool
+    } else if ty == "DATE" {
+        Kind::Date
+    } else if ty.starts_with("TIMESTAMP") || ty.starts_with("DATETIME") {
+        Kind::Timestamp
+    } else {
+        Kind::String
+    }
+}
+
+fn string_generator(col: &Column, length: Option<usize>) -> (&'static str, Vec<Scalar>) {
+    if col.unique {
+        match length {
+            Some(n) if n < UUID_LENGTH => {
+                let end = 10_i64.checked_pow(n as u32).map_or(i64::MAX, |v| v - 1);
+                ("ROW_NUM", vec![Scalar::Int(1), Scalar::Int(end)])
+            }
+            _ => ("UUID4", vec![]),
+        }
+    } else {
+        (schema::guess(&col.name).unwrap_or("UUID4"), vec![])
+    }
+}
+
+fn field(col: Column) -> Result<FieldSchema> {
+    let kind = kind(&col);
+    let mut field = FieldSchema {
+        name: col.name.clone(),
+        kind: Some(kind),
+        nullable: if col.not_null { 0.0 } else { NULL_CHANCE },
+        sql_type: Some(col.sql_type.clone()),
+        ..Default::default()
+    };
+    if !col.values.is_empty() {
+        field.values = col.values;
+        return Ok(field);
+    }
+    match kind {
+        Kind::Int if col.unique || col.serial => {
+            let int = |v: &Option<Scalar>, open, lower| {
+                v.as_ref()
+                    .and_then(|v| bound(v, open, 0, lower))
+                    .map(|v| v.clamp(i128::from(i32::MIN), i128::from(i32::MAX)) as i64)
+            };
+            let start = int(&col.min, col.min_open, true).unwrap_or(1);
+            field.generator = Some("ROW_NUM".to_string());
+            field.args = match int(&col.max, col.max_open, false) {
+                Some(max) => vec![Scalar::Int(start), Scalar::Int(max)],
+                None => vec![Scalar::Int(start)],
+            };
+        }
+        Kind::Int => {
+            let ty = col.sql_type.as_str();
+            let (low, limit) = if ty.starts_with("TINYINT") {
+                (i64::from(i8::MIN), i64::from(i8::MAX))
+            } else if ty.starts_with("SMALLINT") || ty == "INT2" {
+                (i64::from(i16::MIN), i64::from(i16::MAX))
+            } else if ty.starts_with("BIGINT") || ty == "INT8" {
+                // the end of INT_RND is exclusive
+                (i64::MIN, i64::MAX - 1)
+            } else {
+                (i64::from(i32::MIN), i64::from(i32::MAX))
+            };
+            let (min, max) = range(&col, 0, low, limit)?;
+            field.generator = Some("INT_RND".to_string());
+            field.args = vec![Scalar::Int(min), Scalar::Int(max + 1)];
+        }
+        Kind::Decimal => {
+            let (precision, scale) = match col.args[..] {
+                [precision, scale] => (precision, scale),
+                [precision] => (precision, 0),
+                _ => (DECIMAL_DIGITS, DECIMAL_DEFAULT_SCALE),
+            };
+            if scale > precision || scale > DECIMAL_SCALE as usize {
+                return Err(Error::Schema(format!(
+                    "{}: {}({}, {}) is not supported, the scale must be from 0 to {} and not above the precision",
+                    col.name, col.sql_type, precision, scale, DECIMAL_SCALE
+                )));
+            }
+            let limit = 10_i64.pow(precision.min(DECIMAL_DIGITS) as u32) - 1;
+            let (min, max) = range(&col, scale, -limit, limit)?;
+            let text = |units| Scalar::Str(Value::Decimal(units, scale as u32).to_string());
+            field.generator = Some("DECIMAL_RND".to_string());
+            field.args = vec![text(min), text(max), Scalar::Int(scale as i64)];
+        }
+        Kind::Float => {
+            let float = |v: &Option<Scalar>| match v {
+                Some(Scalar::Int(v)) => Some(*v as f64),
+                Some(Scalar::Float(v)) => Some(*v),
+                _ => None,
+            };
+            let (min, max) = match (float(&col.min), float(&col.max)) {
+                (Some(min), Some(max)) => (min, max),
+                (Some(min), None) => (min, min + FLOAT_LIMIT),
+                (None, Some(max)) => (max - FLOAT_LIMIT, max),
+                (None, None) => (-FLOAT_LIMIT, FLOAT_LIMIT),
+            };
+            if min > max {
+                return Err(Error::Schema(format!(
+                    "{}: no {} value satisfies the CHECK constraints",
+                    col.name, col.sql_type
+                )));
+            }
+            if min == max {
+                field.values = vec![Scalar::Float(min)];
+            } else {
+                field.generator = Some("FLOAT_RND".to_string());
+                field.args = vec![Scalar::Float(min), Scalar::Float(max)];
+            }
+        }
+        Kind::Date => {
+            let today = Local::now().naive_local().date().to_string();
+            field.sequential = col.unique;
+            field.min = Some(
+                col.min
+                    .unwrap_or_else(|| Scalar::Str("1970-01-01".to_string())),
+            );
+            field.max = Some(col.max.unwrap_or(Scalar::Str(today)));
+        }
+        Kind::Uuid => field.generator = Some("UUID4".to_string()),
+        Kind::Bool | Kind::Timestamp => {}
+        Kind::String => {
+            let length = col.args.first().copied().filter(|_| {
+                [
+                    "CHAR",
+                    "VARCHAR",
+                    "CHARACTER",
+                    "NCHAR",
+                    "NVARCHAR",
+                    "VARCHAR2",
+                ]
+                .iter()
+                .any(|t| col.sql_type.starts_with(t))
+            });
+            let (generator, args) = string_generator(&col, length);
+            field.generator = Some(generator.to_string());
+            field.args = args;
+            field.length = length;
+        }
+    }
+    Ok(field)
+}
+
+pub fn names(sql: &str) -> Result<Vec<String>> {
+    let tokens = tokenize(sql)?;
+    Ok(tables(&tokens)?.into_iter().map(|t| t.name).collect())
+}
+
+pub fn parse(sql: &str, table: Option<&str>) -> Result<Schema> {
+    let tokens = tokenize(sql)?;
+    let tables = tables(&tokens)?;
+    let found = match table {
+        Some(name) => tables.iter().find(|t| t.name.eq_ignore_ascii_case(name)),
+        None => tables.first(),
+    };
+    let found = match found {
+        Some(found) => found,
+        None => {
+            return Err(Error::Schema(format!(
+                "CREATE TABLE {} is not found",
+                table.unwrap_or("")
+            )))
+        }
+    };
+    let mut cols = vec![];
+    let mut constraints = vec![];
+    for item in split(found.body) {
+        match item.first() {
+            Some(t) if t.is("CONSTRAINT") => constraints.push(item),
+            Some(t)
+                if [
+                    "PRIMARY", "UNIQUE", "CHECK", "FOREIGN", "KEY", "INDEX", "EXCLUDE",
+                ]
+                .iter()
+                .any(|k| t.is(k)) =>
+            {
+                constraints.push(item)
+            }
+            Some(_) => cols.push(column(item)?),
+            None => {}
+        }
+    }
+    for item in constraints {
+        if !constraint(&mut cols, item) {
+            log::warn!("{}: unsupported constraint {:?}", found.name, item);
+        }
+    }
+    Ok(Schema {
+        table: Some(found.name.clone()),
+        fields: cols.into_iter().map(field).collect::<Result<Vec<_>>>()?,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ddl::{names, parse};
+    use crate::escape::Escape;
+    use crate::generust::{Context, Generust, Kind, Parser, Value};
+    use crate::schema::FieldSchema;
+
+    const DDL: &str = r#"
+-- accounts
+CREATE TABLE IF NOT EXISTS public.users (
+    id SERIAL PRIMARY KEY,
+    first_name VARCHAR(5) NOT NULL,
+    "e-mail" VARCHAR(100) UNIQUE,
+    age INT CHECK (age BETWEEN 18 AND 99),
+    score SMALLINT NOT NULL,
+    status TEXT NOT NULL CHECK (status IN ('active', 'it''s banned')),
+    born DATE NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT now(),
+    verified BOOLEAN NOT NULL DEFAULT false,
+    CONSTRAINT users_score CHECK (score >= 0 AND score < 10)
+);
+
+/* orders */
+CREATE TABLE `orders` (
+    `id` BIGINT NOT NULL AUTO_INCREMENT,
+    `amount` DECIMAL(10, 2) NOT NULL CHECK (amount > -5),
+    `code` CHAR(2) NOT NULL UNIQUE,
+    `line` INT NOT NULL UNIQUE CHECK (line <= 3),
+    `total` BIGINT NOT NULL CHECK (total BETWEEN -5000000000 AND 5000000000),
+    PRIMARY KEY (`id`)
+);
+"#;
+
+    fn describe(f: &FieldSchema) -> String {
+        let strings = |vs: &[_]| {
+            vs.iter()
+                .map(|v: &crate::schema::Scalar| v.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        format!(
+            "{} {:?} {} ({}) [{}] {:?} {}",
+            f.name,
+            f.kind,
+            f.generator.clone().unwrap_or_default(),
+            strings(&f.args),
+            strings(&f.values),
+            f.length,
+            f.nullable
+        )
+    }
+
+    #[test]
+    fn test_parse() {
+        let schema = parse(DDL, None).unwrap();
+        assert_eq!(Some("users".to_string()), schema.table);
+        let fields = schema.fields.iter().map(describe).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "id Some(Int) ROW_NUM (1) [] None 0",
+                "first_name Some(String) FIRST_RND () [] Some(5) 0",
+                "e-mail Some(String) UUID4 () [] Some(100) 0.1",
+                "age Some(Int) INT_RND (18,100) [] None 0.1",
+                "score Some(Int) INT_RND (0,10) [] None 0",
+                "status Some(String)  () [active,it's banned] None 0",
+                "born Some(Date)  () [] None 0",
+                "created_at Some(Timestamp)  () [] None 0.1",
+                "verified Some(Bool)  () [] None 0",
+            ],
+            fields
+        );
+    }
+
+    #[test]
+    fn test_table() {
+        let schema = parse(DDL, Some("ORDERS")).unwrap();
+        let fields = schema.fields.iter().map(describe).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "id Some(Int) ROW_NUM (1) [] None 0",
+                "amount Some(Decimal) DECIMAL_RND (-4.99,99999999.99,2) [] None 0",
+                "code Some(String) ROW_NUM (1,99) [] Some(2) 0",
+                "line Some(Int) ROW_NUM (1,3) [] None 0",
+                "total Some(Int) INT_RND (-5000000000,5000000001) [] None 0",
+            ],
+            fields
+        );
+        let parser = Parser::new("\\$", ",", Escape::None).unwrap();
+        let mut g = parse(DDL, Some("orders"))
+            .unwrap()
+            .compile(&parser)
+            .unwrap();
+        for i in 0..3 {
+            assert!(g
+                .generate(&mut Context::new(i), &mut std::io::sink())
+                .is_ok());
+        }
+        assert!(g
+            .generate(&mut Context::new(3), &mut std::io::sink())
+            .is_err());
+        assert!(parse(DDL, Some("missing")).is_err());
+
+        let ranges = parse(
+            "CREATE TABLE t (
+                balance INT CHECK (balance < 0),
+                rate REAL NOT NULL CHECK (rate >= 0.5 AND rate <= 1),
+                price NUMERIC(4, 2) CHECK (price > 0),
+                level TINYINT CHECK (level BETWEEN 1.5 AND 3.5)
+            )",
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                "balance Some(Int) INT_RND (-2147483648,0) [] None 0.1",
+                "rate Some(Float) FLOAT_RND (0.5,1) [] None 0",
+                "price Some(Decimal) DECIMAL_RND (0.01,99.99,2) [] None 0.1",
+                "level Some(Int) INT_RND (2,4) [] None 0.1",
+            ],
+            ranges.fields.iter().map(describe).collect::<Vec<_>>()
+        );
+        assert!(parse("CREATE TABLE t (a INT CHECK (a > 10 AND a < 5))", None).is_err());
+        assert!(parse("CREATE TABLE t (a TINYINT CHECK (a > 200))", None).is_err());
+        assert!(parse("CREATE TABLE t (a NUMERIC(40, 20))", None).is_err());
+        assert!(parse("CREATE TABLE t (a INT", None).is_err());
+        assert_eq!(vec!["users", "orders"], names(DDL).unwrap());
+    }
+
+    #[test]
+    fn test_generate() {
+        let parser = Parser::new("\\$", ",", Escape::None).unwrap();
+        let mut g = parse(DDL, None).unwrap().compile(&parser).unwrap();
+        assert_eq!(Kind::Int, g.fields[0].kind);
+        for i in 0..100 {
+            let mut ctx = Context::new(i);
+            assert!(g.generate(&mut ctx, &mut std::io::sink()).is_ok());
+            assert!(ctx.field("first_name").unwrap().chars().count() <= 5);
+            let score = ctx.field("score").unwrap().parse::<i32>().unwrap();
+            assert!((0..10).contains(&score));
+            if let Some(age) = ctx.field("age") {
+                assert!((18..=99).contains(&age.parse::<i32>().unwrap()));
+            }
+        }
+        let mut g = parse(DDL, Some("orders"))
+            .unwrap()
+            .compile(&parser)
+            .unwrap();
+        for i in 0..3 {
+            let mut ctx = Context::new(i);
+            assert!(g.generate(&mut ctx, &mut std::io::sink()).is_ok());
+            let total = ctx.field("total").unwrap().parse::<i64>().unwrap();
+            assert!((-5_000_000_000..=5_000_000_000).contains(&total));
+            assert!(matches!(ctx.value("amount"), Some(Value::Decimal(v, 2)) if *v >= -499));
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use std::io::{Result, Write};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Escape {
+    None,
+    Csv,
+    Json,
+    Xml,
+    Sql,
+    Copy,
+}
+
+impl FromStr for Escape {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Escape::None),
+            "csv" => Ok(Escape::Csv),
+            "json" => Ok(Escape::Json),
+            "xml" => Ok(Escape::Xml),
+            "sql" => Ok(Escape::Sql),
+            "copy" => Ok(Escape::Copy),
+            _ => Err(format!("unknown escape: {}", s)),
+        }
+    }
+}
+
+impl Escape {
+    pub fn write(self, value: &[u8], w: &mut dyn Write) -> Result<()> {
+        match self {
+            Escape::None => w.write_all(value),
+            Escape::Csv => write_csv(value, w),
+            Escape::Json => write_json(value, w),
+            Escape::Xml => write_xml(value, w),
+            Escape::Sql => write_sql(value, w),
+            Escape::Copy => write_copy(value, w),
+        }
+    }
+}
+
+fn write_csv(value: &[u8], w: &mut dyn Write) -> Result<()> {
+    if !value
+        .iter()
+        .any(|b| matches!(b, b',' | b'"' | b'\n' | b'\r'))
+    {
+        return w.write_all(value);
+    }
+    w.write_all(b"\"")?;
+    for (i, part) in value.split(|b| *b == b'"').enumerate() {
+        if i > 0 {
+            w.write_all(b"\"\"")?;
+        }
+        w.write_all(part)?;
+    }
+    w.write_all(b"\"")
+}
+
+fn write_json(value: &[u8], w: &mut dyn Write) -> Result<()> {
+    let mut start = 0;
+    for (i, b) in value.iter().enumerate() {
+        let esc: &[u8] = match b {
+            b'"' => b"\\\"",
+            b'\\' => b"\\\\",
+            b'\n' => b"\\n",
+            b'\r' => b"\\r",
+            b'\t' => b"\\t",
+            0x08 => b"\\b",
+            0x0c => b"\\f",
+            0x00..=0x1f => b"",
+            _ => continue,
+        };
+        w.write_all(&value[start..i])?;
+        if esc.is_empty() {
+            write!(w, "\\u{:04x}", b)?;
+        } else {
+            w.write_all(esc)?;
+        }
+        start = i + 1;
+    }
+    w.write_all(&value[start..])
+}
+
+fn write_xml(value: &[u8], w: &mut dyn Write) -> Result<()> {
+    let mut start = 0;
+    for (i, b) in value.iter().enumerate() {
+        let esc: &[u8] = match b {
+            b'&' => b"&amp;",
+            b'<' => b"&lt;",
+            b'>' => b"&gt;",
+            b'"' => b"&quot;",
+            b'\'' => b"&apos;",
+            _ => continue,
+        };
+        w.write_all(&value[start..i])?;
+        w.write_all(esc)?;
+        start = i + 1;
+    }
+    w.write_all(&value[start..])
+}
+
+fn write_sql(value: &[u8], w: &mut dyn Write) -> Result<()> {
+    for (i, part) in value.split(|b| *b == b'\'').enumerate() {
+        if i > 0 {
+            w.write_all(b"''")?;
+        }
+        w.write_all(part)?;
+    }
+    Ok(())
+}
+
+fn write_copy(value: &[u8], w: &mut dyn Write) -> Result<()> {
+    let mut start = 0;
+    for (i, b) in value.iter().enumerate() {
+        let esc: &[u8] = match b {
+            b'\\' => b"\\\\",
+            b'\n' => b"\\n",
+            b'\r' => b"\\r",
+            b'\t' => b"\\t",
+            0x08 => b"\\b",
+            0x0b => b"\\v",
+            0x0c => b"\\f",
+            _ => continue,
+        };
+        w.write_all(&value[start..i])?;
+        w.write_all(esc)?;
+        start = i + 1;
+    }
+    w.write_all(&value[start..])
+}
+
+#[cfg(test)]
+mod test {
+    use crate::escape::Escape;
+
+    fn escape(e: Escape, s: &str) -> String {
+        let mut buf = Vec::with_capacity(64);
+        assert!(e.write(s.as_bytes(), &mut buf).is_ok());
+        String::from_utf8(buf).expect("invalid utf8")
+    }
+
+    #[test]
+    fn test_none() {
+        assert_eq!("O'Brien, \"Jr\"", escape(Escape::None, "O'Brien, \"Jr\""));
+    }
+
+    #[test]
+    fn test_csv() {
+        assert_eq!("O'Brien", escape(Escape::Csv, "O'Brien"));
+        assert_eq!("\"a,b\"", escape(Escape::Csv, "a,b"));
+        assert_eq!("\"say \"\"hi\"\"\"", escape(Escape::Csv, "say \"hi\""));
+        assert_eq!("\"a\nb\"", escape(Escape::Csv, "a\nb"));
+    }
+
+    #[test]
+    fn test_json() {
+        assert_eq!("O'Brien", escape(Escape::Json, "O'Brien"));
+        assert_eq!("a\\\"b\\\\c", escape(Escape::Json, "a\"b\\c"));
+        assert_eq!("a\\nb\\tc\\u0001", escape(Escape::Json, "a\nb\tc\u{1}"));
+        assert_eq!("Zürich", escape(Escape::Json, "Zürich"));
+    }
+
+    #[test]
+    fn test_xml() {
+        assert_eq!(
+            "O&apos;Brien &amp; &lt;Co&gt; &quot;Ltd&quot;",
+            escape(Escape::Xml, "O'Brien & <Co> \"Ltd\"")
+        );
+    }
+
+    #[test]
+    fn test_sql() {
+        assert_eq!("O''Brien", escape(Escape::Sql, "O'Brien"));
+        assert_eq!("''''", escape(Escape::Sql, "''"));
+    }
+
+    #[test]
+    fn test_copy() {
+        assert_eq!("O'Brien", escape(Escape::Copy, "O'Brien"));
+        assert_eq!("a\\tb\\nc\\\\d", escape(Escape::Copy, "a\tb\nc\\d"));
+    }
+}
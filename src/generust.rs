@@ -1,16 +1,22 @@
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::fmt::{Debug, Display, Formatter};
 use std::io::Write;
-use std::num::ParseIntError;
+use std::num::{ParseFloatError, ParseIntError};
 use std::option;
 
-use chrono::{Duration, Local, NaiveDate, NaiveDateTime, ParseError};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, ParseError, Utc};
 use memmap::{Mmap, MmapOptions};
 use rand::Rng;
 use regex::Regex;
+use regex_syntax::hir::{Class, Hir, HirKind};
+use serde::Deserialize;
 use std::ffi::OsString;
 use uuid::Uuid;
 
+use crate::escape::Escape;
+
 #[derive(Debug)]
 pub enum Error {
     Macro(String),
@@ -21,7 +27,14 @@ pub enum Error {
     Pattern(glob::PatternError),
     None(option::NoneError),
     ParseInt(ParseIntError),
+    ParseFloat(ParseFloatError),
     ParseChrono(chrono::ParseError),
+    Template(String),
+    Schema(String),
+    Sqlite(rusqlite::Error),
+    Arrow(arrow::error::ArrowError),
+    Parquet(parquet::errors::ParquetError),
+    Xlsx(rust_xlsxwriter::XlsxError),
 }
 
 impl Display for Error {
@@ -36,6 +49,13 @@ impl Display for Error {
             Error::None(err) => Debug::fmt(err, f),
             Error::ParseChrono(err) => Display::fmt(err, f),
             Error::ParseInt(err) => Display::fmt(err, f),
+            Error::ParseFloat(err) => Display::fmt(err, f),
+            Error::Template(txt) => Display::fmt(txt, f),
+            Error::Schema(txt) => Display::fmt(txt, f),
+            Error::Sqlite(err) => Display::fmt(err, f),
+            Error::Arrow(err) => Display::fmt(err, f),
+            Error::Parquet(err) => Display::fmt(err, f),
+            Error::Xlsx(err) => Display::fmt(err, f),
         }
     }
 }
@@ -76,6 +96,30 @@ impl From<option::NoneError> for Error {
     }
 }
 
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Self {
+        Error::Sqlite(err)
+    }
+}
+
+impl From<arrow::error::ArrowError> for Error {
+    fn from(err: arrow::error::ArrowError) -> Self {
+        Error::Arrow(err)
+    }
+}
+
+impl From<parquet::errors::ParquetError> for Error {
+    fn from(err: parquet::errors::ParquetError) -> Self {
+        Error::Parquet(err)
+    }
+}
+
+impl From<rust_xlsxwriter::XlsxError> for Error {
+    fn from(err: rust_xlsxwriter::XlsxError) -> Self {
+        Error::Xlsx(err)
+    }
+}
+
 impl From<chrono::ParseError> for Error {
     fn from(err: ParseError) -> Self {
         Error::ParseChrono(err)
@@ -88,10 +132,289 @@ impl From<ParseIntError> for Error {
     }
 }
 
+impl From<ParseFloatError> for Error {
+    fn from(err: ParseFloatError) -> Self {
+        Error::ParseFloat(err)
+    }
+}
+
 pub type Result<T> = std::result::Result<T, Error>;
 
+// Days from 0001-01-01 to 1970-01-01, the epoch of the binary date encodings.
+pub const EPOCH_DAYS_FROM_CE: i32 = 719_163;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Int(i64),
+    Float(f64),
+    Decimal(i64, u32),
+    Bool(bool),
+    Str(String),
+    Date(NaiveDate),
+    Timestamp(DateTime<Utc>),
+    Uuid(Uuid),
+    List(Vec<Value>),
+    Map(Vec<(String, Value)>),
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Null => Ok(()),
+            Value::Int(v) => Display::fmt(v, f),
+            Value::Float(v) => Display::fmt(v, f),
+            Value::Decimal(units, 0) => Display::fmt(units, f),
+            Value::Decimal(units, scale) => {
+                let pow = 10u64.pow(*scale);
+                let sign = if *units < 0 { "-" } else { "" };
+                let abs = units.unsigned_abs();
+                let width = *scale as usize;
+                write!(
+                    f,
+                    "{}{}.{:0width$}",
+                    sign,
+                    abs / pow,
+                    abs % pow,
+                    width = width
+                )
+            }
+            Value::Bool(v) => Display::fmt(v, f),
+            Value::Str(v) => Display::fmt(v, f),
+            Value::Date(v) => Display::fmt(v, f),
+            Value::Timestamp(v) => Display::fmt(&v.format("%+"), f),
+            Value::Uuid(v) => Display::fmt(v, f),
+            Value::List(_) | Value::Map(_) => {
+                let mut buf = Vec::with_capacity(256);
+                self.write_json(&mut buf).map_err(|_| std::fmt::Error)?;
+                f.write_str(&String::from_utf8_lossy(&buf))
+            }
+        }
+    }
+}
+
+impl Value {
+    pub fn parse(kind: Kind, text: &str) -> Option<Value> {
+        match kind {
+            Kind::String => Some(Value::Str(text.to_string())),
+            Kind::Int => text.parse().ok().map(Value::Int),
+            Kind::Float => text.parse().ok().map(Value::Float),
+            Kind::Bool => text.parse().ok().map(Value::Bool),
+            Kind::Date => text.parse().ok().map(Value::Date),
+            Kind::Timestamp => DateTime::parse_from_rfc3339(text)
+                .ok()
+                .map(|ts| Value::Timestamp(ts.with_timezone(&Utc))),
+            Kind::Decimal => {
+                let (int, frac) = text.split_once('.').unwrap_or((text, ""));
+                if frac.len() > DECIMAL_SCALE as usize || !frac.bytes().all(|b| b.is_ascii_digit())
+                {
+                    return None;
+                }
+                let units = format!("{}{}", int, frac).parse().ok()?;
+                Some(Value::Decimal(units, frac.len() as u32))
+            }
+            Kind::Uuid => text.parse().ok().map(Value::Uuid),
+        }
+    }
+
+    // Whether the value can be written as a field of the given kind without parsing its text.
+    pub fn is(&self, kind: Kind) -> bool {
+        matches!(
+            (self, kind),
+            (Value::Null, _)
+                | (Value::List(_), _)
+                | (Value::Map(_), _)
+                | (Value::Str(_), Kind::String)
+                | (Value::Int(_), Kind::Int)
+                | (Value::Float(_), Kind::Float)
+                | (Value::Decimal(..), Kind::Decimal)
+                | (Value::Bool(_), Kind::Bool)
+                | (Value::Date(_), Kind::Date)
+                | (Value::Timestamp(_), Kind::Timestamp)
+                | (Value::Uuid(_), Kind::Uuid)
+        )
+    }
+
+    pub fn from_json(json: &serde_json::Value) -> Value {
+        match json {
+            serde_json::Value::Null => Value::Null,
+            serde_json::Value::Bool(v) => Value::Bool(*v),
+            serde_json::Value::Number(v) => match v.as_i64() {
+                Some(v) => Value::Int(v),
+                None => Value::Float(v.as_f64().unwrap_or(f64::NAN)),
+            },
+            serde_json::Value::String(v) => Value::Str(v.clone()),
+            serde_json::Value::Array(vs) => Value::List(vs.iter().map(Value::from_json).collect()),
+            serde_json::Value::Object(vs) => Value::Map(
+                vs.iter()
+                    .map(|(k, v)| (k.clone(), Value::from_json(v)))
+                    .collect(),
+            ),
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Int(v) => Some(*v as f64),
+            Value::Float(v) => Some(*v),
+            Value::Decimal(units, scale) => Some(*units as f64 / 10f64.powi(*scale as i32)),
+            _ => None,
+        }
+    }
+
+    // The value in units of 10^-scale, None if it is not a number or has more fractional digits.
+    pub fn as_units(&self, scale: u32) -> Option<i128> {
+        let (units, from) = match self {
+            Value::Int(v) => (i128::from(*v), 0),
+            Value::Decimal(units, from) => (i128::from(*units), *from),
+            _ => return None,
+        };
+        if from > scale {
+            let pow = 10i128.checked_pow(from - scale)?;
+            return if units % pow == 0 {
+                Some(units / pow)
+            } else {
+                None
+            };
+        }
+        units.checked_mul(10i128.checked_pow(scale - from)?)
+    }
+
+    pub fn write(&self, w: &mut dyn Write) -> Result<()> {
+        match self {
+            Value::Str(v) => w.write_all(v.as_bytes())?,
+            Value::List(_) | Value::Map(_) => self.write_json(w)?,
+            v => write!(w, "{}", v)?,
+        }
+        Ok(())
+    }
+
+    pub fn write_json(&self, w: &mut dyn Write) -> Result<()> {
+        match self {
+            Value::Null => w.write_all(b"null")?,
+            Value::Float(v) if !v.is_finite() => w.write_all(b"null")?,
+            Value::Int(_) | Value::Float(_) | Value::Decimal(..) | Value::Bool(_) => {
+                write!(w, "{}", self)?
+            }
+            Value::Str(v) => {
+                w.write_all(b"\"")?;
+                Escape::Json.write(v.as_bytes(), w)?;
+                w.write_all(b"\"")?;
+            }
+            Value::List(vs) => {
+                w.write_all(b"[")?;
+                for (i, v) in vs.iter().enumerate() {
+                    if i > 0 {
+                        w.write_all(b",")?;
+                    }
+                    v.write_json(w)?;
+                }
+                w.write_all(b"]")?;
+            }
+            Value::Map(vs) => {
+                w.write_all(b"{")?;
+                for (i, (k, v)) in vs.iter().enumerate() {
+                    if i > 0 {
+                        w.write_all(b",")?;
+                    }
+                    w.write_all(b"\"")?;
+                    Escape::Json.write(k.as_bytes(), w)?;
+                    w.write_all(b"\":")?;
+                    v.write_json(w)?;
+                }
+                w.write_all(b"}")?;
+            }
+            v => write!(w, "\"{}\"", v)?,
+        }
+        Ok(())
+    }
+}
+
+pub struct Context {
+    pub row: i32,
+    pub fields: Vec<(String, String)>,
+    pub values: Vec<(String, Value)>,
+}
+
+impl Context {
+    pub fn new(row: i32) -> Context {
+        Context {
+            row,
+            fields: vec![],
+            values: vec![],
+        }
+    }
+
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn value(&self, name: &str) -> Option<&Value> {
+        self.values.iter().find(|(n, _)| n == name).map(|(_, v)| v)
+    }
+
+    pub fn typed(&self, f: &FieldDef) -> Option<Value> {
+        let text = self.field(&f.name)?;
+        match self.value(&f.name) {
+            Some(value) if value.is(f.kind) => Some(value.clone()),
+            _ => Some(Value::parse(f.kind, text).unwrap_or_else(|| Value::Str(text.to_string()))),
+        }
+    }
+
+    pub fn set_field(&mut self, name: &str, text: String, value: Value) {
+        match self.fields.iter_mut().find(|(n, _)| n == name) {
+            Some(field) => field.1 = text,
+            None => self.fields.push((name.to_string(), text)),
+        }
+        match self.values.iter_mut().find(|(n, _)| n == name) {
+            Some(field) => field.1 = value,
+            None => self.values.push((name.to_string(), value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    String,
+    Int,
+    Float,
+    Bool,
+    Date,
+    Timestamp,
+    Decimal,
+    Uuid,
+}
+
+// Precision and scale of decimal columns in the typed formats, DECIMAL_RND allows up to 18 digits
+// after the point.
+pub const DECIMAL_PRECISION: u32 = 38;
+pub const DECIMAL_SCALE: u32 = 18;
+
+#[derive(Debug, Clone)]
+pub struct FieldDef {
+    pub name: String,
+    pub kind: Kind,
+    pub sql_type: Option<String>,
+}
+
+// Generators implement `value` and get formatted to text by the default `generate`,
+// templates that write text override `generate` and return it with `text`.
 pub trait Generust {
-    fn generate(&mut self, i: i32, w: &mut dyn Write) -> Result<()>;
+    fn generate(&mut self, ctx: &mut Context, w: &mut dyn Write) -> Result<()> {
+        self.value(ctx)?.write(w)
+    }
+
+    fn value(&mut self, ctx: &mut Context) -> Result<Value>;
+}
+
+pub fn text(generust: &mut dyn Generust, ctx: &mut Context) -> Result<Value> {
+    let mut buf = Vec::with_capacity(64);
+    generust.generate(ctx, &mut buf)?;
+    Ok(Value::Str(String::from_utf8_lossy(&buf).into_owned()))
 }
 
 struct Text {
@@ -107,29 +430,42 @@ impl Text {
 }
 
 impl Generust for Text {
-    fn generate(&mut self, _i: i32, w: &mut dyn Write) -> Result<()> {
+    fn generate(&mut self, _ctx: &mut Context, w: &mut dyn Write) -> Result<()> {
         Ok(w.write(self.text.as_bytes()).map(|_| ())?)
     }
+
+    fn value(&mut self, ctx: &mut Context) -> Result<Value> {
+        text(self, ctx)
+    }
 }
 
 struct RowNum {
     start: i32,
+    end: i64,
 }
 
 impl RowNum {
     fn create(args: &[&str]) -> Result<Box<dyn Generust>> {
-        let start: i32 = match args.len() {
-            0 => 0,
-            1 => args[0].parse::<i32>()?,
+        let (start, end) = match args.len() {
+            0 => (0, i64::MAX),
+            1 => (args[0].parse::<i32>()?, i64::MAX),
+            2 => (args[0].parse::<i32>()?, args[1].parse::<i64>()?),
             _ => return Err(Error::Macro("ROW_NUM".to_string())),
         };
-        Ok(Box::new(RowNum { start }))
+        Ok(Box::new(RowNum { start, end }))
     }
 }
 
 impl Generust for RowNum {
-    fn generate(&mut self, i: i32, w: &mut dyn Write) -> Result<()> {
-        Ok(write!(w, "{}", self.start + i)?)
+    fn value(&mut self, ctx: &mut Context) -> Result<Value> {
+        let v = i64::from(self.start) + i64::from(ctx.row);
+        if v > self.end {
+            return Err(Error::Macro(format!(
+                "ROW_NUM: {} is past the end {}",
+                v, self.end
+            )));
+        }
+        Ok(Value::Int(v))
     }
 }
 
@@ -154,11 +490,10 @@ impl DateRnd {
 }
 
 impl Generust for DateRnd {
-    fn generate(&mut self, _i: i32, w: &mut dyn Write) -> Result<()> {
+    fn value(&mut self, _ctx: &mut Context) -> Result<Value> {
         let mut rng = rand::thread_rng();
         let ts = rng.gen_range(self.start, self.end + 1);
-        let date = NaiveDateTime::from_timestamp(ts, 0).date();
-        Ok(write!(w, "{}", date)?)
+        Ok(Value::Date(NaiveDateTime::from_timestamp(ts, 0).date()))
     }
 }
 
@@ -183,9 +518,9 @@ impl DateSeq {
 }
 
 impl Generust for DateSeq {
-    fn generate(&mut self, i: i32, w: &mut dyn Write) -> Result<()> {
-        let date = self.start + Duration::days(i as i64 % self.length * self.length.signum());
-        Ok(write!(w, "{}", date)?)
+    fn value(&mut self, ctx: &mut Context) -> Result<Value> {
+        let days = ctx.row as i64 % self.length * self.length.signum();
+        Ok(Value::Date(self.start + Duration::days(days)))
     }
 }
 
@@ -198,8 +533,8 @@ impl Uuid4 {
 }
 
 impl Generust for Uuid4 {
-    fn generate(&mut self, _i: i32, w: &mut dyn Write) -> Result<()> {
-        Ok(write!(w, "{}", Uuid::new_v4())?)
+    fn value(&mut self, _ctx: &mut Context) -> Result<Value> {
+        Ok(Value::Uuid(Uuid::new_v4()))
     }
 }
 
@@ -221,32 +556,37 @@ impl IntSeq {
 }
 
 impl Generust for IntSeq {
-    fn generate(&mut self, i: i32, w: &mut dyn Write) -> Result<()> {
-        Ok(write!(w, "{}", self.start + i % (self.end - self.start))?)
+    fn value(&mut self, ctx: &mut Context) -> Result<Value> {
+        Ok(Value::Int(i64::from(
+            self.start + ctx.row % (self.end - self.start),
+        )))
     }
 }
 
 struct IntRnd {
-    start: i32,
-    end: i32,
+    start: i64,
+    end: i64,
 }
 
 impl IntRnd {
     fn create(args: &[&str]) -> Result<Box<dyn Generust>> {
         let (start, end) = match args.len() {
-            0 => (0, std::i32::MAX),
+            0 => (0, i64::from(std::i32::MAX)),
             1 => (0, args[0].parse()?),
             2 => (args[0].parse()?, args[1].parse()?),
             _ => return Err(Error::Macro("INT_RND".to_string())),
         };
+        if start >= end {
+            return Err(Error::Macro("INT_RND".to_string()));
+        }
         Ok(Box::new(IntRnd { start, end }))
     }
 }
 
 impl Generust for IntRnd {
-    fn generate(&mut self, _i: i32, w: &mut dyn Write) -> Result<()> {
+    fn value(&mut self, _ctx: &mut Context) -> Result<Value> {
         let mut rng = rand::thread_rng();
-        Ok(write!(w, "{}", rng.gen_range(self.start, self.end))?)
+        Ok(Value::Int(rng.gen_range(self.start, self.end)))
     }
 }
 
@@ -259,13 +599,13 @@ impl IpV4 {
 }
 
 impl Generust for IpV4 {
-    fn generate(&mut self, _i: i32, w: &mut dyn Write) -> Result<()> {
+    fn value(&mut self, _ctx: &mut Context) -> Result<Value> {
         let mut rng = rand::thread_rng();
         let b1: u8 = rng.gen_range(1, 255);
         let b2: u8 = rng.gen_range(0, 255);
         let b3: u8 = rng.gen_range(0, 255);
         let b4: u8 = rng.gen_range(1, 255);
-        Ok(write!(w, "{}.{}.{}.{}", b1, b2, b3, b4)?)
+        Ok(Value::Str(format!("{}.{}.{}.{}", b1, b2, b3, b4)))
     }
 }
 
@@ -278,8 +618,69 @@ impl Timestamp {
 }
 
 impl Generust for Timestamp {
-    fn generate(&mut self, _i: i32, w: &mut dyn Write) -> Result<()> {
-        Ok(write!(w, "{}", chrono::Utc::now().format("%+"))?)
+    fn value(&mut self, _ctx: &mut Context) -> Result<Value> {
+        Ok(Value::Timestamp(Utc::now()))
+    }
+}
+
+struct TimestampSeq {
+    start: DateTime<Utc>,
+    step: Duration,
+}
+
+impl TimestampSeq {
+    fn create(args: &[&str]) -> Result<Box<dyn Generust>> {
+        fn step(arg: &str) -> Option<Duration> {
+            let unit = arg.trim_start_matches(|c: char| c == '-' || c.is_ascii_digit());
+            let n = arg[..arg.len() - unit.len()].parse::<i64>().ok()?;
+            match unit {
+                "ms" => Duration::try_milliseconds(n),
+                "" | "s" => Duration::try_seconds(n),
+                "m" => Duration::try_minutes(n),
+                "h" => Duration::try_hours(n),
+                "d" => Duration::try_days(n),
+                _ => None,
+            }
+        }
+        let (start, step) = match args.len() {
+            0 => (Utc::now(), Some(Duration::seconds(1))),
+            1 => (args[0].parse()?, Some(Duration::seconds(1))),
+            2 => (args[0].parse()?, step(args[1])),
+            _ => return Err(Error::Macro("TIMESTAMP_SEQ".to_string())),
+        };
+        match step {
+            Some(step) => Ok(Box::new(TimestampSeq { start, step })),
+            None => Err(Error::Macro("TIMESTAMP_SEQ".to_string())),
+        }
+    }
+}
+
+impl Generust for TimestampSeq {
+    fn value(&mut self, ctx: &mut Context) -> Result<Value> {
+        self.step
+            .checked_mul(ctx.row)
+            .and_then(|step| self.start.checked_add_signed(step))
+            .map(Value::Timestamp)
+            .ok_or_else(|| {
+                Error::Macro(format!(
+                    "TIMESTAMP_SEQ: row {} is out of the timestamp range",
+                    ctx.row
+                ))
+            })
+    }
+}
+
+struct Boolean;
+
+impl Boolean {
+    fn create(_args: &[&str]) -> Result<Box<dyn Generust>> {
+        Ok(Box::new(Boolean))
+    }
+}
+
+impl Generust for Boolean {
+    fn value(&mut self, _ctx: &mut Context) -> Result<Value> {
+        Ok(Value::Bool(rand::thread_rng().gen()))
     }
 }
 
@@ -295,11 +696,6 @@ impl EnumRnd {
         };
         Ok(Box::new(EnumRnd { vars }))
     }
-    fn create_boolean(_args: &[&str]) -> Result<Box<dyn Generust>> {
-        Ok(Box::new(EnumRnd {
-            vars: vec!["true".to_string(), "false".to_string()],
-        }))
-    }
     fn create_gender(_args: &[&str]) -> Result<Box<dyn Generust>> {
         Ok(Box::new(EnumRnd {
             vars: vec!["Male".to_string(), "Female".to_string()],
@@ -321,10 +717,10 @@ impl EnumRnd {
 }
 
 impl Generust for EnumRnd {
-    fn generate(&mut self, _i: i32, w: &mut dyn Write) -> Result<()> {
+    fn value(&mut self, _ctx: &mut Context) -> Result<Value> {
         let mut rng = rand::thread_rng();
         let i = rng.gen_range(0, self.vars.len());
-        Ok(w.write(self.vars[i].as_bytes()).map(|_| ())?)
+        Ok(Value::Str(self.vars[i].clone()))
     }
 }
 
@@ -343,9 +739,210 @@ impl EnumSeq {
 }
 
 impl Generust for EnumSeq {
-    fn generate(&mut self, i: i32, w: &mut dyn Write) -> Result<()> {
-        Ok(w.write(self.vars[i as usize % self.vars.len()].as_bytes())
-            .map(|_| ())?)
+    fn value(&mut self, ctx: &mut Context) -> Result<Value> {
+        Ok(Value::Str(
+            self.vars[ctx.row as usize % self.vars.len()].clone(),
+        ))
+    }
+}
+
+struct FloatRnd {
+    start: f64,
+    end: f64,
+}
+
+impl FloatRnd {
+    fn create(args: &[&str]) -> Result<Box<dyn Generust>> {
+        let (start, end) = match args.len() {
+            0 => (0.0, 1.0),
+            1 => (0.0, args[0].parse()?),
+            2 => (args[0].parse()?, args[1].parse()?),
+            _ => return Err(Error::Macro("FLOAT_RND".to_string())),
+        };
+        if start >= end {
+            return Err(Error::Macro("FLOAT_RND".to_string()));
+        }
+        Ok(Box::new(FloatRnd { start, end }))
+    }
+}
+
+impl Generust for FloatRnd {
+    fn value(&mut self, _ctx: &mut Context) -> Result<Value> {
+        let mut rng = rand::thread_rng();
+        Ok(Value::Float(rng.gen_range(self.start, self.end)))
+    }
+}
+
+struct DecimalRnd {
+    start: i64,
+    end: i64,
+    scale: u32,
+}
+
+impl DecimalRnd {
+    fn create(args: &[&str]) -> Result<Box<dyn Generust>> {
+        fn digits(arg: &str) -> u32 {
+            arg.find('.')
+                .map(|i| (arg.len() - i - 1) as u32)
+                .unwrap_or(0)
+        }
+        fn units(arg: &str, scale: u32) -> Result<i64> {
+            let exact = Value::parse(Kind::Decimal, arg)
+                .and_then(|v| v.as_units(scale))
+                .and_then(|v| i64::try_from(v).ok());
+            match exact {
+                Some(units) => Ok(units),
+                None => Ok((arg.parse::<f64>()? * 10f64.powi(scale as i32)).round() as i64),
+            }
+        }
+        let scale = match args.len() {
+            2 => digits(args[0]).max(digits(args[1])),
+            3 => args[2].parse()?,
+            _ => return Err(Error::Macro("DECIMAL_RND".to_string())),
+        };
+        if scale > DECIMAL_SCALE {
+            return Err(Error::Macro("DECIMAL_RND".to_string()));
+        }
+        let (start, end) = (units(args[0], scale)?, units(args[1], scale)?);
+        if start > end {
+            return Err(Error::Macro("DECIMAL_RND".to_string()));
+        }
+        Ok(Box::new(DecimalRnd { start, end, scale }))
+    }
+}
+
+impl Generust for DecimalRnd {
+    fn value(&mut self, _ctx: &mut Context) -> Result<Value> {
+        let mut rng = rand::thread_rng();
+        Ok(Value::Decimal(
+            rng.gen_range(self.start, self.end + 1),
+            self.scale,
+        ))
+    }
+}
+
+static ALPHANUMERIC: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+struct StrRnd {
+    min: usize,
+    max: usize,
+}
+
+impl StrRnd {
+    fn create(args: &[&str]) -> Result<Box<dyn Generust>> {
+        let (min, max) = match args.len() {
+            0 => (8, 16),
+            1 => (args[0].parse()?, args[0].parse()?),
+            2 => (args[0].parse()?, args[1].parse()?),
+            _ => return Err(Error::Macro("STR_RND".to_string())),
+        };
+        if min > max {
+            return Err(Error::Macro("STR_RND".to_string()));
+        }
+        Ok(Box::new(StrRnd { min, max }))
+    }
+}
+
+impl Generust for StrRnd {
+    fn value(&mut self, _ctx: &mut Context) -> Result<Value> {
+        let mut rng = rand::thread_rng();
+        let len = rng.gen_range(self.min, self.max + 1);
+        let mut buf = String::with_capacity(len);
+        for _ in 0..len {
+            let i = rng.gen_range(0, ALPHANUMERIC.len());
+            buf.push(char::from(ALPHANUMERIC[i]));
+        }
+        Ok(Value::Str(buf))
+    }
+}
+
+struct RegexRnd {
+    hir: Hir,
+}
+
+impl RegexRnd {
+    fn create(args: &[&str]) -> Result<Box<dyn Generust>> {
+        if args.is_empty() {
+            return Err(Error::Macro("REGEX".to_string()));
+        }
+        let hir = regex_syntax::parse(&args.join(","))
+            .map_err(|e| Error::Macro(format!("REGEX: {}", e)))?;
+        Ok(Box::new(RegexRnd { hir }))
+    }
+}
+
+fn regex_rnd(hir: &Hir, rng: &mut impl Rng, buf: &mut String) {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Look(_) => {}
+        HirKind::Literal(lit) => buf.push_str(&String::from_utf8_lossy(&lit.0)),
+        HirKind::Class(Class::Unicode(class)) => {
+            let ranges = class
+                .ranges()
+                .iter()
+                .map(|r| (r.start() as u32, r.end() as u32))
+                .collect::<Vec<_>>();
+            let printable = ranges
+                .iter()
+                .filter_map(|&(a, b)| {
+                    let (a, b) = (a.max(0x20), b.min(0x7e));
+                    if a <= b {
+                        Some((a, b))
+                    } else {
+                        None
+                    }
+                })
+                .collect::<Vec<_>>();
+            let ranges = if printable.is_empty() {
+                ranges
+            } else {
+                printable
+            };
+            let total: u32 = ranges.iter().map(|(a, b)| b - a + 1).sum();
+            if total == 0 {
+                return;
+            }
+            let mut n = rng.gen_range(0, total);
+            for (a, b) in ranges {
+                if n <= b - a {
+                    if let Some(c) = std::char::from_u32(a + n) {
+                        buf.push(c);
+                    }
+                    return;
+                }
+                n -= b - a + 1;
+            }
+        }
+        HirKind::Class(Class::Bytes(class)) => {
+            if let Some(r) = class.ranges().first() {
+                let b = rng.gen_range(u16::from(r.start()), u16::from(r.end()) + 1);
+                buf.push(char::from(b as u8));
+            }
+        }
+        HirKind::Repetition(rep) => {
+            let max = rep.max.unwrap_or(rep.min + 8);
+            let count = rng.gen_range(rep.min, max + 1);
+            for _ in 0..count {
+                regex_rnd(&rep.sub, rng, buf);
+            }
+        }
+        HirKind::Capture(cap) => regex_rnd(&cap.sub, rng, buf),
+        HirKind::Concat(hirs) => {
+            for hir in hirs {
+                regex_rnd(hir, rng, buf);
+            }
+        }
+        HirKind::Alternation(hirs) => {
+            let i = rng.gen_range(0, hirs.len());
+            regex_rnd(&hirs[i], rng, buf);
+        }
+    }
+}
+
+impl Generust for RegexRnd {
+    fn value(&mut self, _ctx: &mut Context) -> Result<Value> {
+        let mut buf = String::with_capacity(32);
+        regex_rnd(&self.hir, &mut rand::thread_rng(), &mut buf);
+        Ok(Value::Str(buf))
     }
 }
 
@@ -358,12 +955,12 @@ impl Phone {
 }
 
 impl Generust for Phone {
-    fn generate(&mut self, _i: i32, w: &mut dyn Write) -> Result<()> {
+    fn value(&mut self, _ctx: &mut Context) -> Result<Value> {
         let mut rng = rand::thread_rng();
         let x1 = rng.gen_range(1, 1000);
         let x2 = rng.gen_range(1, 1000);
         let x3 = rng.gen_range(1, 10000);
-        Ok(write!(w, "8-{:03}-{:03}-{:04}", x1, x2, x3)?)
+        Ok(Value::Str(format!("8-{:03}-{:03}-{:04}", x1, x2, x3)))
     }
 }
 
@@ -410,9 +1007,13 @@ impl BytesRnd<'_> {
 }
 
 impl<'a> Generust for BytesRnd<'a> {
-    fn generate(&mut self, _i: i32, w: &mut dyn Write) -> Result<()> {
+    fn generate(&mut self, _ctx: &mut Context, w: &mut dyn Write) -> Result<()> {
         Ok(w.write(random_line(self.bytes)).map(|_| ())?)
     }
+
+    fn value(&mut self, ctx: &mut Context) -> Result<Value> {
+        text(self, ctx)
+    }
 }
 
 fn next_line<'a>(data: &'a [u8], offset: &mut usize) -> &'a [u8] {
@@ -461,10 +1062,14 @@ impl BytesSeq<'_> {
 }
 
 impl<'a> Generust for BytesSeq<'a> {
-    fn generate(&mut self, _i: i32, w: &mut dyn Write) -> Result<()> {
+    fn generate(&mut self, _ctx: &mut Context, w: &mut dyn Write) -> Result<()> {
         Ok(w.write(next_line(self.bytes, &mut self.offset))
             .map(|_| ())?)
     }
+
+    fn value(&mut self, ctx: &mut Context) -> Result<Value> {
+        text(self, ctx)
+    }
 }
 
 struct FileRnd {
@@ -484,9 +1089,13 @@ impl FileRnd {
 }
 
 impl Generust for FileRnd {
-    fn generate(&mut self, _i: i32, w: &mut dyn Write) -> Result<()> {
+    fn generate(&mut self, _ctx: &mut Context, w: &mut dyn Write) -> Result<()> {
         Ok(w.write(random_line(&self.mem)).map(|_| ())?)
     }
+
+    fn value(&mut self, ctx: &mut Context) -> Result<Value> {
+        text(self, ctx)
+    }
 }
 
 struct FileSeq {
@@ -510,22 +1119,207 @@ impl FileSeq {
 }
 
 impl Generust for FileSeq {
-    fn generate(&mut self, _i: i32, w: &mut dyn Write) -> Result<()> {
+    fn generate(&mut self, _ctx: &mut Context, w: &mut dyn Write) -> Result<()> {
         Ok(w.write(next_line(&self.mem, &mut self.offset))
             .map(|_| ())?)
     }
+
+    fn value(&mut self, ctx: &mut Context) -> Result<Value> {
+        text(self, ctx)
+    }
 }
 
+struct Field {
+    name: Option<String>,
+    kind: Option<Kind>,
+    escape: Escape,
+    generust: Box<dyn Generust>,
+    buf: Vec<u8>,
+}
+
+impl Generust for Field {
+    fn generate(&mut self, ctx: &mut Context, w: &mut dyn Write) -> Result<()> {
+        self.buf.clear();
+        match &self.name {
+            Some(name) => {
+                let mut value = self.generust.value(ctx)?;
+                value.write(&mut self.buf)?;
+                self.escape.write(&self.buf, w)?;
+                let text = String::from_utf8_lossy(&self.buf).into_owned();
+                // A schema type that differs from the generator's converts the value once here
+                if let Some(kind) = self.kind.filter(|k| !value.is(*k)) {
+                    if let Some(typed) = Value::parse(kind, &text) {
+                        value = typed;
+                    }
+                }
+                ctx.set_field(name, text, value);
+            }
+            None => {
+                self.generust.generate(ctx, &mut self.buf)?;
+                self.escape.write(&self.buf, w)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn value(&mut self, ctx: &mut Context) -> Result<Value> {
+        text(self, ctx)
+    }
+}
+
+struct Truncate {
+    length: usize,
+    generust: Box<dyn Generust>,
+    buf: Vec<u8>,
+}
+
+pub fn truncate(generust: Box<dyn Generust>, length: usize) -> Box<dyn Generust> {
+    Box::new(Truncate {
+        length,
+        generust,
+        buf: Vec::with_capacity(64),
+    })
+}
+
+impl Generust for Truncate {
+    fn generate(&mut self, ctx: &mut Context, w: &mut dyn Write) -> Result<()> {
+        self.buf.clear();
+        self.generust.generate(ctx, &mut self.buf)?;
+        let text = String::from_utf8_lossy(&self.buf);
+        let end = match text.char_indices().nth(self.length) {
+            Some((i, _)) => i,
+            None => text.len(),
+        };
+        Ok(w.write_all(text[..end].as_bytes())?)
+    }
+
+    fn value(&mut self, ctx: &mut Context) -> Result<Value> {
+        text(self, ctx)
+    }
+}
+
+enum Operand {
+    Row,
+    Field(String),
+}
+
+enum Condition {
+    Chance(f64),
+    Present(String),
+    Compare(Operand, String, String),
+}
+
+impl Condition {
+    fn test(&self, ctx: &Context) -> bool {
+        match self {
+            Condition::Chance(p) => rand::thread_rng().gen_bool(*p),
+            Condition::Present(name) => matches!(ctx.field(name), Some(v) if !v.is_empty()),
+            Condition::Compare(operand, op, value) => {
+                let row = ctx.row.to_string();
+                let left = match operand {
+                    Operand::Row => Some(row.as_str()),
+                    Operand::Field(name) => ctx.field(name),
+                };
+                match left {
+                    Some(left) => compare(left, op, value),
+                    None => false,
+                }
+            }
+        }
+    }
+}
+
+fn compare(left: &str, op: &str, right: &str) -> bool {
+    let ord = match (left.parse::<f64>(), right.parse::<f64>()) {
+        (Ok(l), Ok(r)) => l.partial_cmp(&r),
+        _ => Some(left.cmp(right)),
+    };
+    match ord {
+        Some(ord) => match op {
+            "==" => ord == Ordering::Equal,
+            "!=" => ord != Ordering::Equal,
+            "<" => ord == Ordering::Less,
+            "<=" => ord != Ordering::Greater,
+            ">" => ord == Ordering::Greater,
+            ">=" => ord != Ordering::Less,
+            _ => false,
+        },
+        None => op == "!=",
+    }
+}
+
+struct Conditional {
+    condition: Condition,
+    then: Vec<Box<dyn Generust>>,
+    other: Vec<Box<dyn Generust>>,
+}
+
+impl Generust for Conditional {
+    fn generate(&mut self, ctx: &mut Context, w: &mut dyn Write) -> Result<()> {
+        let branch = if self.condition.test(ctx) {
+            &mut self.then
+        } else {
+            &mut self.other
+        };
+        for g in branch {
+            g.generate(ctx, w)?;
+        }
+        Ok(())
+    }
+
+    fn value(&mut self, ctx: &mut Context) -> Result<Value> {
+        text(self, ctx)
+    }
+}
+
+struct Block {
+    condition: Condition,
+    then: Vec<Box<dyn Generust>>,
+    other: Option<Vec<Box<dyn Generust>>>,
+}
+
+#[derive(Default)]
 pub struct Composite {
+    pub fields: Vec<FieldDef>,
     generusts: Vec<Box<dyn Generust>>,
 }
 
+impl Composite {
+    pub fn push(&mut self, generust: Box<dyn Generust>) {
+        self.generusts.push(generust);
+    }
+
+    pub fn push_field(&mut self, def: FieldDef, generust: Box<dyn Generust>, chance: f64) {
+        let field = Box::new(Field {
+            name: Some(def.name.clone()),
+            kind: Some(def.kind),
+            escape: Escape::None,
+            generust,
+            buf: Vec::with_capacity(64),
+        });
+        if chance < 1.0 {
+            self.generusts.push(Box::new(Conditional {
+                condition: Condition::Chance(chance),
+                then: vec![field],
+                other: vec![],
+            }));
+        } else {
+            self.generusts.push(field);
+        }
+        self.fields.push(def);
+    }
+}
+
 impl Generust for Composite {
-    fn generate(&mut self, i: i32, w: &mut dyn Write) -> Result<()> {
+    fn generate(&mut self, ctx: &mut Context, w: &mut dyn Write) -> Result<()> {
         for g in &mut self.generusts {
-            g.generate(i, w)?;
+            g.generate(ctx, w)?;
         }
-        Ok(w.write(b"\n").map(|_| ())?)
+        Ok(())
+    }
+
+    fn value(&mut self, ctx: &mut Context) -> Result<Value> {
+        text(self, ctx)
     }
 }
 
@@ -534,73 +1328,219 @@ type MacroFactory = fn(&[&str]) -> Result<Box<dyn Generust>>;
 pub struct Parser {
     rx_template: Regex,
     rx_macro: Regex,
-    mc_factories: HashMap<String, MacroFactory>,
+    rx_field: Regex,
+    rx_if: Regex,
+    rx_compare: Regex,
+    mc_factories: HashMap<String, (Kind, MacroFactory)>,
     separator_args: String,
+    escape: Escape,
 }
 
 impl Parser {
-    pub fn new(macro_start: &str, separator_args: &str) -> Result<Parser> {
+    pub fn new(macro_start: &str, separator_args: &str, escape: Escape) -> Result<Parser> {
         let rx_template = Regex::new(&format!("({}{})", macro_start, r"\{([^}]+)}"))?;
         let rx_macro = Regex::new(r"(^.+)\((.*)\)")?;
-
-        fn reg(fs: &mut HashMap<String, MacroFactory>, name: &str, f: MacroFactory) {
-            fs.insert(name.to_string(), f);
+        let rx_field = Regex::new(r"^(\w+)\s*=\s*(.+)$")?;
+        let rx_if = Regex::new(r"^IF\s+(.+)$")?;
+        let rx_compare = Regex::new(r"^(\w+)\s*(==|!=|<=|>=|<|>)\s*(.*?)\s*$")?;
+
+        fn reg(
+            fs: &mut HashMap<String, (Kind, MacroFactory)>,
+            name: &str,
+            kind: Kind,
+            f: MacroFactory,
+        ) {
+            fs.insert(name.to_string(), (kind, f));
         }
 
         let mut mc_factories = HashMap::new();
 
-        reg(&mut mc_factories, "ROW_NUM", RowNum::create);
-        reg(&mut mc_factories, "INT_SEQ", IntSeq::create);
-        reg(&mut mc_factories, "INT_RND", IntRnd::create);
-        reg(&mut mc_factories, "DATE_SEQ", DateSeq::create);
-        reg(&mut mc_factories, "DATE_RND", DateRnd::create);
-        reg(&mut mc_factories, "UUID4", Uuid4::create);
-        reg(&mut mc_factories, "IPV4", IpV4::create);
-        reg(&mut mc_factories, "TIMESTAMP", Timestamp::create);
-        reg(&mut mc_factories, "ENUM_SEQ", EnumSeq::create);
-        reg(&mut mc_factories, "ENUM_RND", EnumRnd::create);
-        reg(&mut mc_factories, "TIME_ZONE", EnumRnd::create_time_zone);
-        reg(&mut mc_factories, "BOOLEAN", EnumRnd::create_boolean);
-        reg(&mut mc_factories, "GENDER", EnumRnd::create_gender);
-        reg(&mut mc_factories, "PHONE", Phone::create);
-        reg(&mut mc_factories, "FIRST_SEQ", BytesSeq::create_first);
-        reg(&mut mc_factories, "FIRST_RND", BytesRnd::create_first);
-        reg(&mut mc_factories, "LAST_SEQ", BytesSeq::create_last);
-        reg(&mut mc_factories, "LAST_RND", BytesRnd::create_last);
-        reg(&mut mc_factories, "DOMAIN_SEQ", BytesSeq::create_domain);
-        reg(&mut mc_factories, "DOMAIN_RND", BytesRnd::create_domain);
+        reg(&mut mc_factories, "ROW_NUM", Kind::Int, RowNum::create);
+        reg(&mut mc_factories, "INT_SEQ", Kind::Int, IntSeq::create);
+        reg(&mut mc_factories, "INT_RND", Kind::Int, IntRnd::create);
+        reg(&mut mc_factories, "DATE_SEQ", Kind::Date, DateSeq::create);
+        reg(&mut mc_factories, "DATE_RND", Kind::Date, DateRnd::create);
+        reg(&mut mc_factories, "UUID4", Kind::Uuid, Uuid4::create);
+        reg(&mut mc_factories, "IPV4", Kind::String, IpV4::create);
+        reg(
+            &mut mc_factories,
+            "TIMESTAMP",
+            Kind::Timestamp,
+            Timestamp::create,
+        );
+        reg(
+            &mut mc_factories,
+            "TIMESTAMP_SEQ",
+            Kind::Timestamp,
+            TimestampSeq::create,
+        );
+        reg(&mut mc_factories, "ENUM_SEQ", Kind::String, EnumSeq::create);
+        reg(&mut mc_factories, "ENUM_RND", Kind::String, EnumRnd::create);
+        reg(
+            &mut mc_factories,
+            "TIME_ZONE",
+            Kind::String,
+            EnumRnd::create_time_zone,
+        );
+        reg(&mut mc_factories, "BOOLEAN", Kind::Bool, Boolean::create);
+        reg(
+            &mut mc_factories,
+            "GENDER",
+            Kind::String,
+            EnumRnd::create_gender,
+        );
+        reg(&mut mc_factories, "PHONE", Kind::String, Phone::create);
+        reg(
+            &mut mc_factories,
+            "FIRST_SEQ",
+            Kind::String,
+            BytesSeq::create_first,
+        );
+        reg(
+            &mut mc_factories,
+            "FIRST_RND",
+            Kind::String,
+            BytesRnd::create_first,
+        );
+        reg(
+            &mut mc_factories,
+            "LAST_SEQ",
+            Kind::String,
+            BytesSeq::create_last,
+        );
+        reg(
+            &mut mc_factories,
+            "LAST_RND",
+            Kind::String,
+            BytesRnd::create_last,
+        );
+        reg(
+            &mut mc_factories,
+            "DOMAIN_SEQ",
+            Kind::String,
+            BytesSeq::create_domain,
+        );
+        reg(
+            &mut mc_factories,
+            "DOMAIN_RND",
+            Kind::String,
+            BytesRnd::create_domain,
+        );
         reg(
             &mut mc_factories,
             "COUNTRY_CODE_SEQ",
+            Kind::String,
             BytesSeq::create_country_code,
         );
         reg(
             &mut mc_factories,
             "COUNTRY_CODE_RND",
+            Kind::String,
             BytesRnd::create_country_code,
         );
-        reg(&mut mc_factories, "FILE_RND", FileRnd::create);
-        reg(&mut mc_factories, "FILE_SEQ", FileSeq::create);
+        reg(&mut mc_factories, "FILE_RND", Kind::String, FileRnd::create);
+        reg(&mut mc_factories, "FILE_SEQ", Kind::String, FileSeq::create);
+        reg(
+            &mut mc_factories,
+            "FLOAT_RND",
+            Kind::Float,
+            FloatRnd::create,
+        );
+        reg(
+            &mut mc_factories,
+            "DECIMAL_RND",
+            Kind::Decimal,
+            DecimalRnd::create,
+        );
+        reg(&mut mc_factories, "STR_RND", Kind::String, StrRnd::create);
+        reg(&mut mc_factories, "REGEX", Kind::String, RegexRnd::create);
 
         Ok(Parser {
             rx_template,
             rx_macro,
+            rx_field,
+            rx_if,
+            rx_compare,
             mc_factories,
             separator_args: separator_args.to_string(),
+            escape,
         })
     }
 
-    pub fn parse(&self, template: &str) -> Result<Box<dyn Generust>> {
+    pub fn parse(&self, template: &str) -> Result<Composite> {
         self.parse_template(template)
     }
 
+    pub fn create(&self, name: &str, args: &[&str]) -> Result<(Kind, Box<dyn Generust>)> {
+        match self.mc_factories.get(name) {
+            Some((kind, factory)) => Ok((*kind, factory(args)?)),
+            None => Err(Error::Macro(format!("{}: unknown macro", name))),
+        }
+    }
+
     fn parse_text(&self, text: &str) -> Box<dyn Generust> {
         Box::new(Text {
             text: String::from(text),
         })
     }
 
-    fn parse_macro(&self, text: &str) -> Result<Box<dyn Generust>> {
+    fn parse_field(&self, text: &str) -> Result<(Option<FieldDef>, Box<dyn Generust>)> {
+        let (name, text) = match self.rx_field.captures(text) {
+            Some(cap) => (
+                Some(cap.get(1).unwrap().as_str().to_string()),
+                cap.get(2).unwrap().as_str(),
+            ),
+            None => (None, text),
+        };
+        let (kind, generust) = self.parse_macro(text)?;
+        if name.is_none() && self.escape == Escape::None {
+            return Ok((None, generust));
+        }
+        let def = name.as_ref().map(|name| FieldDef {
+            name: name.clone(),
+            kind,
+            sql_type: None,
+        });
+        let field = Box::new(Field {
+            name,
+            kind: None,
+            escape: self.escape,
+            generust,
+            buf: Vec::with_capacity(64),
+        });
+        Ok((def, field))
+    }
+
+    fn parse_condition(&self, text: &str) -> Result<Condition> {
+        let text = text.trim();
+        if let Ok(p) = text.parse::<f64>() {
+            return if (0.0..=1.0).contains(&p) {
+                Ok(Condition::Chance(p))
+            } else {
+                Err(Error::Template(format!(
+                    "IF {}: probability out of range",
+                    text
+                )))
+            };
+        }
+        if let Some(cap) = self.rx_compare.captures(text) {
+            let operand = match &cap[1] {
+                "ROW" => Operand::Row,
+                name => Operand::Field(name.to_string()),
+            };
+            return Ok(Condition::Compare(
+                operand,
+                cap[2].to_string(),
+                cap[3].to_string(),
+            ));
+        }
+        if text.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return Ok(Condition::Present(text.to_string()));
+        }
+        Err(Error::Template(format!("IF {}: invalid condition", text)))
+    }
+
+    fn parse_macro(&self, text: &str) -> Result<(Kind, Box<dyn Generust>)> {
         let (name, args) = match self.rx_macro.captures(text) {
             Some(cap) => {
                 let name = cap.get(1)?.as_str();
@@ -615,35 +1555,88 @@ impl Parser {
             None => (text, vec![]),
         };
         match self.mc_factories.get(name) {
-            Some(factory) => factory(&args),
-            None => Text::parse(text),
+            Some((kind, factory)) => Ok((*kind, factory(&args)?)),
+            None => Ok((Kind::String, Text::parse(text)?)),
         }
     }
 
-    fn parse_template(&self, template: &str) -> Result<Box<dyn Generust>> {
+    fn parse_template(&self, template: &str) -> Result<Composite> {
+        fn push(root: &mut Vec<Box<dyn Generust>>, blocks: &mut [Block], g: Box<dyn Generust>) {
+            match blocks.last_mut() {
+                Some(Block {
+                    other: Some(other), ..
+                }) => other.push(g),
+                Some(block) => block.then.push(g),
+                None => root.push(g),
+            }
+        }
+
         let mut gs: Vec<Box<dyn Generust>> = vec![];
+        let mut fields: Vec<FieldDef> = vec![];
+        let mut blocks: Vec<Block> = vec![];
         let mut start = 0;
         for cap in self.rx_template.captures_iter(template) {
             let outer = cap.get(1).unwrap();
-            let inner = cap.get(2).unwrap();
+            let inner = cap.get(2).unwrap().as_str().trim();
 
             // Text
             if outer.start() > start {
-                gs.push(self.parse_text(&template[start..outer.start()]))
+                push(
+                    &mut gs,
+                    &mut blocks,
+                    self.parse_text(&template[start..outer.start()]),
+                );
             }
 
-            // Generust
-            gs.push(self.parse_macro(inner.as_str())?);
+            // Directive or Generust
+            if let Some(cap) = self.rx_if.captures(inner) {
+                blocks.push(Block {
+                    condition: self.parse_condition(&cap[1])?,
+                    then: vec![],
+                    other: None,
+                });
+            } else if inner == "ELSE" {
+                match blocks.last_mut() {
+                    Some(block) if block.other.is_none() => block.other = Some(vec![]),
+                    _ => return Err(Error::Template("ELSE without IF".to_string())),
+                }
+            } else if inner == "END" {
+                let block = match blocks.pop() {
+                    Some(block) => block,
+                    None => return Err(Error::Template("END without IF".to_string())),
+                };
+                let conditional = Box::new(Conditional {
+                    condition: block.condition,
+                    then: block.then,
+                    other: block.other.unwrap_or_default(),
+                });
+                push(&mut gs, &mut blocks, conditional);
+            } else {
+                let (def, field) = self.parse_field(inner)?;
+                if let Some(def) = def {
+                    if !fields.iter().any(|f| f.name == def.name) {
+                        fields.push(def);
+                    }
+                }
+                push(&mut gs, &mut blocks, field);
+            }
 
             start = outer.end();
         }
 
         // Text
         if template.len() > start {
-            gs.push(self.parse_text(&template[start..]))
+            push(&mut gs, &mut blocks, self.parse_text(&template[start..]));
         }
 
-        Ok(Box::new(Composite { generusts: gs }))
+        if !blocks.is_empty() {
+            return Err(Error::Template("IF without END".to_string()));
+        }
+
+        Ok(Composite {
+            fields,
+            generusts: gs,
+        })
     }
 }
 
@@ -657,23 +1650,28 @@ mod test {
     use chrono::{DateTime, NaiveDate};
     use uuid::Uuid;
 
-    use crate::generust::{Generust, Parser};
+    use crate::escape::Escape;
+    use crate::generust::{truncate, Context, FieldDef, Generust, Kind, Parser, Value};
     use test::Bencher;
 
     fn parser() -> Parser {
-        Parser::new("\\$", ",").unwrap()
+        Parser::new("\\$", ",", Escape::None).unwrap()
     }
 
     fn parse(name: &str) -> Box<dyn Generust> {
-        parser().parse_macro(name).unwrap()
+        parser().parse_macro(name).unwrap().1
     }
 
     fn generate(g: &mut Box<dyn Generust>, i: i32) -> String {
         let mut buf = Vec::with_capacity(512);
-        assert!(g.generate(i, &mut buf).is_ok());
+        assert!(g.generate(&mut Context::new(i), &mut buf).is_ok());
         String::from_utf8(buf).expect("invalid utf8")
     }
 
+    fn template(text: &str) -> Box<dyn Generust> {
+        Box::new(parser().parse(text).unwrap())
+    }
+
     type Probe = fn(i: i32, s: &str);
 
     fn roll(mut g: &mut Box<dyn Generust>, f: Probe) {
@@ -694,8 +1692,11 @@ mod test {
     fn test_row_num() {
         let mut g = parse("ROW_NUM");
         roll(&mut g, |i, s| {
-            assert_eq!(i, s.parse().unwrap());
+            assert_eq!(i, s.parse::<i32>().unwrap());
         });
+        let mut g = parse("ROW_NUM(1,2)");
+        assert_eq!("2", generate(&mut g, 1));
+        assert!(g.generate(&mut Context::new(2), &mut vec![]).is_err());
     }
 
     #[test]
@@ -708,6 +1709,9 @@ mod test {
     fn test_int_rnd() {
         let mut g = parse("INT_RND(3, 17)");
         roll(&mut g, |_, s| assert!(s.parse::<i32>().is_ok()));
+        let mut g = parse("INT_RND(-5000000000, -4999999999)");
+        roll(&mut g, |_, s| assert_eq!("-5000000000", s));
+        assert!(parser().parse_macro("INT_RND(5, 5)").is_err());
     }
 
     #[test]
@@ -750,6 +1754,28 @@ mod test {
         });
     }
 
+    #[test]
+    fn test_timestamp_seq() {
+        let mut g = parse("TIMESTAMP_SEQ(2020-01-01T00:00:00Z,90s)");
+        assert_eq!("2020-01-01T00:00:00+00:00", generate(&mut g, 0));
+        assert_eq!("2020-01-01T00:03:00+00:00", generate(&mut g, 2));
+        let mut g = parse("TIMESTAMP_SEQ(2020-01-01T00:00:00.5+02:00,-250ms)");
+        assert_eq!("2019-12-31T22:00:00.250+00:00", generate(&mut g, 1));
+        let mut g = parse("TIMESTAMP_SEQ");
+        roll(&mut g, |_, s| {
+            assert!(DateTime::parse_from_rfc3339(s).is_ok());
+        });
+        assert!(parser()
+            .parse("${TIMESTAMP_SEQ(2020-01-01T00:00:00Z,1w)}")
+            .is_err());
+        assert!(parser().parse("${TIMESTAMP_SEQ(2020-01-01)}").is_err());
+        assert!(parser()
+            .parse("${TIMESTAMP_SEQ(2020-01-01T00:00:00Z,9223372036854775807d)}")
+            .is_err());
+        let mut g = parse("TIMESTAMP_SEQ(2020-01-01T00:00:00Z,100000000d)");
+        assert!(g.generate(&mut Context::new(1), &mut vec![]).is_err());
+    }
+
     #[test]
     fn test_enum_seq() {
         let mut g = parse("ENUM_SEQ(1,2,3)");
@@ -790,6 +1816,57 @@ mod test {
         });
     }
 
+    #[test]
+    fn test_float_rnd() {
+        let mut g = parse("FLOAT_RND(-1.5, 2.5)");
+        roll(&mut g, |_, s| {
+            let f = s.parse::<f64>().unwrap();
+            assert!((-1.5..2.5).contains(&f));
+        });
+    }
+
+    #[test]
+    fn test_decimal_rnd() {
+        let mut g = parse("DECIMAL_RND(-1.5, 2.25)");
+        roll(&mut g, |_, s| {
+            assert_eq!(2, s.len() - s.find('.').unwrap() - 1);
+            let f = s.parse::<f64>().unwrap();
+            assert!((-1.5..=2.25).contains(&f));
+        });
+        let mut g = parse("DECIMAL_RND(0, 1, 0)");
+        roll(&mut g, |_, s| assert!(s == "0" || s == "1"));
+        assert_eq!("-0.05", Value::Decimal(-5, 2).to_string());
+        assert_eq!("12.340", Value::Decimal(12340, 3).to_string());
+        assert_eq!(
+            Some(Value::Decimal(-5, 2)),
+            Value::parse(Kind::Decimal, "-0.05")
+        );
+        assert_eq!(None, Value::parse(Kind::Decimal, "1e3"));
+        assert_eq!(Some(-500), Value::Decimal(-5, 2).as_units(4));
+        assert_eq!(Some(12), Value::Decimal(1200, 2).as_units(0));
+        assert_eq!(None, Value::Decimal(1234, 3).as_units(2));
+    }
+
+    #[test]
+    fn test_str_rnd() {
+        let mut g = parse("STR_RND(3, 5)");
+        roll(&mut g, |_, s| {
+            assert!((3..=5).contains(&s.len()));
+            assert!(s.chars().all(|c| c.is_ascii_alphanumeric()));
+        });
+    }
+
+    #[test]
+    fn test_regex() {
+        let pattern = r"^[A-Z]{2}-\d+(x|yz)?\.[^\n]$";
+        let rx = regex::Regex::new(pattern).unwrap();
+        let mut g = parser().create("REGEX", &[pattern]).unwrap().1;
+        for i in 0..123 {
+            let s = generate(&mut g, i);
+            assert!(rx.is_match(&s), "{}", s);
+        }
+    }
+
     #[test]
     fn test_phone() {
         let mut g = parse("PHONE");
@@ -820,7 +1897,152 @@ mod test {
             .parse("@{UUID4},@{ENUM_SEQ(1,2,3),@{INT_RND(1,10)}")
             .unwrap();
         let mut buf = Vec::with_capacity(128);
-        assert!(g.generate(0, &mut buf).is_ok());
+        assert!(g.generate(&mut Context::new(0), &mut buf).is_ok());
+    }
+
+    #[test]
+    fn test_truncate() {
+        let mut g = truncate(parse("ENUM_SEQ(abc,Zürich,z)"), 2);
+        assert_eq!("ab", generate(&mut g, 0));
+        assert_eq!("Zü", generate(&mut g, 1));
+        assert_eq!("z", generate(&mut g, 2));
+    }
+
+    #[test]
+    fn test_field() {
+        let mut g = template("${id=ROW_NUM(1)}");
+        let mut ctx = Context::new(4);
+        let mut buf = Vec::with_capacity(128);
+        assert!(g.generate(&mut ctx, &mut buf).is_ok());
+        assert_eq!(Some("5"), ctx.field("id"));
+    }
+
+    #[test]
+    fn test_fields() {
+        let g = parser()
+            .parse("${id=ROW_NUM},${IF 0.5}${d=DATE_RND}${ELSE}${d=DATE_SEQ}${END},${ok=BOOLEAN}")
+            .unwrap();
+        let fields = g
+            .fields
+            .iter()
+            .map(|f| (f.name.as_str(), f.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![("id", Kind::Int), ("d", Kind::Date), ("ok", Kind::Bool)],
+            fields
+        );
+    }
+
+    #[test]
+    fn test_values() {
+        let mut g =
+            template("${id=ROW_NUM(1)},${d=DATE_SEQ(2020-01-01,2020-02-01)},${e=ENUM_SEQ(a)}");
+        let mut ctx = Context::new(2);
+        let mut buf = vec![];
+        assert!(g.generate(&mut ctx, &mut buf).is_ok());
+        assert_eq!(b"3,2020-01-03,a".to_vec(), buf);
+        assert_eq!(Some(&Value::Int(3)), ctx.value("id"));
+        assert_eq!(
+            Some(&Value::Date(NaiveDate::from_ymd_opt(2020, 1, 3).unwrap())),
+            ctx.value("d")
+        );
+        assert_eq!(Some(&Value::Str("a".to_string())), ctx.value("e"));
+        assert_eq!(Some("2020-01-03"), ctx.field("d"));
+    }
+
+    #[test]
+    fn test_typed() {
+        let mut g = template("${n=INT_RND(5,6)},${d=DECIMAL_RND(1.5,1.5)},${u=UUID4}");
+        let mut ctx = Context::new(0);
+        let mut buf = vec![];
+        assert!(g.generate(&mut ctx, &mut buf).is_ok());
+        let def = |name: &str, kind| FieldDef {
+            name: name.to_string(),
+            kind,
+            sql_type: None,
+        };
+        assert_eq!(Some(Value::Int(5)), ctx.typed(&def("n", Kind::Int)));
+        assert_eq!(
+            Some(Value::Str("5".to_string())),
+            ctx.typed(&def("n", Kind::String))
+        );
+        assert_eq!(Some(Value::Float(5.0)), ctx.typed(&def("n", Kind::Float)));
+        assert_eq!(
+            Some(Value::Decimal(15, 1)),
+            ctx.typed(&def("d", Kind::Decimal))
+        );
+        assert_eq!(Some(Value::Float(1.5)), ctx.typed(&def("d", Kind::Float)));
+        assert!(matches!(
+            ctx.typed(&def("u", Kind::Uuid)),
+            Some(Value::Uuid(_))
+        ));
+    }
+
+    #[test]
+    fn test_json() {
+        let value = Value::from_json(&serde_json::json!({
+            "a": [1, 1.5, null, true],
+            "b": "x\"y",
+        }));
+        let mut buf = vec![];
+        assert!(value.write_json(&mut buf).is_ok());
+        assert_eq!(br#"{"a":[1,1.5,null,true],"b":"x\"y"}"#.to_vec(), buf);
+        let mut buf = vec![];
+        let values = Value::List(vec![
+            Value::Decimal(105, 1),
+            Value::Date(NaiveDate::from_ymd_opt(2020, 1, 2).unwrap()),
+            Value::Float(f64::NAN),
+        ]);
+        assert!(values.write_json(&mut buf).is_ok());
+        assert_eq!(br#"[10.5,"2020-01-02",null]"#.to_vec(), buf);
+        assert_eq!("", Value::Null.to_string());
+    }
+
+    #[test]
+    fn test_if_row() {
+        let mut g = template("${IF ROW == 0}first${ELSE}${ROW_NUM}${END}");
+        roll(&mut g, |i, s| match i {
+            0 => assert_eq!("first", s),
+            _ => assert_eq!(i.to_string(), s),
+        });
+    }
+
+    #[test]
+    fn test_if_field() {
+        let mut g =
+            template("${n=INT_SEQ(10)}${IF n >= 5} big${END}${IF n != 3}${ELSE} three${END}");
+        roll(&mut g, |i, s| match i % 10 {
+            3 => assert_eq!("3 three", s),
+            n if n >= 5 => assert_eq!(format!("{} big", n), s),
+            n => assert_eq!(n.to_string(), s),
+        });
+    }
+
+    #[test]
+    fn test_if_chance() {
+        let mut g = template("${IF 0}never${END}${IF 1.0}always${END}");
+        roll(&mut g, |_, s| assert_eq!("always", s));
+    }
+
+    #[test]
+    fn test_escape() {
+        let mut g: Box<dyn Generust> = Box::new(
+            Parser::new("\\$", ",", Escape::Csv)
+                .unwrap()
+                .parse("${e=ENUM_SEQ(a;b,c\"d)},\"${IF e == c\"d}quoted${END}")
+                .unwrap(),
+        );
+        assert_eq!("a;b,\"", generate(&mut g, 0));
+        assert_eq!("\"c\"\"d\",\"quoted", generate(&mut g, 1));
+    }
+
+    #[test]
+    fn test_if_unbalanced() {
+        assert!(parser().parse("${IF ROW == 0}").is_err());
+        assert!(parser().parse("${END}").is_err());
+        assert!(parser().parse("${ELSE}").is_err());
+        assert!(parser().parse("${IF 0.5}${ELSE}${ELSE}${END}").is_err());
+        assert!(parser().parse("${IF 2}${END}").is_err());
     }
 
     #[bench]
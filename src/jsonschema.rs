@@ -0,0 +1,583 @@
+use std::io::Write;
+use std::path::Path;
+
+use rand::Rng;
+use serde_json::{Map, Value};
+
+use crate::escape::Escape;
+use crate::generust::{
+    text, truncate, Composite, Context, Error, Generust, Parser, Result, Value as Typed,
+};
+use crate::schema;
+
+const MAX_DEPTH: usize = 8;
+const MAX_REFS: usize = 32;
+const MAX_TRIES: usize = 100;
+const MAX_RANGE: i64 = 1000;
+const OPTIONAL_CHANCE: f64 = 0.5;
+
+enum Node {
+    Null,
+    Literal(Vec<Typed>),
+    Raw(Box<dyn Generust>),
+    Str(Box<dyn Generust>),
+    Array(Box<Node>, usize, usize),
+    Object(Vec<(String, bool, Node)>),
+    OneOf(Vec<Node>),
+}
+
+impl Node {
+    fn value(&mut self, ctx: &mut Context) -> Result<Typed> {
+        let mut rng = rand::thread_rng();
+        Ok(match self {
+            Node::Null => Typed::Null,
+            Node::Literal(values) => values[rng.gen_range(0, values.len())].clone(),
+            Node::Raw(generust) => generust.value(ctx)?,
+            Node::Str(generust) => match generust.value(ctx)? {
+                v @ Typed::Str(_) | v @ Typed::Date(_) | v @ Typed::Timestamp(_) => v,
+                v @ Typed::Uuid(_) => v,
+                v => Typed::Str(v.to_string()),
+            },
+            Node::Array(item, min, max) => {
+                let len = rng.gen_range(*min, *max + 1);
+                let mut items = Vec::with_capacity(len);
+                for _ in 0..len {
+                    items.push(item.value(ctx)?);
+                }
+                Typed::List(items)
+            }
+            Node::Object(props) => {
+                let mut entries = Vec::with_capacity(props.len());
+                for (key, required, node) in props {
+                    if *required || rng.gen_bool(OPTIONAL_CHANCE) {
+                        entries.push((key.clone(), node.value(ctx)?));
+                    }
+                }
+                Typed::Map(entries)
+            }
+            Node::OneOf(nodes) => {
+                let i = rng.gen_range(0, nodes.len());
+                nodes[i].value(ctx)?
+            }
+        })
+    }
+}
+
+struct Document {
+    root: Node,
+}
+
+impl Generust for Document {
+    fn generate(&mut self, ctx: &mut Context, w: &mut dyn Write) -> Result<()> {
+        self.root.value(ctx)?.write_json(w)
+    }
+
+    fn value(&mut self, ctx: &mut Context) -> Result<Typed> {
+        self.root.value(ctx)
+    }
+}
+
+struct Bounded {
+    generust: Box<dyn Generust>,
+    min: usize,
+    max: usize,
+    path: String,
+    buf: Vec<u8>,
+}
+
+impl Generust for Bounded {
+    fn generate(&mut self, ctx: &mut Context, w: &mut dyn Write) -> Result<()> {
+        for _ in 0..MAX_TRIES {
+            self.buf.clear();
+            self.generust.generate(ctx, &mut self.buf)?;
+            let len = String::from_utf8_lossy(&self.buf).chars().count();
+            if (self.min..=self.max).contains(&len) {
+                return Ok(w.write_all(&self.buf)?);
+            }
+        }
+        invalid(
+            &self.path,
+            &format!(
+                "no value of {} to {} characters is generated",
+                self.min, self.max
+            ),
+        )
+    }
+
+    fn value(&mut self, ctx: &mut Context) -> Result<Typed> {
+        text(self, ctx)
+    }
+}
+
+struct Compiler<'a> {
+    root: &'a Value,
+    parser: Parser,
+}
+
+fn invalid<T>(path: &str, msg: &str) -> Result<T> {
+    Err(Error::Schema(format!("{}: {}", path, msg)))
+}
+
+fn kind(schema: &Map<String, Value>) -> &str {
+    match schema.get("type") {
+        Some(Value::String(t)) => t,
+        Some(Value::Array(ts)) => ts
+            .iter()
+            .filter_map(|t| t.as_str())
+            .find(|t| *t != "null")
+            .unwrap_or("null"),
+        _ if schema.contains_key("properties") => "object",
+        _ if schema.contains_key("items") => "array",
+        _ if schema.contains_key("minimum") || schema.contains_key("maximum") => "number",
+        _ => "string",
+    }
+}
+
+fn usize_of(schema: &Map<String, Value>, key: &str) -> Option<usize> {
+    schema.get(key).and_then(|v| v.as_u64()).map(|v| v as usize)
+}
+
+impl<'a> Compiler<'a> {
+    fn node(&self, schema: &Value, path: &str, name: &str, depth: usize) -> Result<Node> {
+        let schema = match schema {
+            Value::Object(schema) => schema,
+            Value::Bool(true) => return self.string(&Map::new(), path, name),
+            Value::Bool(false) => return Ok(Node::Null),
+            _ => return invalid(path, "schema must be an object or a boolean"),
+        };
+        let minimal = depth >= MAX_DEPTH;
+        if let Some(reference) = schema.get("$ref") {
+            if depth >= MAX_REFS {
+                return invalid(
+                    path,
+                    &format!("cyclic $ref {} has no finite value", reference),
+                );
+            }
+            let target = reference
+                .as_str()
+                .and_then(|r| r.strip_prefix('#'))
+                .and_then(|r| self.root.pointer(r));
+            return match target {
+                Some(target) => self.node(target, path, name, depth + 1),
+                None => invalid(path, &format!("unresolved $ref {}", reference)),
+            };
+        }
+        if let Some(value) = schema.get("const") {
+            return Ok(Node::Literal(vec![Typed::from_json(value)]));
+        }
+        if let Some(values) = schema.get("enum") {
+            return match values.as_array() {
+                Some(values) if !values.is_empty() => {
+                    Ok(Node::Literal(values.iter().map(Typed::from_json).collect()))
+                }
+                _ => invalid(path, "enum must be a non empty array"),
+            };
+        }
+        for key in &["oneOf", "anyOf"] {
+            if let Some(variants) = schema.get(*key) {
+                return match variants.as_array() {
+                    Some(variants) if !variants.is_empty() && minimal => {
+                        let mut nodes = vec![];
+                        let mut error = None;
+                        for v in variants {
+                            match self.node(v, path, name, depth) {
+                                Ok(node) => nodes.push(node),
+                                Err(e) => error = Some(e),
+                            }
+                        }
+                        match error {
+                            Some(e) if nodes.is_empty() => Err(e),
+                            _ => Ok(Node::OneOf(nodes)),
+                        }
+                    }
+                    Some(variants) if !variants.is_empty() => Ok(Node::OneOf(
+                        variants
+                            .iter()
+                            .map(|v| self.node(v, path, name, depth))
+                            .collect::<Result<Vec<Node>>>()?,
+                    )),
+                    _ => invalid(path, &format!("{} must be a non empty array", key)),
+                };
+            }
+        }
+        if let Some(parts) = schema.get("allOf") {
+            let merged = self.merge(schema, parts, path, depth)?;
+            return self.node(&Value::Object(merged), path, name, depth + 1);
+        }
+        match kind(schema) {
+            "null" => Ok(Node::Null),
+            "boolean" => Ok(Node::Raw(self.parser.create("BOOLEAN", &[])?.1)),
+            "integer" => self.integer(schema, path),
+            "number" => self.number(schema, path),
+            "string" => self.string(schema, path, name),
+            "array" => {
+                let default = if minimal { 0 } else { 1 };
+                let min = usize_of(schema, "minItems").unwrap_or(default);
+                let max = usize_of(schema, "maxItems").unwrap_or_else(|| min.max(3));
+                if min > max {
+                    return invalid(path, "minItems is greater than maxItems");
+                }
+                let max = if minimal { min } else { max };
+                let item = match schema.get("items") {
+                    _ if max == 0 => Node::Null,
+                    Some(items) => self.node(items, &format!("{}/items", path), name, depth)?,
+                    None => self.string(&Map::new(), path, name)?,
+                };
+                Ok(Node::Array(Box::new(item), min, max))
+            }
+            "object" => {
+                let required = match schema.get("required") {
+                    Some(Value::Array(names)) => names.iter().filter_map(|n| n.as_str()).collect(),
+                    _ => vec![],
+                };
+                let mut props = vec![];
+                if let Some(Value::Object(properties)) = schema.get("properties") {
+                    for (key, prop) in properties {
+                        if minimal && !required.contains(&key.as_str()) {
+                            continue;
+                        }
+                        let path = format!("{}/properties/{}", path, key);
+                        let node = self.node(prop, &path, key, depth)?;
+                        props.push((key.clone(), required.contains(&key.as_str()), node));
+                    }
+                }
+                Ok(Node::Object(props))
+            }
+            other => invalid(path, &format!("unknown type {}", other)),
+        }
+    }
+
+    fn merge(
+        &self,
+        schema: &Map<String, Value>,
+        parts: &Value,
+        path: &str,
+        depth: usize,
+    ) -> Result<Map<String, Value>> {
+        let mut merged = schema.clone();
+        merged.remove("allOf");
+        let parts = match parts.as_array() {
+            Some(parts) => parts,
+            None => return invalid(path, "allOf must be an array"),
+        };
+        for part in parts {
+            let mut part = part;
+            for hop in depth..=MAX_REFS {
+                match part.get("$ref").and_then(|r| r.as_str()) {
+                    Some(r) if hop == MAX_REFS => {
+                        return invalid(path, &format!("cyclic $ref {} has no finite value", r))
+                    }
+                    Some(r) => match r.strip_prefix('#').and_then(|r| self.root.pointer(r)) {
+                        Some(target) => part = target,
+                        None => return invalid(path, &format!("unresolved $ref {}", r)),
+                    },
+                    None => break,
+                }
+            }
+            let part = match part {
+                Value::Object(part) => part,
+                _ => continue,
+            };
+            for (key, value) in part {
+                match (merged.get_mut(key), value) {
+                    (Some(Value::Object(props)), Value::Object(more)) if key == "properties" => {
+                        props.extend(more.clone())
+                    }
+                    (Some(Value::Array(names)), Value::Array(more)) if key == "required" => {
+                        names.extend(more.iter().cloned())
+                    }
+                    _ if key == "$ref" => {}
+                    _ => {
+                        merged.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        Ok(merged)
+    }
+
+    fn integer(&self, schema: &Map<String, Value>, path: &str) -> Result<Node> {
+        let bound = |key: &str, exclusive: &str| match (schema.get(key), schema.get(exclusive)) {
+            (_, Some(Value::Number(n))) => n.as_f64().map(|n| (n, true)),
+            (Some(v), Some(Value::Bool(true))) => v.as_f64().map(|v| (v, true)),
+            (Some(v), _) => v.as_f64().map(|v| (v, false)),
+            (None, _) => None,
+        };
+        let min = bound("minimum", "exclusiveMinimum").map(|(v, exclusive)| {
+            if exclusive {
+                v.floor() as i64 + 1
+            } else {
+                v.ceil() as i64
+            }
+        });
+        let max = bound("maximum", "exclusiveMaximum").map(|(v, exclusive)| {
+            if exclusive {
+                v.ceil() as i64
+            } else {
+                v.floor() as i64 + 1
+            }
+        });
+        let (min, max) = match (min, max) {
+            (Some(min), Some(max)) => (min, max),
+            (Some(min), None) => (min, min.saturating_add(MAX_RANGE)),
+            (None, Some(max)) => (max.saturating_sub(MAX_RANGE), max),
+            (None, None) => (0, MAX_RANGE),
+        };
+        let clamp = |v: i64| v.max(i64::from(i32::MIN)).min(i64::from(i32::MAX));
+        let (min, max) = (clamp(min), clamp(max));
+        if min >= max {
+            return invalid(path, "minimum is greater than maximum");
+        }
+        let args = [min.to_string(), max.to_string()];
+        let args = args.iter().map(|a| a.as_str()).collect::<Vec<&str>>();
+        Ok(Node::Raw(self.parser.create("INT_RND", &args)?.1))
+    }
+
+    fn number(&self, schema: &Map<String, Value>, path: &str) -> Result<Node> {
+        let value = |key: &str, exclusive: &str| {
+            schema
+                .get(key)
+                .or_else(|| schema.get(exclusive).filter(|v| v.is_number()))
+                .and_then(|v| v.as_f64())
+        };
+        let (min, max) = match (
+            value("minimum", "exclusiveMinimum"),
+            value("maximum", "exclusiveMaximum"),
+        ) {
+            (Some(min), Some(max)) => (min, max),
+            (Some(min), None) => (min, min + MAX_RANGE as f64),
+            (None, Some(max)) => (max - MAX_RANGE as f64, max),
+            (None, None) => (0.0, MAX_RANGE as f64),
+        };
+        if min >= max {
+            return invalid(path, "minimum is greater than maximum");
+        }
+        let args = [min.to_string(), max.to_string()];
+        let args = args.iter().map(|a| a.as_str()).collect::<Vec<&str>>();
+        Ok(Node::Raw(self.parser.create("FLOAT_RND", &args)?.1))
+    }
+
+    fn string(&self, schema: &Map<String, Value>, path: &str, name: &str) -> Result<Node> {
+        let min = usize_of(schema, "minLength");
+        let max = usize_of(schema, "maxLength");
+        let format = schema.get("format").and_then(|f| f.as_str());
+        let pattern = schema.get("pattern").and_then(|p| p.as_str());
+        let (template, range) = match format {
+            Some("uuid") => (Some("${UUID4}"), Some((36, Some(36)))),
+            Some("date-time") => (Some("${TIMESTAMP}"), None),
+            Some("date") => (Some("${DATE_RND}"), Some((10, Some(10)))),
+            Some("email") | Some("idn-email") => {
+                (Some("${FIRST_RND}.${LAST_RND}@${DOMAIN_RND}"), None)
+            }
+            Some("ipv4") => (Some("${IPV4}"), Some((7, Some(15)))),
+            Some("hostname") | Some("idn-hostname") => (Some("${DOMAIN_RND}"), None),
+            Some("uri") | Some("url") => (Some("https://${DOMAIN_RND}/"), None),
+            _ => (None, None),
+        };
+        let (generust, range): (Box<dyn Generust>, _) = match (template, pattern) {
+            (Some(template), _) => (Box::new(self.parser.parse(template)?), range),
+            (None, Some(pattern)) => {
+                let range = regex_syntax::parse(pattern).ok().map(|hir| {
+                    let props = hir.properties();
+                    (props.minimum_len().unwrap_or(0), props.maximum_len())
+                });
+                (self.parser.create("REGEX", &[pattern])?.1, range)
+            }
+            (None, None) => {
+                let generust = match schema::guess(name) {
+                    Some(generator) if min.is_none() => {
+                        let generust = self.parser.create(generator, &[])?.1;
+                        match max {
+                            Some(max) => truncate(generust, max),
+                            None => generust,
+                        }
+                    }
+                    _ => {
+                        let min = min.unwrap_or_else(|| max.unwrap_or(8).min(8));
+                        let max = max.unwrap_or_else(|| min.max(16));
+                        if min > max {
+                            return invalid(path, "minLength is greater than maxLength");
+                        }
+                        let args = [min.to_string(), max.to_string()];
+                        let args = args.iter().map(|a| a.as_str()).collect::<Vec<&str>>();
+                        self.parser.create("STR_RND", &args)?.1
+                    }
+                };
+                return Ok(Node::Str(generust));
+            }
+        };
+        if min.is_none() && max.is_none() {
+            return Ok(Node::Str(generust));
+        }
+        let (min, max) = (min.unwrap_or(0), max.unwrap_or(usize::MAX));
+        if let Some((lo, hi)) = range {
+            if min > hi.unwrap_or(usize::MAX) || max < lo || min > max {
+                return invalid(
+                    path,
+                    "minLength and maxLength do not fit the format or pattern",
+                );
+            }
+        }
+        Ok(Node::Str(Box::new(Bounded {
+            generust,
+            min,
+            max,
+            path: path.to_string(),
+            buf: Vec::with_capacity(64),
+        })))
+    }
+}
+
+pub fn document(root: &Value, schema: &Value, path: &str) -> Result<Box<dyn Generust>> {
+    let compiler = Compiler {
+        root,
+        parser: Parser::new("\\$", ",", Escape::None)?,
+    };
+    let root = compiler.node(schema, path, "", 0)?;
+    Ok(Box::new(Document { root }))
+}
+
+pub fn compile(schema: &Value) -> Result<Composite> {
+    let mut composite = Composite::default();
+    composite.push(document(schema, schema, "#")?);
+    Ok(composite)
+}
+
+pub fn load(path: &Path) -> Result<Composite> {
+    let text = std::fs::read_to_string(path)?;
+    let schema = serde_json::from_str(&text)
+        .map_err(|e| Error::Schema(format!("{}: {}", path.display(), e)))?;
+    compile(&schema)
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::{json, Value};
+
+    use crate::generust::{Context, Generust};
+    use crate::jsonschema::compile;
+
+    fn generate(schema: Value) -> Vec<Value> {
+        let mut composite = compile(&schema).unwrap();
+        (0..50)
+            .map(|i| {
+                let mut buf = Vec::with_capacity(256);
+                assert!(composite.generate(&mut Context::new(i), &mut buf).is_ok());
+                serde_json::from_slice(&buf).expect("invalid json")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_scalars() {
+        for v in generate(json!({"type": "integer", "minimum": 5, "exclusiveMaximum": 8})) {
+            assert!((5..8).contains(&v.as_i64().unwrap()));
+        }
+        for v in generate(json!({"type": "number", "minimum": -1, "maximum": 1})) {
+            assert!((-1.0..=1.0).contains(&v.as_f64().unwrap()));
+        }
+        for v in generate(json!({"type": "string", "minLength": 2, "maxLength": 4})) {
+            assert!((2..=4).contains(&v.as_str().unwrap().len()));
+        }
+        let rx = regex::Regex::new("^[a-c]{3}-[0-9]$").unwrap();
+        for v in generate(json!({"type": "string", "pattern": "^[a-c]{3}-[0-9]$"})) {
+            assert!(rx.is_match(v.as_str().unwrap()));
+        }
+        for v in generate(json!({"enum": ["a", 1, null]})) {
+            assert!(v == json!("a") || v == json!(1) || v.is_null());
+        }
+        for v in generate(json!({"type": "string", "format": "email"})) {
+            assert!(v.as_str().unwrap().contains('@'));
+        }
+        for v in generate(json!({"type": "string", "format": "email", "maxLength": 24})) {
+            let v = v.as_str().unwrap();
+            assert!(v.contains('@') && v.chars().count() <= 24);
+        }
+        let rx = regex::Regex::new("^[a-c]{2,9}$").unwrap();
+        for v in generate(
+            json!({"type": "string", "pattern": "^[a-c]{2,9}$", "minLength": 4, "maxLength": 5}),
+        ) {
+            let v = v.as_str().unwrap();
+            assert!(rx.is_match(v) && (4..=5).contains(&v.len()));
+        }
+    }
+
+    #[test]
+    fn test_recursive() {
+        let schema = json!({
+            "$ref": "#/$defs/node",
+            "$defs": {
+                "node": {
+                    "type": "object",
+                    "required": ["name", "kids"],
+                    "properties": {
+                        "name": {"type": "string"},
+                        "note": {"type": "string"},
+                        "kids": {"type": "array", "items": {"$ref": "#/$defs/node"}, "maxItems": 1}
+                    }
+                }
+            }
+        });
+        fn check(v: &Value) {
+            assert!(v["name"].is_string());
+            for kid in v["kids"].as_array().unwrap() {
+                check(kid);
+            }
+        }
+        for v in generate(schema) {
+            check(&v);
+        }
+    }
+
+    #[test]
+    fn test_document() {
+        let schema = json!({
+            "type": "object",
+            "required": ["id", "tags", "owner"],
+            "properties": {
+                "id": {"type": "string", "format": "uuid"},
+                "note": {"type": "string"},
+                "tags": {"type": "array", "items": {"type": "boolean"}, "minItems": 1, "maxItems": 2},
+                "owner": {"$ref": "#/$defs/person"}
+            },
+            "$defs": {
+                "person": {
+                    "allOf": [
+                        {"properties": {"first_name": {"type": "string"}}, "required": ["first_name"]},
+                        {"properties": {"born": {"type": "string", "format": "date"}}, "required": ["born"]}
+                    ]
+                }
+            }
+        });
+        let mut notes = 0;
+        for v in generate(schema) {
+            let keys = v.as_object().unwrap().keys().collect::<Vec<_>>();
+            assert!(keys == ["id", "tags", "owner"] || keys == ["id", "note", "tags", "owner"]);
+            notes += keys.len() - 3;
+            assert_eq!(36, v["id"].as_str().unwrap().len());
+            let tags = v["tags"].as_array().unwrap();
+            assert!((1..=2).contains(&tags.len()) && tags.iter().all(|t| t.is_boolean()));
+            assert!(v["owner"]["first_name"].is_string());
+            assert_eq!(10, v["owner"]["born"].as_str().unwrap().len());
+        }
+        assert!(0 < notes && notes < 50);
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(compile(&json!({"$ref": "#/nope"})).is_err());
+        assert!(compile(&json!({"type": "integer", "minimum": 5, "maximum": 1})).is_err());
+        assert!(compile(&json!({"type": "string", "pattern": "("})).is_err());
+        assert!(compile(&json!({"type": "tuple"})).is_err());
+        assert!(compile(&json!({"type": "string", "format": "uuid", "maxLength": 10})).is_err());
+        assert!(compile(&json!({"type": "string", "pattern": "^a{3}$", "minLength": 4})).is_err());
+        assert!(compile(&json!({"$ref": "#"})).is_err());
+        assert!(compile(&json!({"allOf": [{"$ref": "#"}]})).is_err());
+        let cyclic = json!({
+            "type": "object",
+            "required": ["next"],
+            "properties": {"next": {"$ref": "#"}}
+        });
+        assert!(compile(&cyclic).is_err());
+    }
+}
@@ -1,44 +1,315 @@
 #![feature(try_trait)]
 #![feature(test)]
 
+use std::fs::File;
 use std::io::{BufWriter, Read, Write};
 
 use structopt::StructOpt;
 
-use options::Options;
+use options::{Command, Options};
 
-use crate::generust::{Error, Parser, Result};
+use crate::columnar::{Ipc, Parquet};
+use crate::generust::{Composite, Context, Error, Generust, Parser, Result};
+use crate::output::{Lines, Output, Partitions, Parts, Rotation};
+use crate::schema::Schema;
+use crate::sqlite::Sqlite;
+use crate::writer::{Format, Settings};
+use crate::xlsx::Xlsx;
 
+mod avro;
+mod cbor;
+mod columnar;
+mod config;
+mod ddl;
+mod escape;
 mod generust;
+mod jsonschema;
 mod logger;
+mod metrics;
+mod msgpack;
+mod openapi;
 mod options;
+mod output;
+mod proto;
+mod schema;
+mod sqlite;
+mod writer;
+mod xlsx;
+
+const SQLITE: &str = "sqlite:";
+const PARQUET: &str = "parquet:";
+const ARROW: &str = "arrow:";
+const ARROW_STREAM: &str = "arrow-stream:";
+const XLSX: &str = "xlsx:";
+const SHEET: &str = "Sheet1";
 
 fn quit(code: Option<i32>) {
     std::process::exit(code.unwrap_or(1));
 }
 
-fn run(opts: Options) -> Result<()> {
-    log::debug!("read template");
-    let mut template = String::new();
-    std::io::stdin().lock().read_to_string(&mut template)?;
+fn trim_line_break(text: &str) -> &str {
+    match text.strip_suffix('\n') {
+        Some(text) => text.strip_suffix('\r').unwrap_or(text),
+        None => text,
+    }
+}
 
-    log::debug!("parse template");
-    let parser = Parser::new(&opts.macro_start, &opts.separator_args)?;
-    let mut generust = parser.parse(&template)?;
+fn load(
+    opts: &Options,
+    parser: &Parser,
+    mut table: Option<String>,
+) -> Result<(Composite, Format, i32, Option<String>)> {
+    let mut count = opts.count;
+    let (generust, format) = match (&opts.command, &opts.schema, &opts.json_schema) {
+        (Some(Command::Openapi { spec }), _, _) => {
+            log::debug!("parse openapi specification");
+            let (generust, bodies) = openapi::load(spec)?;
+            count *= bodies as i32;
+            (generust, opts.format.unwrap_or(Format::Text))
+        }
+        (Some(Command::Proto { file, message }), _, _) => {
+            log::debug!("parse proto file");
+            let format = opts.format.unwrap_or(Format::Text);
+            let binary = format == Format::Protobuf;
+            (proto::load(file, message.as_deref(), binary)?, format)
+        }
+        (None, _, Some(path)) => {
+            log::debug!("parse json schema");
+            (jsonschema::load(path)?, opts.format.unwrap_or(Format::Text))
+        }
+        (None, Some(path), None) => {
+            log::debug!("parse schema");
+            let schema = Schema::load(path, table.as_deref())?;
+            table = table.or_else(|| schema.table.clone());
+            (schema.compile(parser)?, opts.format.unwrap_or(Format::Csv))
+        }
+        (None, None, None) => {
+            log::debug!("read template");
+            let mut template = String::new();
+            std::io::stdin().lock().read_to_string(&mut template)?;
 
-    let stdout = std::io::stdout();
-    let output = stdout.lock();
-    let mut buffer = BufWriter::new(output);
+            log::debug!("parse template");
+            let template = trim_line_break(&template);
+            (parser.parse(template)?, opts.format.unwrap_or(Format::Text))
+        }
+    };
+    Ok((generust, format, count, table))
+}
+
+fn generate(generust: &mut Composite, count: i32, output: &mut dyn Output) -> Result<()> {
+    let mut text = Vec::with_capacity(1024);
     let mut p = 0;
-    for i in 0..opts.count {
-        generust.generate(i, &mut buffer)?;
-        let n = 100 * i / opts.count;
+    for i in 0..count {
+        let mut ctx = Context::new(i);
+        text.clear();
+        generust.generate(&mut ctx, &mut text)?;
+        output.write(&ctx, &text)?;
+        let n = 100 * i / count;
         if n > p {
             p = n;
             log::debug!("progress: {}%", p);
         }
     }
-    Ok(buffer.flush()?)
+    Ok(())
+}
+
+fn xlsx(opts: &Options, parser: &Parser, path: &str) -> Result<()> {
+    let tables = match (&opts.command, &opts.schema, &opts.json_schema, &opts.table) {
+        (None, Some(schema), None, None) => Schema::tables(schema)?,
+        _ => vec![],
+    };
+    let tables = if tables.is_empty() {
+        vec![opts.table.clone()]
+    } else {
+        tables.into_iter().map(Some).collect()
+    };
+    let mut xlsx = Xlsx::new(path);
+    for table in tables {
+        let (mut generust, _, count, table) = load(opts, parser, table)?;
+        xlsx.sheet(table.as_deref().unwrap_or(SHEET), &generust.fields, count)?;
+        generate(&mut generust, count, &mut xlsx)?;
+    }
+    xlsx.finish()
+}
+
+fn run(opts: Options) -> Result<()> {
+    let parser = Parser::new(&opts.macro_start, &opts.separator_args, opts.escape)?;
+    let rotation = Rotation {
+        rows: opts.chunk_size.unwrap_or(0),
+        bytes: opts.chunk_bytes.unwrap_or(0),
+    };
+    let partitioned = !opts.partition_by.is_empty();
+    if rotation.rows > 0 || rotation.bytes > 0 || partitioned {
+        match opts.output.as_deref() {
+            Some(target)
+                if [SQLITE, PARQUET, ARROW, ARROW_STREAM, XLSX]
+                    .iter()
+                    .any(|p| target.starts_with(p)) =>
+            {
+                return Err(Error::Template(format!(
+                    "{}: rotation and partitioning are not supported by the output",
+                    target
+                )))
+            }
+            None if partitioned => {
+                return Err(Error::Template(
+                    "partitioning requires --output with the file name".to_string(),
+                ))
+            }
+            None => {
+                return Err(Error::Template(
+                    "rotation requires --output with the {part} placeholder".to_string(),
+                ))
+            }
+            _ => {}
+        }
+    }
+    if let Some(path) = opts.output.as_deref().and_then(|t| t.strip_prefix(XLSX)) {
+        return xlsx(&opts, &parser, path);
+    }
+    let (mut generust, format, count, table) = load(&opts, &parser, opts.table.clone())?;
+
+    if format == Format::Protobuf && !matches!(opts.command, Some(Command::Proto { .. })) {
+        return Err(Error::Template(
+            "protobuf format requires the proto command".to_string(),
+        ));
+    }
+
+    let settings = Settings {
+        table,
+        dialect: opts.dialect,
+        batch_size: opts.batch_size,
+        avro_schema: opts.avro_schema.clone(),
+        compression: opts.compression,
+        block_size: opts.row_group_size,
+        xml_root: opts.xml_root.clone(),
+        xml_record: opts.xml_record.clone(),
+        xml_attributes: opts.xml_attributes.clone(),
+        layout: opts.layout.clone(),
+        overflow: opts.overflow,
+        index: opts.index.clone(),
+        id_field: opts.id_field.clone(),
+        measurement: opts.measurement.clone(),
+        tags: opts.tags.clone(),
+        time_field: opts.time_field.clone(),
+    };
+    for name in &opts.partition_by {
+        if !generust.fields.iter().any(|f| f.name == *name) {
+            return Err(Error::Template(format!(
+                "{}: partition field is not found",
+                name
+            )));
+        }
+    }
+    let fields: Vec<_> = generust
+        .fields
+        .iter()
+        .filter(|f| !opts.partition_by.contains(&f.name))
+        .cloned()
+        .collect();
+    let writer = format.writer(&fields, &settings)?;
+    let mut separator = opts.record_separator;
+    if writer.binary() {
+        if opts.header.is_some() || opts.footer.is_some() || opts.delimiter.is_some() {
+            return Err(Error::Template(format!(
+                "{:?} format does not support header, footer and delimiter",
+                format
+            )));
+        }
+        separator.clear();
+    }
+    if rotation.bytes > 0 && !writer.separated() {
+        return Err(Error::Template(format!(
+            "{:?} format writes the records in blocks and does not support --chunk-bytes",
+            format
+        )));
+    }
+    if !writer.separated() && opts.delimiter.is_some() {
+        return Err(Error::Template(format!(
+            "{:?} format writes the records at the end and does not support delimiter",
+            format
+        )));
+    }
+    let header = opts.header.or_else(|| writer.header());
+    let footer = opts.footer.or_else(|| writer.footer());
+    let parse = |parser: &Parser, text: &Option<String>| match text {
+        Some(text) => parser.parse(text).map(Some),
+        None => Ok(None),
+    };
+
+    let stdout = std::io::stdout();
+    let mut output: Box<dyn Output> = match opts.output.as_deref() {
+        Some(target) if target.starts_with(SQLITE) => Box::new(Sqlite::new(
+            &target[SQLITE.len()..],
+            settings.table.as_deref(),
+            &generust.fields,
+            opts.batch_size,
+        )?),
+        Some(target) if target.starts_with(PARQUET) => Box::new(Parquet::new(
+            &target[PARQUET.len()..],
+            &generust.fields,
+            opts.row_group_size,
+            opts.compression,
+        )?),
+        Some(target) if target.starts_with(ARROW) => Box::new(Ipc::new(
+            &target[ARROW.len()..],
+            &generust.fields,
+            opts.row_group_size,
+            false,
+        )?),
+        Some(target) if target.starts_with(ARROW_STREAM) => Box::new(Ipc::new(
+            &target[ARROW_STREAM.len()..],
+            &generust.fields,
+            opts.row_group_size,
+            true,
+        )?),
+        Some(target) if partitioned => {
+            let delimiter = opts.delimiter.unwrap_or_default();
+            Box::new(Partitions::new(
+                target,
+                opts.partition_by,
+                opts.max_open_files,
+                Box::new(move |path| {
+                    let fields = fields.clone();
+                    let settings = settings.clone();
+                    Parts::new(
+                        path,
+                        rotation,
+                        Box::new(move || format.writer(&fields, &settings)),
+                        parse(&parser, &header)?,
+                        parse(&parser, &footer)?,
+                        delimiter.clone(),
+                        separator.clone(),
+                    )
+                }),
+            )?)
+        }
+        Some(target) if rotation.rows > 0 || rotation.bytes > 0 => Box::new(Parts::new(
+            target,
+            rotation,
+            Box::new(move || format.writer(&fields, &settings)),
+            parse(&parser, &header)?,
+            parse(&parser, &footer)?,
+            opts.delimiter.unwrap_or_default(),
+            separator,
+        )?),
+        target => {
+            let out: Box<dyn Write> = match target {
+                Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+                None => Box::new(BufWriter::new(stdout.lock())),
+            };
+            Box::new(Lines::new(
+                out,
+                writer,
+                parse(&parser, &header)?,
+                parse(&parser, &footer)?,
+                opts.delimiter.unwrap_or_default(),
+                separator,
+            ))
+        }
+    };
+    generate(&mut generust, count, output.as_mut())?;
+    output.finish()
 }
 
 fn main() {
@@ -49,7 +320,11 @@ fn main() {
         Err(e) => panic!("failed to initialize logger: {}", e),
     }
 
-    if atty::is(atty::Stream::Stdin) {
+    if opts.command.is_none()
+        && opts.schema.is_none()
+        && opts.json_schema.is_none()
+        && atty::is(atty::Stream::Stdin)
+    {
         Options::clap()
             .print_help()
             .unwrap_or_else(|err| log::error!("{}", err));
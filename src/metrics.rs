@@ -0,0 +1,437 @@
+use std::collections::HashSet;
+use std::io::Write;
+
+use chrono::NaiveTime;
+
+use crate::generust::{Context, Error, FieldDef, Result, Value};
+use crate::writer::{Settings, Writer};
+
+struct Series {
+    measurement: String,
+    tags: Vec<FieldDef>,
+    fields: Vec<FieldDef>,
+    time: Option<FieldDef>,
+}
+
+impl Series {
+    fn new(fields: Vec<FieldDef>, settings: &Settings) -> Result<Series> {
+        let measurement = match settings.measurement.as_ref().or(settings.table.as_ref()) {
+            Some(measurement) => measurement.clone(),
+            None => {
+                return Err(Error::Template(
+                    "metrics formats require a measurement name".to_string(),
+                ))
+            }
+        };
+        for name in settings.tags.iter().chain(&settings.time_field) {
+            if !fields.iter().any(|f| &f.name == name) {
+                return Err(Error::Template(format!("{}: unknown field", name)));
+            }
+        }
+        let time = fields
+            .iter()
+            .find(|f| settings.time_field.as_ref() == Some(&f.name))
+            .cloned();
+        let (tags, fields): (Vec<FieldDef>, Vec<FieldDef>) = fields
+            .into_iter()
+            .filter(|f| settings.time_field.as_ref() != Some(&f.name))
+            .partition(|f| settings.tags.contains(&f.name));
+        Ok(Series {
+            measurement,
+            tags,
+            fields,
+            time,
+        })
+    }
+
+    fn nanos(&self, ctx: &Context) -> Result<Option<i64>> {
+        let time = match &self.time {
+            Some(time) => time,
+            None => return Ok(None),
+        };
+        match ctx.typed(time) {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::Timestamp(v)) => Ok(v.timestamp_nanos_opt()),
+            Some(Value::Date(v)) => Ok(v.and_time(NaiveTime::MIN).and_utc().timestamp_nanos_opt()),
+            Some(v) => Err(Error::Template(format!(
+                "{}: {} is not a timestamp",
+                time.name, v
+            ))),
+        }
+    }
+}
+
+const NAME: &[char] = &[',', '=', ' '];
+const MEASUREMENT: &[char] = &[',', ' '];
+const STRING: &[char] = &['"', '\\'];
+const LABEL: &[char] = &['"', '\\', '\n'];
+
+fn write_escaped(v: &str, special: &[char], w: &mut dyn Write) -> Result<()> {
+    let mut start = 0;
+    for (i, c) in v.char_indices().filter(|(_, c)| special.contains(c)) {
+        w.write_all(&v.as_bytes()[start..i])?;
+        match c {
+            '\n' => w.write_all(b"\\n")?,
+            c => write!(w, "\\{}", c)?,
+        }
+        start = i + c.len_utf8();
+    }
+    Ok(w.write_all(&v.as_bytes()[start..])?)
+}
+
+pub struct Influx {
+    series: Series,
+}
+
+impl Influx {
+    pub fn new(fields: Vec<FieldDef>, settings: &Settings) -> Result<Influx> {
+        Ok(Influx {
+            series: Series::new(fields, settings)?,
+        })
+    }
+}
+
+impl Writer for Influx {
+    fn write(&mut self, ctx: &Context, _text: &[u8], w: &mut dyn Write) -> Result<()> {
+        let series = &self.series;
+        write_escaped(&series.measurement, MEASUREMENT, w)?;
+        for f in &series.tags {
+            match ctx.field(&f.name) {
+                Some(value) if !value.is_empty() => {
+                    w.write_all(b",")?;
+                    write_escaped(&f.name, NAME, w)?;
+                    w.write_all(b"=")?;
+                    write_escaped(value, NAME, w)?;
+                }
+                _ => {}
+            }
+        }
+        let mut count = 0;
+        for f in &series.fields {
+            let value = match ctx.typed(f) {
+                None | Some(Value::Null) => continue,
+                Some(Value::Float(v)) if !v.is_finite() => continue,
+                Some(value) => value,
+            };
+            w.write_all(if count == 0 { b" " } else { b"," })?;
+            write_escaped(&f.name, NAME, w)?;
+            w.write_all(b"=")?;
+            match value {
+                Value::Int(v) => write!(w, "{}i", v)?,
+                Value::Float(_) | Value::Decimal(..) | Value::Bool(_) => write!(w, "{}", value)?,
+                v => {
+                    w.write_all(b"\"")?;
+                    write_escaped(&v.to_string(), STRING, w)?;
+                    w.write_all(b"\"")?;
+                }
+            }
+            count += 1;
+        }
+        if count == 0 {
+            return Err(Error::Template(format!(
+                "{}: line requires at least one field",
+                series.measurement
+            )));
+        }
+        if let Some(nanos) = series.nanos(ctx)? {
+            write!(w, " {}", nanos)?;
+        }
+        Ok(())
+    }
+}
+
+fn metric_name(name: &str) -> String {
+    name.chars()
+        .enumerate()
+        .map(|(i, c)| match c {
+            'a'..='z' | 'A'..='Z' | '_' | ':' => c,
+            '0'..='9' if i > 0 => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+pub struct Prometheus {
+    series: Series,
+    open_metrics: bool,
+    families: Vec<(String, Vec<u8>)>,
+    labels: Vec<u8>,
+    // The series written without a timestamp, which can have only one sample
+    seen: HashSet<Vec<u8>>,
+}
+
+impl Prometheus {
+    pub fn new(
+        fields: Vec<FieldDef>,
+        settings: &Settings,
+        open_metrics: bool,
+    ) -> Result<Prometheus> {
+        let series = Series::new(fields, settings)?;
+        let families = series
+            .fields
+            .iter()
+            .map(|f| {
+                let name = metric_name(&format!("{}_{}", series.measurement, f.name));
+                (name, vec![])
+            })
+            .collect();
+        Ok(Prometheus {
+            series,
+            open_metrics,
+            families,
+            labels: vec![],
+            seen: HashSet::new(),
+        })
+    }
+}
+
+impl Writer for Prometheus {
+    fn separated(&self) -> bool {
+        false
+    }
+
+    fn write(&mut self, ctx: &Context, _text: &[u8], _w: &mut dyn Write) -> Result<()> {
+        self.labels.clear();
+        for f in &self.series.tags {
+            if let Some(value) = ctx.field(&f.name) {
+                self.labels
+                    .extend_from_slice(if self.labels.is_empty() { b"{" } else { b"," });
+                self.labels
+                    .extend_from_slice(metric_name(&f.name).as_bytes());
+                self.labels.extend_from_slice(b"=\"");
+                write_escaped(value, LABEL, &mut self.labels)?;
+                self.labels.extend_from_slice(b"\"");
+            }
+        }
+        if !self.labels.is_empty() {
+            self.labels.extend_from_slice(b"}");
+        }
+        let nanos = self.series.nanos(ctx)?;
+        for (f, (name, samples)) in self.series.fields.iter().zip(&mut self.families) {
+            let value = match ctx.typed(f) {
+                Some(Value::Bool(v)) => f64::from(u8::from(v)),
+                Some(v) => match v.as_f64() {
+                    Some(v) => v,
+                    None => continue,
+                },
+                None => continue,
+            };
+            if nanos.is_none() {
+                let mut series = name.as_bytes().to_vec();
+                series.extend_from_slice(&self.labels);
+                if self.seen.contains(&series) {
+                    return Err(Error::Template(format!(
+                        "{}: more than one sample of the series requires --time-field",
+                        String::from_utf8_lossy(&series)
+                    )));
+                }
+                self.seen.insert(series);
+            }
+            samples.extend_from_slice(name.as_bytes());
+            samples.extend_from_slice(&self.labels);
+            match value {
+                v if v.is_nan() => samples.extend_from_slice(b" NaN"),
+                v if v.is_infinite() && v > 0.0 => samples.extend_from_slice(b" +Inf"),
+                v if v.is_infinite() => samples.extend_from_slice(b" -Inf"),
+                v => write!(samples, " {}", v)?,
+            }
+            match nanos {
+                Some(nanos) if self.open_metrics => {
+                    let (secs, nanos) = (
+                        nanos.div_euclid(1_000_000_000),
+                        nanos.rem_euclid(1_000_000_000),
+                    );
+                    match nanos {
+                        0 => write!(samples, " {}", secs)?,
+                        _ => write!(
+                            samples,
+                            " {}.{}",
+                            secs,
+                            format!("{:09}", nanos).trim_end_matches('0')
+                        )?,
+                    }
+                }
+                Some(nanos) => write!(samples, " {}", nanos.div_euclid(1_000_000))?,
+                None => {}
+            }
+            samples.push(b'\n');
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, w: &mut dyn Write) -> Result<()> {
+        for (name, samples) in &self.families {
+            if !samples.is_empty() {
+                writeln!(w, "# TYPE {} gauge", name)?;
+                w.write_all(samples)?;
+            }
+        }
+        if self.open_metrics {
+            w.write_all(b"# EOF\n")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{DateTime, TimeZone, Utc};
+
+    use crate::escape::Escape;
+    use crate::generust::{Context, FieldDef, Kind, Parser, Value};
+    use crate::metrics::{Influx, Prometheus};
+    use crate::output::{Lines, Output};
+    use crate::writer::{Settings, Writer};
+
+    fn fields() -> Vec<FieldDef> {
+        vec![
+            FieldDef {
+                name: "host".to_string(),
+                kind: Kind::String,
+                sql_type: None,
+            },
+            FieldDef {
+                name: "time".to_string(),
+                kind: Kind::Timestamp,
+                sql_type: None,
+            },
+            FieldDef {
+                name: "cpu".to_string(),
+                kind: Kind::Float,
+                sql_type: None,
+            },
+            FieldDef {
+                name: "procs".to_string(),
+                kind: Kind::Int,
+                sql_type: None,
+            },
+            FieldDef {
+                name: "up".to_string(),
+                kind: Kind::Bool,
+                sql_type: None,
+            },
+            FieldDef {
+                name: "note".to_string(),
+                kind: Kind::String,
+                sql_type: None,
+            },
+        ]
+    }
+
+    fn settings() -> Settings {
+        Settings {
+            measurement: Some("sys load".to_string()),
+            tags: vec!["host".to_string()],
+            time_field: Some("time".to_string()),
+            ..Settings::default()
+        }
+    }
+
+    fn context(row: i32, host: &str, time: &str) -> Context {
+        let mut ctx = Context::new(row);
+        ctx.set_field("host", host.to_string(), Value::Str(host.to_string()));
+        let ts = DateTime::parse_from_rfc3339(time).unwrap();
+        ctx.set_field(
+            "time",
+            time.to_string(),
+            Value::Timestamp(ts.with_timezone(&Utc)),
+        );
+        ctx.set_field("cpu", "0.5".to_string(), Value::Float(0.5));
+        ctx.set_field("procs", "12".to_string(), Value::Int(12));
+        ctx.set_field("up", "true".to_string(), Value::Bool(true));
+        let note = "say \"hi\"".to_string();
+        ctx.set_field("note", note.clone(), Value::Str(note));
+        ctx
+    }
+
+    #[test]
+    fn test_influx() {
+        let mut writer = Influx::new(fields(), &settings()).unwrap();
+        let mut buf = vec![];
+        let ctx = context(0, "a b,c=d", "2020-01-01T00:00:00.000000001Z");
+        assert!(writer.write(&ctx, b"", &mut buf).is_ok());
+        assert_eq!(
+            "sys\\ load,host=a\\ b\\,c\\=d cpu=0.5,procs=12i,up=true,note=\"say \\\"hi\\\"\" 1577836800000000001",
+            String::from_utf8(buf).unwrap()
+        );
+        let mut ctx = Context::new(0);
+        ctx.set_field("host", String::new(), Value::Str(String::new()));
+        ctx.set_field("procs", "1".to_string(), Value::Int(1));
+        let mut buf = vec![];
+        assert!(writer.write(&ctx, b"", &mut buf).is_ok());
+        assert_eq!("sys\\ load procs=1i", String::from_utf8(buf).unwrap());
+        assert!(writer.write(&Context::new(0), b"", &mut vec![]).is_err());
+        assert!(Influx::new(fields(), &Settings::default()).is_err());
+        let settings = Settings {
+            tags: vec!["nope".to_string()],
+            ..settings()
+        };
+        assert!(Influx::new(fields(), &settings).is_err());
+    }
+
+    #[test]
+    fn test_prometheus() {
+        let mut writer = Prometheus::new(fields(), &settings(), false).unwrap();
+        let mut buf = vec![];
+        for (i, time) in ["2020-01-01T00:00:00Z", "2020-01-01T00:00:01.5Z"]
+            .iter()
+            .enumerate()
+        {
+            let ctx = context(i as i32, "a\"b", time);
+            assert!(writer.write(&ctx, b"", &mut buf).is_ok());
+        }
+        assert!(buf.is_empty());
+        assert!(writer.finish(&mut buf).is_ok());
+        assert_eq!(
+            "# TYPE sys_load_cpu gauge\n\
+             sys_load_cpu{host=\"a\\\"b\"} 0.5 1577836800000\n\
+             sys_load_cpu{host=\"a\\\"b\"} 0.5 1577836801500\n\
+             # TYPE sys_load_procs gauge\n\
+             sys_load_procs{host=\"a\\\"b\"} 12 1577836800000\n\
+             sys_load_procs{host=\"a\\\"b\"} 12 1577836801500\n\
+             # TYPE sys_load_up gauge\n\
+             sys_load_up{host=\"a\\\"b\"} 1 1577836800000\n\
+             sys_load_up{host=\"a\\\"b\"} 1 1577836801500\n",
+            String::from_utf8(buf).unwrap()
+        );
+
+        let parser = Parser::new("\\$", ",", Escape::None).unwrap();
+        let mut buf = vec![];
+        let mut output = Lines::new(
+            &mut buf,
+            Box::new(Prometheus::new(fields(), &settings(), false).unwrap()),
+            Some(parser.parse("# HELP sys_load_procs processes").unwrap()),
+            None,
+            String::new(),
+            "\n".to_string(),
+        );
+        for (i, host) in ["a", "b", "a"].iter().enumerate() {
+            let mut ctx = Context::new(i as i32);
+            ctx.set_field("host", host.to_string(), Value::Str(host.to_string()));
+            ctx.set_field("procs", "12".to_string(), Value::Int(12));
+            assert_eq!(i < 2, output.write(&ctx, b"").is_ok());
+        }
+        assert!(output.finish().is_ok());
+        drop(output);
+        assert_eq!(
+            "# HELP sys_load_procs processes\n\
+             # TYPE sys_load_procs gauge\n\
+             sys_load_procs{host=\"a\"} 12\n\
+             sys_load_procs{host=\"b\"} 12\n",
+            String::from_utf8(buf).unwrap()
+        );
+
+        let mut writer = Prometheus::new(fields(), &settings(), true).unwrap();
+        let mut buf = vec![];
+        let mut ctx = Context::new(0);
+        let time = Utc.timestamp_opt(1_577_836_801, 250_000_000).unwrap();
+        ctx.set_field("time", time.to_rfc3339(), Value::Timestamp(time));
+        ctx.set_field("cpu", "NaN".to_string(), Value::Float(f64::NAN));
+        assert!(writer.write(&ctx, b"", &mut buf).is_ok());
+        assert!(writer.finish(&mut buf).is_ok());
+        assert_eq!(
+            "# TYPE sys_load_cpu gauge\nsys_load_cpu NaN 1577836801.25\n# EOF\n",
+            String::from_utf8(buf).unwrap()
+        );
+    }
+}
@@ -0,0 +1,225 @@
+use std::io::Write;
+
+use crate::generust::{Context, FieldDef, Result, Value};
+use crate::writer::Writer;
+
+const TIMESTAMP: u8 = 0xff;
+
+pub struct MsgPack {
+    fields: Vec<FieldDef>,
+    buf: Vec<u8>,
+}
+
+impl MsgPack {
+    pub fn new(fields: Vec<FieldDef>) -> MsgPack {
+        MsgPack {
+            fields,
+            buf: Vec::with_capacity(256),
+        }
+    }
+}
+
+fn write_len(len: usize, fix: u8, fix_max: usize, codes: [u8; 3], buf: &mut Vec<u8>) {
+    if len < fix_max {
+        buf.push(fix | len as u8);
+    } else if codes[0] != 0 && len <= 0xff {
+        buf.push(codes[0]);
+        buf.push(len as u8);
+    } else if len <= 0xffff {
+        buf.push(codes[1]);
+        buf.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        buf.push(codes[2]);
+        buf.extend_from_slice(&(len as u32).to_be_bytes());
+    }
+}
+
+fn write_str(v: &str, buf: &mut Vec<u8>) {
+    write_len(v.len(), 0xa0, 32, [0xd9, 0xda, 0xdb], buf);
+    buf.extend_from_slice(v.as_bytes());
+}
+
+fn write_int(v: i64, buf: &mut Vec<u8>) {
+    if (-32..128).contains(&v) {
+        buf.push(v as u8);
+    } else if v >= 0 && v <= i64::from(u8::MAX) {
+        buf.extend_from_slice(&[0xcc, v as u8]);
+    } else if v >= 0 && v <= i64::from(u16::MAX) {
+        buf.push(0xcd);
+        buf.extend_from_slice(&(v as u16).to_be_bytes());
+    } else if v >= 0 && v <= i64::from(u32::MAX) {
+        buf.push(0xce);
+        buf.extend_from_slice(&(v as u32).to_be_bytes());
+    } else if v >= 0 {
+        buf.push(0xcf);
+        buf.extend_from_slice(&(v as u64).to_be_bytes());
+    } else if v >= i64::from(i8::MIN) {
+        buf.extend_from_slice(&[0xd0, v as u8]);
+    } else if v >= i64::from(i16::MIN) {
+        buf.push(0xd1);
+        buf.extend_from_slice(&(v as i16).to_be_bytes());
+    } else if v >= i64::from(i32::MIN) {
+        buf.push(0xd2);
+        buf.extend_from_slice(&(v as i32).to_be_bytes());
+    } else {
+        buf.push(0xd3);
+        buf.extend_from_slice(&v.to_be_bytes());
+    }
+}
+
+fn write_value(value: &Value, buf: &mut Vec<u8>) {
+    match value {
+        Value::Null => buf.push(0xc0),
+        Value::Bool(false) => buf.push(0xc2),
+        Value::Bool(true) => buf.push(0xc3),
+        Value::Int(v) => write_int(*v, buf),
+        Value::Float(_) | Value::Decimal(..) => {
+            buf.push(0xcb);
+            buf.extend_from_slice(&value.as_f64().unwrap_or(f64::NAN).to_be_bytes());
+        }
+        Value::Str(v) => write_str(v, buf),
+        Value::Date(_) | Value::Uuid(_) => write_str(&value.to_string(), buf),
+        Value::Timestamp(v) => {
+            let (secs, nanos) = (v.timestamp(), v.timestamp_subsec_nanos());
+            if secs >> 34 != 0 {
+                buf.extend_from_slice(&[0xc7, 12, TIMESTAMP]);
+                buf.extend_from_slice(&nanos.to_be_bytes());
+                buf.extend_from_slice(&secs.to_be_bytes());
+            } else if nanos == 0 && secs <= i64::from(u32::MAX) {
+                buf.extend_from_slice(&[0xd6, TIMESTAMP]);
+                buf.extend_from_slice(&(secs as u32).to_be_bytes());
+            } else {
+                buf.extend_from_slice(&[0xd7, TIMESTAMP]);
+                let v = (u64::from(nanos) << 34) | secs as u64;
+                buf.extend_from_slice(&v.to_be_bytes());
+            }
+        }
+        Value::List(vs) => {
+            write_len(vs.len(), 0x90, 16, [0, 0xdc, 0xdd], buf);
+            for v in vs {
+                write_value(v, buf);
+            }
+        }
+        Value::Map(vs) => {
+            write_len(vs.len(), 0x80, 16, [0, 0xde, 0xdf], buf);
+            for (k, v) in vs {
+                write_str(k, buf);
+                write_value(v, buf);
+            }
+        }
+    }
+}
+
+impl Writer for MsgPack {
+    fn binary(&self) -> bool {
+        true
+    }
+
+    fn write(&mut self, ctx: &Context, _text: &[u8], w: &mut dyn Write) -> Result<()> {
+        self.buf.clear();
+        write_len(self.fields.len(), 0x80, 16, [0, 0xde, 0xdf], &mut self.buf);
+        for f in &self.fields {
+            write_str(&f.name, &mut self.buf);
+            write_value(&ctx.typed(f).unwrap_or(Value::Null), &mut self.buf);
+        }
+        Ok(w.write_all(&self.buf)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{NaiveDate, TimeZone, Utc};
+
+    use crate::generust::{Context, FieldDef, Kind, Value};
+    use crate::msgpack::{write_int, write_str, write_value, MsgPack};
+    use crate::writer::Writer;
+
+    fn encode(value: Value) -> Vec<u8> {
+        let mut buf = vec![];
+        write_value(&value, &mut buf);
+        buf
+    }
+
+    #[test]
+    fn test_int() {
+        let ints = vec![
+            (0, vec![0x00]),
+            (127, vec![0x7f]),
+            (-32, vec![0xe0]),
+            (128, vec![0xcc, 0x80]),
+            (256, vec![0xcd, 0x01, 0x00]),
+            (65536, vec![0xce, 0x00, 0x01, 0x00, 0x00]),
+            (-33, vec![0xd0, 0xdf]),
+            (-129, vec![0xd1, 0xff, 0x7f]),
+            (-32769, vec![0xd2, 0xff, 0xff, 0x7f, 0xff]),
+        ];
+        for (v, expected) in ints {
+            let mut buf = vec![];
+            write_int(v, &mut buf);
+            assert_eq!(expected, buf, "{}", v);
+        }
+        let mut buf = vec![];
+        write_int(1 << 40, &mut buf);
+        assert_eq!(0xcf, buf[0]);
+        assert_eq!(9, buf.len());
+    }
+
+    #[test]
+    fn test_values() {
+        assert_eq!(vec![0xc3], encode(Value::Bool(true)));
+        assert_eq!(
+            vec![0xcb, 0x3f, 0xf8, 0, 0, 0, 0, 0, 0],
+            encode(Value::Float(1.5))
+        );
+        assert_eq!(
+            b"\xaa2020-01-02".to_vec(),
+            encode(Value::Date(NaiveDate::from_ymd_opt(2020, 1, 2).unwrap()))
+        );
+        assert_eq!(
+            vec![0xd6, 0xff, 0, 0, 0, 1],
+            encode(Value::Timestamp(Utc.timestamp_opt(1, 0).unwrap()))
+        );
+        assert_eq!(
+            vec![0xd7, 0xff, 0, 0, 0, 4, 0, 0, 0, 1],
+            encode(Value::Timestamp(Utc.timestamp_opt(1, 1).unwrap()))
+        );
+        assert_eq!(
+            b"\x92\x81\xa1a\xc0\xcb\x3f\xf8\0\0\0\0\0\0".to_vec(),
+            encode(Value::List(vec![
+                Value::Map(vec![("a".to_string(), Value::Null)]),
+                Value::Decimal(15, 1),
+            ]))
+        );
+        let mut buf = vec![];
+        write_str(&"a".repeat(40), &mut buf);
+        assert_eq!(&[0xd9, 40], &buf[..2]);
+    }
+
+    #[test]
+    fn test_msgpack() {
+        let fields = vec![
+            FieldDef {
+                name: "id".to_string(),
+                kind: Kind::Int,
+                sql_type: None,
+            },
+            FieldDef {
+                name: "ok".to_string(),
+                kind: Kind::Bool,
+                sql_type: None,
+            },
+            FieldDef {
+                name: "name".to_string(),
+                kind: Kind::String,
+                sql_type: None,
+            },
+        ];
+        let mut writer = MsgPack::new(fields);
+        let mut ctx = Context::new(0);
+        ctx.set_field("id", "5".to_string(), Value::Int(5));
+        ctx.set_field("ok", "false".to_string(), Value::Bool(false));
+        let mut buf = vec![];
+        assert!(writer.write(&ctx, b"", &mut buf).is_ok());
+        assert_eq!(b"\x83\xa2id\x05\xa2ok\xc2\xa4name\xc0".to_vec(), buf);
+    }
+}
@@ -0,0 +1,274 @@
+use std::io::Write;
+use std::path::Path;
+
+use serde_json::{Map, Value};
+
+use crate::generust::{text, Composite, Context, Error, Generust, Result, Value as Typed};
+use crate::jsonschema;
+
+const METHODS: &[&str] = &[
+    "get", "put", "post", "delete", "options", "head", "patch", "trace",
+];
+
+struct Payload {
+    prefix: String,
+    body: Box<dyn Generust>,
+}
+
+struct Payloads {
+    payloads: Vec<Payload>,
+}
+
+impl Generust for Payloads {
+    fn generate(&mut self, ctx: &mut Context, w: &mut dyn Write) -> Result<()> {
+        let i = ctx.row as usize % self.payloads.len();
+        let payload = &mut self.payloads[i];
+        w.write_all(payload.prefix.as_bytes())?;
+        payload.body.generate(ctx, w)?;
+        Ok(w.write_all(b"}")?)
+    }
+
+    fn value(&mut self, ctx: &mut Context) -> Result<Typed> {
+        text(self, ctx)
+    }
+}
+
+fn escape_pointer(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+fn resolve<'a>(root: &'a Value, value: &'a Value) -> Result<&'a Value> {
+    let mut value = value;
+    let mut seen = vec![];
+    while let Some(reference) = value.get("$ref") {
+        if seen.contains(&reference) {
+            return Err(Error::Schema(format!("cyclic $ref {}", reference)));
+        }
+        seen.push(reference);
+        value = match reference
+            .as_str()
+            .and_then(|r| r.strip_prefix('#'))
+            .and_then(|r| root.pointer(r))
+        {
+            Some(target) => target,
+            None => return Err(Error::Schema(format!("unresolved $ref {}", reference))),
+        };
+    }
+    Ok(value)
+}
+
+fn json_schema<'a>(root: &'a Value, body: &'a Value) -> Result<Option<(String, &'a Value)>> {
+    let content = match resolve(root, body)?.get("content") {
+        Some(Value::Object(content)) => content,
+        _ => return Ok(None),
+    };
+    Ok(content
+        .iter()
+        .find(|(media, _)| media.contains("json"))
+        .and_then(|(media, value)| {
+            value
+                .get("schema")
+                .map(|schema| (format!("/content/{}/schema", escape_pointer(media)), schema))
+        }))
+}
+
+struct Spec<'a> {
+    root: &'a Value,
+    payloads: Vec<Payload>,
+}
+
+impl<'a> Spec<'a> {
+    fn push(&mut self, meta: Map<String, Value>, schema: &Value, path: &str) -> Result<()> {
+        let mut prefix = Value::Object(meta).to_string();
+        prefix.pop();
+        prefix.push_str(",\"body\":");
+        let body = jsonschema::document(self.root, schema, path)?;
+        self.payloads.push(Payload { prefix, body });
+        Ok(())
+    }
+
+    fn operation(&mut self, path: &str, method: &str, op: &Value) -> Result<()> {
+        let pointer = format!("#/paths/{}/{}", escape_pointer(path), method);
+        let id = match op.get("operationId").and_then(|id| id.as_str()) {
+            Some(id) => id.to_string(),
+            None => format!("{} {}", method.to_uppercase(), path),
+        };
+        let meta = |kind: &str| {
+            let mut meta = Map::new();
+            meta.insert("operation".to_string(), Value::from(id.as_str()));
+            meta.insert("method".to_string(), Value::from(method.to_uppercase()));
+            meta.insert("path".to_string(), Value::from(path));
+            meta.insert("kind".to_string(), Value::from(kind));
+            meta
+        };
+        if let Some(body) = op.get("requestBody") {
+            if let Some((suffix, schema)) = json_schema(self.root, body)? {
+                let pointer = format!("{}/requestBody{}", pointer, suffix);
+                self.push(meta("request"), schema, &pointer)?;
+            }
+        }
+        if let Some(Value::Object(responses)) = op.get("responses") {
+            for (status, response) in responses {
+                if let Some((suffix, schema)) = json_schema(self.root, response)? {
+                    let pointer = format!("{}/responses/{}{}", pointer, status, suffix);
+                    let mut meta = meta("response");
+                    meta.insert("status".to_string(), Value::from(status.as_str()));
+                    self.push(meta, schema, &pointer)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn compile(root: &Value) -> Result<(Composite, usize)> {
+    match root.get("openapi").and_then(|v| v.as_str()) {
+        Some(version) if version.starts_with('3') => {}
+        _ => return Err(Error::Schema("only OpenAPI 3 is supported".to_string())),
+    }
+    let mut spec = Spec {
+        root,
+        payloads: vec![],
+    };
+    if let Some(Value::Object(paths)) = root.get("paths") {
+        for (path, item) in paths {
+            let item = resolve(root, item)?;
+            for method in METHODS {
+                if let Some(op) = item.get(*method) {
+                    spec.operation(path, method, op)?;
+                }
+            }
+        }
+    }
+    let count = spec.payloads.len();
+    if count == 0 {
+        return Err(Error::Schema(
+            "no JSON request or response bodies are found".to_string(),
+        ));
+    }
+    let mut composite = Composite::default();
+    composite.push(Box::new(Payloads {
+        payloads: spec.payloads,
+    }));
+    Ok((composite, count))
+}
+
+pub fn load(path: &Path) -> Result<(Composite, usize)> {
+    let text = std::fs::read_to_string(path)?;
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let spec: Value = match ext {
+        "yaml" | "yml" => serde_yaml::from_str(&text).map_err(|e| e.to_string()),
+        _ => serde_json::from_str(&text).map_err(|e| e.to_string()),
+    }
+    .map_err(|e| Error::Schema(format!("{}: {}", path.display(), e)))?;
+    compile(&spec)
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::Value;
+
+    use crate::generust::{Context, Generust};
+    use crate::openapi::compile;
+
+    const SPEC: &str = r##"
+openapi: 3.0.3
+info: {title: users, version: "1"}
+paths:
+  /users:
+    post:
+      operationId: createUser
+      requestBody:
+        $ref: "#/components/requestBodies/User"
+      responses:
+        "201":
+          content:
+            application/json:
+              schema: {$ref: "#/components/schemas/User"}
+        "400":
+          description: invalid user
+  /users/{id}:
+    parameters:
+      - {name: id, in: path}
+    delete:
+      responses:
+        "200":
+          content:
+            application/problem+json:
+              schema:
+                type: object
+                required: [code]
+                properties:
+                  code: {type: integer, minimum: 1, maximum: 1}
+components:
+  requestBodies:
+    User:
+      content:
+        application/json:
+          schema: {$ref: "#/components/schemas/User"}
+  schemas:
+    User:
+      type: object
+      required: [id, email, born]
+      properties:
+        id: {type: string, format: uuid}
+        email: {type: string, format: email}
+        born: {type: string, format: date}
+"##;
+
+    #[test]
+    fn test_compile() {
+        let spec: Value = serde_yaml::from_str(SPEC).unwrap();
+        let (mut composite, count) = compile(&spec).unwrap();
+        assert_eq!(3, count);
+        let lines = (0..6)
+            .map(|i| {
+                let mut buf = Vec::with_capacity(256);
+                assert!(composite.generate(&mut Context::new(i), &mut buf).is_ok());
+                serde_json::from_slice::<Value>(&buf).expect("invalid json")
+            })
+            .collect::<Vec<_>>();
+        let ops = lines
+            .iter()
+            .map(|v| {
+                format!(
+                    "{} {} {} {} {}",
+                    v["operation"].as_str().unwrap(),
+                    v["method"].as_str().unwrap(),
+                    v["path"].as_str().unwrap(),
+                    v["kind"].as_str().unwrap(),
+                    v["status"].as_str().unwrap_or("-"),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "createUser POST /users request -",
+                "createUser POST /users response 201",
+                "DELETE /users/{id} DELETE /users/{id} response 200",
+            ],
+            ops[..3].to_vec()
+        );
+        assert_eq!(ops[..3], ops[3..]);
+        for v in &lines[..2] {
+            assert_eq!(36, v["body"]["id"].as_str().unwrap().len());
+            assert!(v["body"]["email"].as_str().unwrap().contains('@'));
+        }
+        assert_eq!(1, lines[2]["body"]["code"].as_i64().unwrap());
+    }
+
+    #[test]
+    fn test_invalid() {
+        let spec = |text: &str| serde_yaml::from_str::<Value>(text).unwrap();
+        assert!(compile(&spec("swagger: '2.0'")).is_err());
+        assert!(compile(&spec("openapi: 3.1.0\npaths: {}")).is_err());
+        assert!(compile(&spec(
+            "openapi: 3.1.0\npaths: {/a: {get: {requestBody: {$ref: '#/nope'}}}}"
+        ))
+        .is_err());
+        assert!(compile(&spec(
+            "openapi: 3.1.0\npaths: {/a: {get: {responses: {'200': {$ref: '#/components/responses/A'}}}}}\ncomponents: {responses: {A: {$ref: '#/components/responses/A'}}}"
+        ))
+        .is_err());
+    }
+}
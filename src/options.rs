@@ -1,5 +1,11 @@
+use std::path::PathBuf;
+
 use structopt::StructOpt;
 
+use crate::columnar::Compression;
+use crate::escape::Escape;
+use crate::writer::{Dialect, Format, Layout, Overflow};
+
 #[derive(StructOpt, Debug)]
 #[structopt(name = "Generust", author = "devpts00", about = "Data generator tool")]
 pub struct Options {
@@ -29,4 +35,267 @@ pub struct Options {
 
     #[structopt(short, long, default_value = "2", help = "Verbosity level from 0 to 5")]
     pub verbose: u8,
+
+    #[structopt(
+        long,
+        parse(from_str = unescape),
+        help = "Template emitted once before the records, e.g. 'id,name' for CSV or '[' for JSON arrays"
+    )]
+    pub header: Option<String>,
+
+    #[structopt(
+        long,
+        parse(from_str = unescape),
+        help = "Template emitted once after the records, e.g. ']' for JSON arrays"
+    )]
+    pub footer: Option<String>,
+
+    #[structopt(
+        long,
+        parse(from_str = unescape),
+        help = "Text emitted between the records, e.g. ',' for JSON arrays"
+    )]
+    pub delimiter: Option<String>,
+
+    #[structopt(
+        short,
+        long,
+        default_value = r"\n",
+        parse(from_str = unescape),
+        help = r"Text emitted after every record, header and footer, e.g. '\r\n', '\0' or '' for none"
+    )]
+    pub record_separator: String,
+
+    #[structopt(
+        short,
+        long,
+        default_value = "none",
+        possible_values = &["none", "csv", "json", "xml", "sql", "copy"],
+        help = "Escape generated values for the output format, literal text of the template is left as is"
+    )]
+    pub escape: Escape,
+
+    #[structopt(
+        short,
+        long,
+        possible_values = &["text", "csv", "jsonl", "sql", "copy", "copy-binary", "avro", "avro-single", "msgpack", "cbor", "protobuf", "xml", "fixed", "yaml", "toml", "bulk", "influx", "prometheus", "openmetrics"],
+        help = "Output format, 'text' emits the template as is, the others emit the named fields, default is 'text' for templates and 'csv' for schemas"
+    )]
+    pub format: Option<Format>,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Schema file in json, yaml, toml or sql format to generate the records from instead of the template"
+    )]
+    pub schema: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        conflicts_with = "schema",
+        help = "JSON Schema file to generate JSON documents from instead of the template"
+    )]
+    pub json_schema: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Avro schema file (.avsc) of the records for the 'avro' and 'avro-single' formats, inferred from the named fields if not set"
+    )]
+    pub avro_schema: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        default_value = "records",
+        help = "Root element of the 'xml' format"
+    )]
+    pub xml_root: String,
+
+    #[structopt(
+        long,
+        default_value = "record",
+        help = "Element of every record of the 'xml' format"
+    )]
+    pub xml_record: String,
+
+    #[structopt(
+        long,
+        use_delimiter = true,
+        help = "Fields emitted as attributes of the record element rather than child elements by the 'xml' format, e.g. 'id,type'"
+    )]
+    pub xml_attributes: Vec<String>,
+
+    #[structopt(
+        long,
+        use_delimiter = true,
+        help = "Columns of the 'fixed' format as name:width[:left|right[:pad]] with the width in bytes, e.g. 'id:8:right:0,name:20'"
+    )]
+    pub layout: Vec<Layout>,
+
+    #[structopt(
+        long,
+        default_value = "error",
+        possible_values = &["error", "truncate"],
+        help = "What the 'fixed' format does with values wider than their column"
+    )]
+    pub overflow: Overflow,
+
+    #[structopt(long, help = "Index of the 'bulk' format, the table by default")]
+    pub index: Option<String>,
+
+    #[structopt(
+        long,
+        help = "Field to take the document _id from for the 'bulk' format"
+    )]
+    pub id_field: Option<String>,
+
+    #[structopt(
+        long,
+        help = "Number of records per file, the files are named by --output with the {part} placeholder replaced by the file number, e.g. 'out-{part:04}.csv'"
+    )]
+    pub chunk_size: Option<usize>,
+
+    #[structopt(
+        long,
+        help = "Maximum size in bytes of a file, unless a single record is larger, the files are named like with --chunk-size"
+    )]
+    pub chunk_bytes: Option<usize>,
+
+    #[structopt(
+        long,
+        use_delimiter = true,
+        help = "Fields to write the records into 'name=value' directories by under the --output directory, e.g. 'dt,region'"
+    )]
+    pub partition_by: Vec<String>,
+
+    #[structopt(
+        long,
+        default_value = "64",
+        help = "Number of partition files kept open at once, the least recently written one is closed beyond that"
+    )]
+    pub max_open_files: usize,
+
+    #[structopt(
+        long,
+        help = "Measurement of the 'influx' format and metric name prefix of the 'prometheus' and 'openmetrics' formats, the table by default"
+    )]
+    pub measurement: Option<String>,
+
+    #[structopt(
+        long,
+        use_delimiter = true,
+        help = "Fields emitted as tags or labels by the metrics formats, e.g. 'host,region'"
+    )]
+    pub tags: Vec<String>,
+
+    #[structopt(
+        long,
+        help = "Field to take the timestamps of the metrics formats from, e.g. a TIMESTAMP_SEQ field"
+    )]
+    pub time_field: Option<String>,
+
+    #[structopt(
+        long,
+        help = "Table to generate the records for if the sql schema has many CREATE TABLE statements, also the table to insert into for the 'sql' format"
+    )]
+    pub table: Option<String>,
+
+    #[structopt(
+        long,
+        default_value = "postgres",
+        possible_values = &["postgres", "mysql", "sqlite"],
+        help = "SQL dialect to quote identifiers and values with for the 'sql' format"
+    )]
+    pub dialect: Dialect,
+
+    #[structopt(
+        long,
+        default_value = "100",
+        help = "Number of records per INSERT statement for the 'sql' format and per transaction for the sqlite output"
+    )]
+    pub batch_size: usize,
+
+    #[structopt(
+        short,
+        long,
+        help = "File to write the records to instead of stdout, 'sqlite:path.db' to insert them into the --table of a SQLite database, 'parquet:path.parquet' to write a Parquet file, 'arrow:path.arrow' or 'arrow-stream:path.arrows' to write an Arrow IPC file or stream, 'xlsx:path.xlsx' to write an Excel workbook, or a file name pattern with {part} for --chunk-size and --chunk-bytes"
+    )]
+    pub output: Option<String>,
+
+    #[structopt(
+        long,
+        default_value = "100000",
+        help = "Number of records per row group of the Parquet output, per record batch of the Arrow output and per block of the 'avro' format"
+    )]
+    pub row_group_size: usize,
+
+    #[structopt(
+        long,
+        default_value = "snappy",
+        possible_values = &["none", "deflate", "snappy", "gzip", "zstd"],
+        help = "Compression of the Parquet output and codec of the 'avro' format, which supports none, deflate and snappy"
+    )]
+    pub compression: Compression,
+
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum Command {
+    #[structopt(
+        about = "Generate JSON request and response bodies of every operation in an OpenAPI 3 specification, the count is per body"
+    )]
+    Openapi {
+        #[structopt(parse(from_os_str), help = "Specification file in json or yaml format")]
+        spec: PathBuf,
+    },
+    #[structopt(
+        about = "Generate messages of a proto3 file in the JSON mapping, or length-delimited binary messages with the 'protobuf' format"
+    )]
+    Proto {
+        #[structopt(parse(from_os_str), help = "Proto file")]
+        file: PathBuf,
+
+        #[structopt(
+            short,
+            long,
+            help = "Message to generate, full or short name, the first top-level message of the file by default"
+        )]
+        message: Option<String>,
+    },
+}
+
+fn unescape(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => res.push('\n'),
+            Some('r') => res.push('\r'),
+            Some('t') => res.push('\t'),
+            Some('0') => res.push('\0'),
+            Some(c) => res.push(c),
+            None => res.push('\\'),
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use crate::options::unescape;
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!("a\nb", unescape(r"a\nb"));
+        assert_eq!("\r\n\t\0", unescape(r"\r\n\t\0"));
+        assert_eq!("a\\b,", unescape(r"a\\b\,"));
+        assert_eq!("a\\", unescape(r"a\"));
+    }
 }
@@ -0,0 +1,653 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+use crate::generust::{Composite, Context, Error, Generust, Result};
+use crate::writer::Writer;
+
+pub trait Output {
+    fn write(&mut self, ctx: &Context, text: &[u8]) -> Result<()>;
+    fn finish(&mut self) -> Result<()>;
+}
+
+pub struct Lines<W: Write> {
+    out: W,
+    writer: Box<dyn Writer>,
+    header: Option<Composite>,
+    footer: Option<Composite>,
+    delimiter: String,
+    separator: String,
+    count: i32,
+    rendered: Option<Vec<u8>>,
+}
+
+impl<W: Write> Lines<W> {
+    pub fn new(
+        out: W,
+        writer: Box<dyn Writer>,
+        header: Option<Composite>,
+        footer: Option<Composite>,
+        delimiter: String,
+        separator: String,
+    ) -> Lines<W> {
+        Lines {
+            out,
+            writer,
+            header,
+            footer,
+            delimiter,
+            separator,
+            count: 0,
+            rendered: None,
+        }
+    }
+
+    fn rotate(&mut self, out: W, writer: Box<dyn Writer>) {
+        self.out = out;
+        self.writer = writer;
+        self.count = 0;
+        self.rendered = None;
+    }
+
+    // Generates the footer ahead of the records, it is written as is by finish,
+    // and returns its size with the separators that finish writes after the records
+    fn render_footer(&mut self) -> Result<usize> {
+        let mut footer = vec![];
+        if let Some(template) = &mut self.footer {
+            template.generate(&mut Context::new(0), &mut footer)?;
+            footer.extend_from_slice(self.separator.as_bytes());
+        }
+        let mut size = footer.len();
+        if self.writer.separated() {
+            size += self.separator.len();
+        }
+        self.rendered = Some(footer);
+        Ok(size)
+    }
+
+    fn write_header(&mut self) -> Result<()> {
+        if let Some(header) = &mut self.header {
+            header.generate(&mut Context::new(0), &mut self.out)?;
+            self.out.write_all(self.separator.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn write_footer(&mut self) -> Result<()> {
+        self.writer.finish(&mut self.out)?;
+        if self.count > 0 && self.writer.separated() {
+            self.out.write_all(self.separator.as_bytes())?;
+        }
+        if let Some(footer) = self.rendered.take() {
+            self.out.write_all(&footer)?;
+        } else if let Some(footer) = &mut self.footer {
+            footer.generate(&mut Context::new(self.count), &mut self.out)?;
+            self.out.write_all(self.separator.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Output for Lines<W> {
+    fn write(&mut self, ctx: &Context, text: &[u8]) -> Result<()> {
+        if self.count == 0 {
+            self.write_header()?;
+        } else if self.writer.separated() {
+            self.writer.delimit(&mut self.out)?;
+            self.out.write_all(self.delimiter.as_bytes())?;
+            self.out.write_all(self.separator.as_bytes())?;
+        }
+        self.writer.write(ctx, text, &mut self.out)?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.count == 0 {
+            self.write_header()?;
+        }
+        self.write_footer()?;
+        Ok(self.out.flush()?)
+    }
+}
+
+struct Counter {
+    path: String,
+    out: Option<BufWriter<File>>,
+    bytes: usize,
+}
+
+impl Counter {
+    fn create(path: &str) -> Result<Counter> {
+        log::debug!("write part: {}", path);
+        Ok(Counter {
+            path: path.to_string(),
+            out: Some(BufWriter::new(File::create(path)?)),
+            bytes: 0,
+        })
+    }
+
+    fn suspend(&mut self) -> Result<()> {
+        if let Some(mut out) = self.out.take() {
+            out.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for Counter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let out = match &mut self.out {
+            Some(out) => out,
+            None => {
+                let file = OpenOptions::new().append(true).open(&self.path)?;
+                self.out.insert(BufWriter::new(file))
+            }
+        };
+        let n = out.write(buf)?;
+        self.bytes += n;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.out {
+            Some(out) => out.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+pub type Writers = Box<dyn FnMut() -> Result<Box<dyn Writer>>>;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Rotation {
+    pub rows: usize,
+    pub bytes: usize,
+}
+
+pub struct Parts {
+    pattern: Regex,
+    path: String,
+    writers: Writers,
+    lines: Lines<Counter>,
+    rotation: Rotation,
+    part: usize,
+    // Mirrors the writer of the file to measure the next record under --chunk-bytes
+    probe: Option<Box<dyn Writer>>,
+    footer: usize,
+    reserved: usize,
+    record: Vec<u8>,
+}
+
+impl Parts {
+    pub fn new(
+        path: &str,
+        rotation: Rotation,
+        mut writers: Writers,
+        header: Option<Composite>,
+        footer: Option<Composite>,
+        delimiter: String,
+        separator: String,
+    ) -> Result<Parts> {
+        let pattern = Regex::new(r"\{part(?::(0?)(\d+))?\}")?;
+        if (rotation.rows > 0 || rotation.bytes > 0) && !pattern.is_match(path) {
+            return Err(Error::Template(format!(
+                "{}: rotated output requires the {{part}} placeholder",
+                path
+            )));
+        }
+        let out = Counter::create(&part_name(&pattern, path, 0))?;
+        let mut parts = Parts {
+            pattern,
+            path: path.to_string(),
+            lines: Lines::new(out, writers()?, header, footer, delimiter, separator),
+            writers,
+            rotation,
+            part: 0,
+            probe: None,
+            footer: 0,
+            reserved: 0,
+            record: vec![],
+        };
+        parts.start()?;
+        Ok(parts)
+    }
+
+    // With a byte limit the footer of a file is generated when the file is started,
+    // so its size can be reserved for every record
+    fn start(&mut self) -> Result<()> {
+        if self.rotation.bytes > 0 {
+            self.probe = Some((self.writers)()?);
+            self.footer = self.lines.render_footer()?;
+        }
+        Ok(())
+    }
+
+    pub fn suspend(&mut self) -> Result<()> {
+        self.lines.out.suspend()
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.lines.finish()?;
+        self.part += 1;
+        let out = Counter::create(&part_name(&self.pattern, &self.path, self.part))?;
+        self.lines.rotate(out, (self.writers)()?);
+        self.start()
+    }
+
+    // Writes the first record of a file, even if it is larger than the limit, and reserves
+    // the bytes that the writer closes the file with, measured on a writer of their own
+    fn write_first(&mut self, ctx: &Context, text: &[u8]) -> Result<()> {
+        self.lines.write(ctx, text)?;
+        if let Some(probe) = &mut self.probe {
+            probe.write(ctx, text, &mut std::io::sink())?;
+            let mut closing = (self.writers)()?;
+            closing.write(ctx, text, &mut std::io::sink())?;
+            self.record.clear();
+            closing.finish(&mut self.record)?;
+            self.reserved = self.record.len() + self.footer;
+        }
+        Ok(())
+    }
+
+    // Writes the record to the probe, the file gets the same bytes if they fit
+    fn measure(&mut self, ctx: &Context, text: &[u8]) -> Result<usize> {
+        self.record.clear();
+        if let Some(probe) = &mut self.probe {
+            probe.delimit(&mut self.record)?;
+            self.record
+                .extend_from_slice(self.lines.delimiter.as_bytes());
+            self.record
+                .extend_from_slice(self.lines.separator.as_bytes());
+            probe.write(ctx, text, &mut self.record)?;
+        }
+        Ok(self.record.len())
+    }
+}
+
+fn part_name(pattern: &Regex, path: &str, part: usize) -> String {
+    pattern
+        .replace_all(path, |cap: &regex::Captures| {
+            let width = cap.get(2).map_or(0, |m| m.as_str().parse().unwrap_or(0));
+            match cap.get(1).map(|m| m.as_str()) {
+                Some("0") => format!("{:0width$}", part, width = width),
+                _ => format!("{:width$}", part, width = width),
+            }
+        })
+        .into_owned()
+}
+
+impl Output for Parts {
+    fn write(&mut self, ctx: &Context, text: &[u8]) -> Result<()> {
+        let count = self.lines.count as usize;
+        if count > 0 && self.rotation.rows > 0 && count >= self.rotation.rows {
+            self.next()?;
+        }
+        if self.probe.is_none() || self.lines.count == 0 {
+            return self.write_first(ctx, text);
+        }
+        let record = self.measure(ctx, text)?;
+        if self.lines.out.bytes + record + self.reserved > self.rotation.bytes {
+            self.next()?;
+            return self.write_first(ctx, text);
+        }
+        self.lines.write(ctx, text)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.lines.finish()
+    }
+}
+
+const DEFAULT_PARTITION: &str = "__HIVE_DEFAULT_PARTITION__";
+
+fn escape_path(v: &str) -> String {
+    let mut escaped = String::with_capacity(v.len());
+    for c in v.chars() {
+        if c < ' ' || "\"#%'*/:=?\\\x7f{[]^".contains(c) {
+            escaped.push_str(&format!("%{:02X}", c as u32));
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+pub type Files = Box<dyn FnMut(&str) -> Result<Parts>>;
+
+pub struct Partitions {
+    dir: PathBuf,
+    file: String,
+    fields: Vec<String>,
+    files: Files,
+    parts: HashMap<PathBuf, Parts>,
+    open: VecDeque<PathBuf>,
+    max_open: usize,
+}
+
+impl Partitions {
+    pub fn new(
+        path: &str,
+        fields: Vec<String>,
+        max_open: usize,
+        files: Files,
+    ) -> Result<Partitions> {
+        let path = Path::new(path);
+        let file = match path.file_name() {
+            Some(file) => file.to_string_lossy().into_owned(),
+            None => {
+                return Err(Error::Template(format!(
+                    "{}: partitioned output requires a file name",
+                    path.display()
+                )))
+            }
+        };
+        if max_open == 0 {
+            return Err(Error::Template(
+                "partitioned output requires at least one open file".to_string(),
+            ));
+        }
+        Ok(Partitions {
+            dir: path.parent().unwrap_or_else(|| Path::new("")).to_path_buf(),
+            file,
+            fields,
+            files,
+            parts: HashMap::new(),
+            open: VecDeque::new(),
+            max_open,
+        })
+    }
+
+    fn partition(&self, ctx: &Context) -> PathBuf {
+        let mut dir = self.dir.clone();
+        for name in &self.fields {
+            let value = match ctx.field(name) {
+                Some(value) if !value.is_empty() => escape_path(value),
+                _ => DEFAULT_PARTITION.to_string(),
+            };
+            dir.push(format!("{}={}", escape_path(name), value));
+        }
+        dir
+    }
+}
+
+impl Output for Partitions {
+    fn write(&mut self, ctx: &Context, text: &[u8]) -> Result<()> {
+        let dir = self.partition(ctx);
+        match self.open.iter().position(|d| *d == dir) {
+            Some(i) => {
+                if let Some(d) = self.open.remove(i) {
+                    self.open.push_back(d);
+                }
+            }
+            None => {
+                if self.open.len() >= self.max_open {
+                    if let Some(parts) = self.open.pop_front().and_then(|d| self.parts.get_mut(&d))
+                    {
+                        parts.suspend()?;
+                    }
+                }
+                self.open.push_back(dir.clone());
+            }
+        }
+        let parts = match self.parts.entry(dir) {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => {
+                std::fs::create_dir_all(e.key())?;
+                let path = e.key().join(&self.file);
+                log::debug!("write partition: {}", e.key().display());
+                e.insert((self.files)(&path.to_string_lossy())?)
+            }
+        };
+        parts.write(ctx, text)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        for parts in self.parts.values_mut() {
+            parts.finish()?;
+            parts.suspend()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use crate::escape::Escape;
+    use crate::generust::{Context, FieldDef, Kind, Parser};
+    use crate::output::{escape_path, Lines, Output, Partitions, Parts, Rotation};
+    use crate::writer::{Format, Settings};
+
+    fn lines(count: i32, header: Option<&str>, footer: Option<&str>) -> String {
+        let parser = Parser::new("\\$", ",", Escape::None).unwrap();
+        let mut buf = Vec::with_capacity(128);
+        let mut output = Lines::new(
+            &mut buf,
+            Format::Text.writer(&[], &Settings::default()).unwrap(),
+            header.map(|h| parser.parse(h).unwrap()),
+            footer.map(|f| parser.parse(f).unwrap()),
+            ",".to_string(),
+            "\n".to_string(),
+        );
+        for i in 0..count {
+            assert!(output.write(&Context::new(i), b"{}").is_ok());
+        }
+        assert!(output.finish().is_ok());
+        drop(output);
+        String::from_utf8(buf).expect("invalid utf8")
+    }
+
+    #[test]
+    fn test_lines() {
+        assert_eq!("{}\n", lines(1, None, None));
+        assert_eq!("{},\n{},\n{}\n", lines(3, None, None));
+        assert_eq!("[\n{},\n{}\n]\n", lines(2, Some("["), Some("]")));
+        assert_eq!("[\n]\n", lines(0, Some("["), Some("]")));
+        assert_eq!("{}\n2\n", lines(1, None, Some("${ROW_NUM(1)}")));
+    }
+
+    fn parts(count: i32, rotation: Rotation, path: &str) -> Vec<(String, String)> {
+        let dir = std::env::temp_dir().join(format!(
+            "generust-parts-{}-{}-{}",
+            std::process::id(),
+            rotation.rows,
+            rotation.bytes
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let parser = Parser::new("\\$", ",", Escape::None).unwrap();
+        let mut output = Parts::new(
+            dir.join(path).to_str().unwrap(),
+            rotation,
+            Box::new(|| Format::Text.writer(&[], &Settings::default())),
+            Some(parser.parse("[").unwrap()),
+            Some(parser.parse("]${ROW_NUM}").unwrap()),
+            ",".to_string(),
+            "\n".to_string(),
+        )
+        .unwrap();
+        for i in 0..count {
+            assert!(output
+                .write(&Context::new(i), i.to_string().as_bytes())
+                .is_ok());
+        }
+        assert!(output.finish().is_ok());
+        drop(output);
+        read_parts(&dir)
+    }
+
+    fn read_parts(dir: &Path) -> Vec<(String, String)> {
+        let mut files = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|e| {
+                let path = e.unwrap().path();
+                let text = std::fs::read_to_string(&path).unwrap();
+                (
+                    path.file_name().unwrap().to_string_lossy().into_owned(),
+                    text,
+                )
+            })
+            .collect::<Vec<(String, String)>>();
+        files.sort();
+        std::fs::remove_dir_all(dir).unwrap();
+        files
+    }
+
+    fn file(name: &str, text: &str) -> (String, String) {
+        (name.to_string(), text.to_string())
+    }
+
+    #[test]
+    fn test_parts() {
+        let rows = Rotation { rows: 2, bytes: 0 };
+        assert_eq!(
+            vec![
+                file("0.txt", "[\n0,\n1\n]2\n"),
+                file("1.txt", "[\n2,\n3\n]2\n"),
+                file("2.txt", "[\n4\n]1\n"),
+            ],
+            parts(5, rows, "{part}.txt")
+        );
+        let bytes = Rotation { rows: 0, bytes: 10 };
+        assert_eq!(
+            vec![
+                file("out-00.txt", "[\n0,\n1\n]0\n"),
+                file("out-01.txt", "[\n2,\n3\n]0\n"),
+                file("out-02.txt", "[\n4\n]0\n"),
+            ],
+            parts(5, bytes, "out-{part:02}.txt")
+        );
+        let bytes = Rotation { rows: 0, bytes: 9 };
+        let files = parts(3, bytes, "out-{part}.txt");
+        assert_eq!(3, files.len());
+        assert!(files.iter().all(|(_, text)| text.len() <= 9));
+        let bytes = Rotation { rows: 0, bytes: 1 };
+        assert_eq!(
+            vec![file("0.txt", "[\n0\n]0\n"), file("1.txt", "[\n1\n]0\n")],
+            parts(2, bytes, "{part}.txt")
+        );
+        assert_eq!(
+            vec![file("a 0-0.txt", "[\n]0\n")],
+            parts(0, Rotation::default(), "a{part:2}-{part}.txt")
+        );
+        let writers = Box::new(|| Format::Text.writer(&[], &Settings::default()));
+        assert!(Parts::new(
+            "part.txt",
+            Rotation { rows: 1, bytes: 0 },
+            writers,
+            None,
+            None,
+            "".to_string(),
+            "".to_string()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_parts_sql() {
+        let dir = std::env::temp_dir().join(format!("generust-parts-sql-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let parser = Parser::new("\\$", ",", Escape::None).unwrap();
+        let fields = vec![FieldDef {
+            name: "id".to_string(),
+            kind: Kind::Int,
+            sql_type: None,
+        }];
+        let settings = Settings {
+            table: Some("t".to_string()),
+            batch_size: 2,
+            ..Settings::default()
+        };
+        let mut output = Parts::new(
+            dir.join("{part:02}.sql").to_str().unwrap(),
+            Rotation { rows: 0, bytes: 60 },
+            Box::new(move || Format::Sql.writer(&fields, &settings)),
+            None,
+            Some(parser.parse("-- ${STR_RND(1,20)}").unwrap()),
+            "".to_string(),
+            "\n".to_string(),
+        )
+        .unwrap();
+        for i in 0..20 {
+            let mut ctx = Context::new(i);
+            ctx.fields.push(("id".to_string(), i.to_string()));
+            assert!(output.write(&ctx, b"").is_ok());
+        }
+        assert!(output.finish().is_ok());
+        drop(output);
+        let files = read_parts(&dir);
+        assert!(files.len() > 1);
+        let mut ids = vec![];
+        for (_, text) in &files {
+            assert!(text.len() <= 60, "{}", text);
+            let (records, footer) = text.split_at(text.rfind("-- ").unwrap());
+            assert!(records.ends_with(");\n") && footer.ends_with('\n'));
+            for statement in records.split_terminator(";\n") {
+                assert!(statement.starts_with("INSERT INTO "));
+                assert!(statement.matches('(').count() <= 3);
+                ids.extend(
+                    statement
+                        .split('(')
+                        .skip(2)
+                        .map(|v| v.split(')').next().unwrap().to_string()),
+                );
+            }
+        }
+        assert_eq!((0..20).map(|i| i.to_string()).collect::<Vec<_>>(), ids);
+    }
+
+    #[test]
+    fn test_partitions() {
+        assert_eq!("a%2Fb%3Dc%25 d%0A", escape_path("a/b=c% d\n"));
+        let dir = std::env::temp_dir().join(format!("generust-partitions-{}", std::process::id()));
+        let parser = Parser::new("\\$", ",", Escape::None).unwrap();
+        let mut output = Partitions::new(
+            dir.join("part-{part}.txt").to_str().unwrap(),
+            vec!["dt".to_string(), "region".to_string()],
+            1,
+            Box::new(move |path| {
+                Parts::new(
+                    path,
+                    Rotation { rows: 2, bytes: 0 },
+                    Box::new(|| Format::Text.writer(&[], &Settings::default())),
+                    Some(parser.parse("[")?),
+                    None,
+                    ",".to_string(),
+                    "\n".to_string(),
+                )
+            }),
+        )
+        .unwrap();
+        for (i, (dt, region)) in [
+            ("1", "eu"),
+            ("2", "eu"),
+            ("1", "eu"),
+            ("1", ""),
+            ("1", "eu"),
+        ]
+        .iter()
+        .enumerate()
+        {
+            let mut ctx = Context::new(i as i32);
+            ctx.fields.push(("dt".to_string(), dt.to_string()));
+            ctx.fields.push(("region".to_string(), region.to_string()));
+            assert!(output.write(&ctx, i.to_string().as_bytes()).is_ok());
+        }
+        assert!(output.finish().is_ok());
+        let read = |path: &str| std::fs::read_to_string(dir.join(path)).unwrap();
+        assert_eq!("[\n0,\n2\n", read("dt=1/region=eu/part-0.txt"));
+        assert_eq!("[\n4\n", read("dt=1/region=eu/part-1.txt"));
+        assert_eq!("[\n1\n", read("dt=2/region=eu/part-0.txt"));
+        assert_eq!(
+            "[\n3\n",
+            read("dt=1/region=__HIVE_DEFAULT_PARTITION__/part-0.txt")
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(Partitions::new("out.txt", vec![], 0, Box::new(|_| unreachable!())).is_err());
+    }
+}
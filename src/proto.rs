@@ -0,0 +1,1126 @@
+use std::io::Write;
+use std::path::Path;
+
+use rand::Rng;
+
+use crate::escape::Escape;
+use crate::generust::{Composite, Context, Error, Generust, Parser, Result, Value};
+use crate::schema;
+
+const MAX_DEPTH: usize = 4;
+const MAX_ITEMS: usize = 3;
+const OPTIONAL_CHANCE: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Scalar {
+    Double,
+    Float,
+    Int32,
+    Int64,
+    Uint32,
+    Uint64,
+    Sint32,
+    Sint64,
+    Fixed32,
+    Fixed64,
+    Sfixed32,
+    Sfixed64,
+    Bool,
+    String,
+    Bytes,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Option<Scalar> {
+        Some(match name {
+            "double" => Scalar::Double,
+            "float" => Scalar::Float,
+            "int32" => Scalar::Int32,
+            "int64" => Scalar::Int64,
+            "uint32" => Scalar::Uint32,
+            "uint64" => Scalar::Uint64,
+            "sint32" => Scalar::Sint32,
+            "sint64" => Scalar::Sint64,
+            "fixed32" => Scalar::Fixed32,
+            "fixed64" => Scalar::Fixed64,
+            "sfixed32" => Scalar::Sfixed32,
+            "sfixed64" => Scalar::Sfixed64,
+            "bool" => Scalar::Bool,
+            "string" => Scalar::String,
+            "bytes" => Scalar::Bytes,
+            _ => return None,
+        })
+    }
+
+    fn wire_type(self) -> u8 {
+        match self {
+            Scalar::Double | Scalar::Fixed64 | Scalar::Sfixed64 => 1,
+            Scalar::String | Scalar::Bytes => 2,
+            Scalar::Float | Scalar::Fixed32 | Scalar::Sfixed32 => 5,
+            _ => 0,
+        }
+    }
+
+    fn generator(self, name: &str) -> (&'static str, &'static [&'static str]) {
+        match self {
+            Scalar::Double | Scalar::Float => ("FLOAT_RND", &["0", "1000"]),
+            Scalar::Sint32 | Scalar::Sint64 | Scalar::Sfixed32 | Scalar::Sfixed64 => {
+                ("INT_RND", &["-1000", "1000"])
+            }
+            Scalar::Bool => ("BOOLEAN", &[]),
+            Scalar::String => match schema::guess(name) {
+                Some(generator) => (generator, &[]),
+                None => ("STR_RND", &[]),
+            },
+            Scalar::Bytes => ("STR_RND", &[]),
+            _ => ("INT_RND", &["0", "1000"]),
+        }
+    }
+
+    fn is_long(self) -> bool {
+        matches!(
+            self,
+            Scalar::Int64 | Scalar::Uint64 | Scalar::Sint64 | Scalar::Fixed64 | Scalar::Sfixed64
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Type {
+    Scalar(Scalar),
+    Named(String),
+    Enum(usize),
+    Message(usize),
+    Map(Scalar, Box<Type>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Label {
+    Single,
+    Optional,
+    Repeated,
+}
+
+#[derive(Debug)]
+struct FieldDesc {
+    name: String,
+    json_name: String,
+    number: u32,
+    label: Label,
+    ty: Type,
+    packed: bool,
+    oneof: Option<usize>,
+}
+
+#[derive(Debug)]
+struct MessageDesc {
+    name: String,
+    top: bool,
+    fields: Vec<FieldDesc>,
+    oneofs: usize,
+}
+
+#[derive(Debug)]
+struct EnumDesc {
+    name: String,
+    values: Vec<(String, i32)>,
+}
+
+#[derive(Debug, Default)]
+struct Proto {
+    package: String,
+    messages: Vec<MessageDesc>,
+    enums: Vec<EnumDesc>,
+}
+
+fn json_name(name: &str) -> String {
+    let mut res = String::with_capacity(name.len());
+    let mut upper = false;
+    for c in name.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            res.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            res.push(c);
+        }
+    }
+    res
+}
+
+struct Tokens {
+    tokens: Vec<(String, usize)>,
+    pos: usize,
+}
+
+impl Tokens {
+    fn new(text: &str) -> Tokens {
+        let chars = text.chars().collect::<Vec<char>>();
+        let mut tokens = vec![];
+        let (mut i, mut line) = (0, 1);
+        while i < chars.len() {
+            let c = chars[i];
+            if c == '\n' {
+                line += 1;
+                i += 1;
+            } else if c.is_whitespace() {
+                i += 1;
+            } else if c == '/' && chars.get(i + 1) == Some(&'/') {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            } else if c == '/' && chars.get(i + 1) == Some(&'*') {
+                i += 2;
+                while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                    if chars[i] == '\n' {
+                        line += 1;
+                    }
+                    i += 1;
+                }
+                i += 2;
+            } else if c == '"' || c == '\'' {
+                let mut token = String::new();
+                token.push('"');
+                i += 1;
+                while i < chars.len() && chars[i] != c {
+                    if chars[i] == '\\' && i + 1 < chars.len() {
+                        i += 1;
+                    }
+                    token.push(chars[i]);
+                    i += 1;
+                }
+                i += 1;
+                tokens.push((token, line));
+            } else if c.is_alphanumeric() || c == '_' || c == '.' || c == '-' || c == '+' {
+                let start = i;
+                while i < chars.len()
+                    && (chars[i].is_alphanumeric() || matches!(chars[i], '_' | '.' | '-' | '+'))
+                {
+                    i += 1;
+                }
+                tokens.push((chars[start..i].iter().collect(), line));
+            } else {
+                tokens.push((c.to_string(), line));
+                i += 1;
+            }
+        }
+        Tokens { tokens, pos: 0 }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|(t, _)| t.as_str())
+    }
+
+    fn error<T>(&self, msg: &str) -> Result<T> {
+        let (token, line) = match self.tokens.get(self.pos.saturating_sub(1)) {
+            Some((token, line)) => (token.as_str(), *line),
+            None => ("end of file", 0),
+        };
+        Err(Error::Schema(format!(
+            "proto: line {}: {} at '{}'",
+            line, msg, token
+        )))
+    }
+
+    fn next(&mut self) -> Result<String> {
+        match self.tokens.get(self.pos) {
+            Some((token, _)) => {
+                self.pos += 1;
+                Ok(token.clone())
+            }
+            None => self.error("unexpected end of file"),
+        }
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<()> {
+        if self.next()? != expected {
+            return self.error(&format!("expected '{}'", expected));
+        }
+        Ok(())
+    }
+
+    fn skip(&mut self) -> Result<()> {
+        let mut depth = 0;
+        loop {
+            match self.next()?.as_str() {
+                ";" if depth == 0 => return Ok(()),
+                "{" => depth += 1,
+                "}" if depth == 1 => return Ok(()),
+                "}" => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+
+    fn options(&mut self) -> Result<Vec<(String, String)>> {
+        let mut options = vec![];
+        if self.peek() != Some("[") {
+            return Ok(options);
+        }
+        self.next()?;
+        loop {
+            let name = self.next()?;
+            self.expect("=")?;
+            let value = self.next()?;
+            if value == "{" {
+                let mut depth = 1;
+                while depth > 0 {
+                    match self.next()?.as_str() {
+                        "{" => depth += 1,
+                        "}" => depth -= 1,
+                        _ => {}
+                    }
+                }
+            }
+            options.push((name, value));
+            match self.next()?.as_str() {
+                "," => continue,
+                "]" => return Ok(options),
+                _ => return self.error("expected ',' or ']'"),
+            }
+        }
+    }
+}
+
+impl Proto {
+    fn parse(text: &str) -> Result<Proto> {
+        let mut tokens = Tokens::new(text);
+        let mut proto = Proto::default();
+        while let Some(token) = tokens.peek() {
+            match token {
+                "syntax" => {
+                    tokens.next()?;
+                    tokens.expect("=")?;
+                    if tokens.next()? != "\"proto3" {
+                        return tokens.error("only proto3 syntax is supported");
+                    }
+                    tokens.expect(";")?;
+                }
+                "package" => {
+                    tokens.next()?;
+                    proto.package = tokens.next()?;
+                    tokens.expect(";")?;
+                }
+                "message" => {
+                    let scope = proto.package.clone();
+                    proto.message(&mut tokens, &scope, true)?;
+                }
+                "enum" => {
+                    let scope = proto.package.clone();
+                    proto.enumeration(&mut tokens, &scope)?;
+                }
+                ";" => {
+                    tokens.next()?;
+                }
+                _ => tokens.skip()?,
+            }
+        }
+        proto.resolve()?;
+        Ok(proto)
+    }
+
+    fn message(&mut self, tokens: &mut Tokens, scope: &str, top: bool) -> Result<()> {
+        tokens.expect("message")?;
+        let name = scoped(scope, &tokens.next()?);
+        let index = self.messages.len();
+        self.messages.push(MessageDesc {
+            name: name.clone(),
+            top,
+            fields: vec![],
+            oneofs: 0,
+        });
+        tokens.expect("{")?;
+        let mut fields = vec![];
+        let mut oneofs = 0;
+        let mut oneof = None;
+        loop {
+            match tokens.peek() {
+                Some("}") if oneof.is_some() => {
+                    tokens.next()?;
+                    oneof = None;
+                }
+                Some("}") => {
+                    tokens.next()?;
+                    break;
+                }
+                Some("message") => self.message(tokens, &name, false)?,
+                Some("enum") => self.enumeration(tokens, &name)?,
+                Some("oneof") => {
+                    tokens.next()?;
+                    tokens.next()?;
+                    tokens.expect("{")?;
+                    oneof = Some(oneofs);
+                    oneofs += 1;
+                }
+                Some("option") | Some("reserved") | Some("extensions") | Some("extend") => {
+                    tokens.skip()?
+                }
+                Some(";") => {
+                    tokens.next()?;
+                }
+                Some(_) => fields.push(field(tokens, &name, oneof)?),
+                None => return tokens.error("expected '}'"),
+            }
+        }
+        self.messages[index].fields = fields;
+        self.messages[index].oneofs = oneofs;
+        Ok(())
+    }
+
+    fn enumeration(&mut self, tokens: &mut Tokens, scope: &str) -> Result<()> {
+        tokens.expect("enum")?;
+        let name = scoped(scope, &tokens.next()?);
+        tokens.expect("{")?;
+        let mut values = vec![];
+        loop {
+            match tokens.peek() {
+                Some("}") => {
+                    tokens.next()?;
+                    break;
+                }
+                Some("option") | Some("reserved") => tokens.skip()?,
+                Some(";") => {
+                    tokens.next()?;
+                }
+                Some(_) => {
+                    let symbol = tokens.next()?;
+                    tokens.expect("=")?;
+                    let number = match tokens.next()?.parse::<i32>() {
+                        Ok(number) => number,
+                        Err(_) => return tokens.error("invalid enum value"),
+                    };
+                    tokens.options()?;
+                    tokens.expect(";")?;
+                    values.push((symbol, number));
+                }
+                None => return tokens.error("expected '}'"),
+            }
+        }
+        if values.is_empty() {
+            return tokens.error(&format!("enum {} has no values", name));
+        }
+        self.enums.push(EnumDesc { name, values });
+        Ok(())
+    }
+
+    fn lookup(&self, scope: &str, name: &str) -> Option<Type> {
+        let find = |full: &str| {
+            if let Some(i) = self.messages.iter().position(|m| m.name == full) {
+                return Some(Type::Message(i));
+            }
+            self.enums
+                .iter()
+                .position(|e| e.name == full)
+                .map(Type::Enum)
+        };
+        if let Some(name) = name.strip_prefix('.') {
+            return find(name);
+        }
+        let mut scope = scope;
+        loop {
+            if let Some(ty) = find(&scoped(scope, name)) {
+                return Some(ty);
+            }
+            match scope.rfind('.') {
+                Some(i) => scope = &scope[..i],
+                None if !scope.is_empty() => scope = "",
+                None => return None,
+            }
+        }
+    }
+
+    fn resolve(&mut self) -> Result<()> {
+        for m in 0..self.messages.len() {
+            for f in 0..self.messages[m].fields.len() {
+                let scope = self.messages[m].name.clone();
+                let ty = match &self.messages[m].fields[f].ty {
+                    Type::Named(name) => self.resolve_type(&scope, name)?,
+                    Type::Map(key, value) => match value.as_ref() {
+                        Type::Named(name) => {
+                            Type::Map(*key, Box::new(self.resolve_type(&scope, name)?))
+                        }
+                        _ => continue,
+                    },
+                    _ => continue,
+                };
+                self.messages[m].fields[f].ty = ty;
+            }
+        }
+        Ok(())
+    }
+
+    fn resolve_type(&self, scope: &str, name: &str) -> Result<Type> {
+        match self.lookup(scope, name) {
+            Some(ty) => Ok(ty),
+            None => Err(Error::Schema(format!(
+                "proto: {}: unresolved type {}",
+                scope, name
+            ))),
+        }
+    }
+}
+
+fn scoped(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", scope, name)
+    }
+}
+
+fn field(tokens: &mut Tokens, scope: &str, oneof: Option<usize>) -> Result<FieldDesc> {
+    let mut label = Label::Single;
+    let mut token = tokens.next()?;
+    match token.as_str() {
+        "repeated" => label = Label::Repeated,
+        "optional" => label = Label::Optional,
+        "required" => return tokens.error("required fields are not supported by proto3"),
+        _ => {}
+    }
+    if label != Label::Single {
+        token = tokens.next()?;
+    }
+    let ty = if token == "map" {
+        tokens.expect("<")?;
+        let key = match Scalar::parse(&tokens.next()?) {
+            Some(key) if !matches!(key, Scalar::Double | Scalar::Float | Scalar::Bytes) => key,
+            _ => return tokens.error("invalid map key type"),
+        };
+        tokens.expect(",")?;
+        let value = tokens.next()?;
+        tokens.expect(">")?;
+        let value = match Scalar::parse(&value) {
+            Some(scalar) => Type::Scalar(scalar),
+            None => Type::Named(value),
+        };
+        Type::Map(key, Box::new(value))
+    } else {
+        match Scalar::parse(&token) {
+            Some(scalar) => Type::Scalar(scalar),
+            None => Type::Named(token),
+        }
+    };
+    let name = tokens.next()?;
+    tokens.expect("=")?;
+    let number = match tokens.next()?.parse::<u32>() {
+        Ok(number) if number > 0 => number,
+        _ => return tokens.error(&format!("{}.{}: invalid field number", scope, name)),
+    };
+    let options = tokens.options()?;
+    tokens.expect(";")?;
+    let option = |key: &str| options.iter().find(|(k, _)| k == key).map(|(_, v)| v);
+    Ok(FieldDesc {
+        json_name: match option("json_name") {
+            Some(json) => json.trim_start_matches('"').to_string(),
+            None => json_name(&name),
+        },
+        name,
+        number,
+        label,
+        ty,
+        packed: option("packed").map(|v| v != "false").unwrap_or(true),
+        oneof,
+    })
+}
+
+enum Gen {
+    Scalar(Scalar, Box<dyn Generust>),
+    Enum(Vec<String>),
+    Message(MessageGen),
+}
+
+struct FieldGen {
+    name: String,
+    label: Label,
+    key: Option<(Scalar, Box<dyn Generust>)>,
+    gen: Gen,
+}
+
+struct MessageGen {
+    fields: Vec<FieldGen>,
+    oneofs: Vec<Vec<usize>>,
+}
+
+impl Gen {
+    fn value(&mut self, ctx: &mut Context) -> Result<Value> {
+        Ok(match self {
+            Gen::Scalar(Scalar::String, generust) | Gen::Scalar(Scalar::Bytes, generust) => {
+                match generust.value(ctx)? {
+                    v @ Value::Str(_) => v,
+                    v => Value::Str(v.to_string()),
+                }
+            }
+            Gen::Scalar(_, generust) => generust.value(ctx)?,
+            Gen::Enum(symbols) => {
+                let i = rand::thread_rng().gen_range(0, symbols.len());
+                Value::Str(symbols[i].clone())
+            }
+            Gen::Message(message) => message.value(ctx)?,
+        })
+    }
+}
+
+impl MessageGen {
+    fn value(&mut self, ctx: &mut Context) -> Result<Value> {
+        let mut rng = rand::thread_rng();
+        let chosen = self
+            .oneofs
+            .iter()
+            .map(|group| group[rng.gen_range(0, group.len())])
+            .collect::<Vec<usize>>();
+        let mut entries = Vec::with_capacity(self.fields.len());
+        for (i, f) in self.fields.iter_mut().enumerate() {
+            if self.oneofs.iter().any(|group| group.contains(&i)) && !chosen.contains(&i) {
+                continue;
+            }
+            if f.label == Label::Optional && !rng.gen_bool(OPTIONAL_CHANCE) {
+                continue;
+            }
+            let value = match (&mut f.key, f.label) {
+                (Some((_, key)), _) => {
+                    let mut entries = vec![];
+                    for _ in 0..rng.gen_range(1, MAX_ITEMS + 1) {
+                        entries.push((key.value(ctx)?.to_string(), f.gen.value(ctx)?));
+                    }
+                    Value::Map(entries)
+                }
+                (None, Label::Repeated) => {
+                    let mut items = vec![];
+                    for _ in 0..rng.gen_range(1, MAX_ITEMS + 1) {
+                        items.push(f.gen.value(ctx)?);
+                    }
+                    Value::List(items)
+                }
+                (None, _) => f.gen.value(ctx)?,
+            };
+            entries.push((f.name.clone(), value));
+        }
+        Ok(Value::Map(entries))
+    }
+}
+
+struct Compiler<'a> {
+    proto: &'a Proto,
+    parser: Parser,
+}
+
+impl<'a> Compiler<'a> {
+    fn scalar(&self, scalar: Scalar, name: &str) -> Result<Box<dyn Generust>> {
+        let (generator, args) = scalar.generator(name);
+        Ok(self.parser.create(generator, args)?.1)
+    }
+
+    fn gen(&self, ty: &Type, name: &str, depth: usize) -> Result<Option<Gen>> {
+        Ok(Some(match ty {
+            Type::Scalar(scalar) => Gen::Scalar(*scalar, self.scalar(*scalar, name)?),
+            Type::Enum(i) => Gen::Enum(
+                self.proto.enums[*i]
+                    .values
+                    .iter()
+                    .map(|(s, _)| s.clone())
+                    .collect(),
+            ),
+            Type::Message(_) if depth >= MAX_DEPTH => return Ok(None),
+            Type::Message(i) => Gen::Message(self.message(*i, depth + 1)?),
+            Type::Map(_, value) => return self.gen(value, name, depth),
+            Type::Named(name) => {
+                return Err(Error::Schema(format!("proto: unresolved type {}", name)))
+            }
+        }))
+    }
+
+    fn message(&self, index: usize, depth: usize) -> Result<MessageGen> {
+        let desc = &self.proto.messages[index];
+        let mut fields = vec![];
+        let mut oneofs = vec![vec![]; desc.oneofs];
+        for f in &desc.fields {
+            let gen = match self.gen(&f.ty, &f.name, depth)? {
+                Some(gen) => gen,
+                None => continue,
+            };
+            let key = match &f.ty {
+                Type::Map(key, _) => Some((*key, self.scalar(*key, &f.name)?)),
+                _ => None,
+            };
+            if let Some(group) = f.oneof {
+                oneofs[group].push(fields.len());
+            }
+            fields.push(FieldGen {
+                name: f.name.clone(),
+                label: f.label,
+                key,
+                gen,
+            });
+        }
+        oneofs.retain(|group| !group.is_empty());
+        Ok(MessageGen { fields, oneofs })
+    }
+}
+
+fn write_varint(mut v: u64, buf: &mut Vec<u8>) {
+    while v >= 0x80 {
+        buf.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    buf.push(v as u8);
+}
+
+fn write_tag(number: u32, wire_type: u8, buf: &mut Vec<u8>) {
+    write_varint((u64::from(number) << 3) | u64::from(wire_type), buf)
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut res = String::with_capacity(bytes.len() * 4 / 3 + 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                res.push(ALPHABET[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                res.push('=');
+            }
+        }
+    }
+    res
+}
+
+impl Proto {
+    fn find(&self, name: &str) -> Result<usize> {
+        let full = name.trim_start_matches('.');
+        let found = self
+            .messages
+            .iter()
+            .position(|m| m.name == full)
+            .or_else(|| {
+                self.messages
+                    .iter()
+                    .position(|m| m.name == scoped(&self.package, full))
+            })
+            .or_else(|| {
+                self.messages
+                    .iter()
+                    .position(|m| m.name.rsplit('.').next() == Some(full))
+            });
+        match found {
+            Some(i) => Ok(i),
+            None => Err(Error::Schema(format!("proto: unknown message {}", name))),
+        }
+    }
+
+    fn mismatch<T>(&self, f: &FieldDesc, value: &Value) -> Result<T> {
+        Err(Error::Template(format!(
+            "{}: {} does not match {:?}",
+            f.name, value, f.ty
+        )))
+    }
+
+    fn enum_number(&self, index: usize, value: &Value) -> Option<i32> {
+        let symbol = match value {
+            Value::Str(symbol) => symbol,
+            _ => return None,
+        };
+        self.enums[index]
+            .values
+            .iter()
+            .find(|(s, _)| s == symbol)
+            .map(|(_, n)| *n)
+    }
+
+    fn encode_scalar(&self, scalar: Scalar, value: &Value, buf: &mut Vec<u8>) -> bool {
+        match (scalar, value) {
+            (Scalar::Double, v) if v.as_f64().is_some() => {
+                buf.extend_from_slice(&v.as_f64().unwrap_or(0.0).to_le_bytes())
+            }
+            (Scalar::Float, v) if v.as_f64().is_some() => {
+                buf.extend_from_slice(&(v.as_f64().unwrap_or(0.0) as f32).to_le_bytes())
+            }
+            (Scalar::Int32, Value::Int(v)) => write_varint(*v as i32 as i64 as u64, buf),
+            (Scalar::Int64, Value::Int(v)) | (Scalar::Uint64, Value::Int(v)) => {
+                write_varint(*v as u64, buf)
+            }
+            (Scalar::Uint32, Value::Int(v)) => write_varint(u64::from(*v as u32), buf),
+            (Scalar::Sint32, Value::Int(v)) => {
+                let v = *v as i32;
+                write_varint(u64::from(((v << 1) ^ (v >> 31)) as u32), buf)
+            }
+            (Scalar::Sint64, Value::Int(v)) => write_varint(((v << 1) ^ (v >> 63)) as u64, buf),
+            (Scalar::Fixed32, Value::Int(v)) => buf.extend_from_slice(&(*v as u32).to_le_bytes()),
+            (Scalar::Sfixed32, Value::Int(v)) => buf.extend_from_slice(&(*v as i32).to_le_bytes()),
+            (Scalar::Fixed64, Value::Int(v)) | (Scalar::Sfixed64, Value::Int(v)) => {
+                buf.extend_from_slice(&v.to_le_bytes())
+            }
+            (Scalar::Bool, Value::Bool(v)) => buf.push(*v as u8),
+            (Scalar::String, Value::Str(v)) | (Scalar::Bytes, Value::Str(v)) => {
+                write_varint(v.len() as u64, buf);
+                buf.extend_from_slice(v.as_bytes());
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn encode_single(
+        &self,
+        f: &FieldDesc,
+        ty: &Type,
+        number: u32,
+        value: &Value,
+        buf: &mut Vec<u8>,
+    ) -> Result<()> {
+        match (ty, value) {
+            (Type::Scalar(scalar), _) => {
+                write_tag(number, scalar.wire_type(), buf);
+                if !self.encode_scalar(*scalar, value, buf) {
+                    return self.mismatch(f, value);
+                }
+            }
+            (Type::Enum(i), _) => match self.enum_number(*i, value) {
+                Some(n) => {
+                    write_tag(number, 0, buf);
+                    write_varint(n as i64 as u64, buf);
+                }
+                None => return self.mismatch(f, value),
+            },
+            (Type::Message(i), _) => {
+                let mut nested = vec![];
+                self.encode(*i, value, &mut nested)?;
+                write_tag(number, 2, buf);
+                write_varint(nested.len() as u64, buf);
+                buf.extend_from_slice(&nested);
+            }
+            _ => return self.mismatch(f, value),
+        }
+        Ok(())
+    }
+
+    fn encode(&self, index: usize, value: &Value, buf: &mut Vec<u8>) -> Result<()> {
+        let desc = &self.messages[index];
+        let entries = match value {
+            Value::Map(entries) => entries,
+            _ => {
+                return Err(Error::Template(format!(
+                    "{}: {} is not a message",
+                    desc.name, value
+                )))
+            }
+        };
+        for (name, value) in entries {
+            let f = match desc.fields.iter().find(|f| &f.name == name) {
+                Some(f) => f,
+                None => {
+                    return Err(Error::Template(format!(
+                        "{}: unknown field {}",
+                        desc.name, name
+                    )))
+                }
+            };
+            match (&f.ty, f.label, value) {
+                (Type::Map(key, ty), _, Value::Map(entries)) => {
+                    for (k, v) in entries {
+                        let mut entry = vec![];
+                        let key_value = match key {
+                            Scalar::String => Value::Str(k.clone()),
+                            Scalar::Bool => Value::Bool(k == "true"),
+                            _ => Value::Int(k.parse()?),
+                        };
+                        self.encode_single(f, &Type::Scalar(*key), 1, &key_value, &mut entry)?;
+                        self.encode_single(f, ty, 2, v, &mut entry)?;
+                        write_tag(f.number, 2, buf);
+                        write_varint(entry.len() as u64, buf);
+                        buf.extend_from_slice(&entry);
+                    }
+                }
+                (Type::Scalar(scalar), Label::Repeated, Value::List(items))
+                    if f.packed && scalar.wire_type() != 2 =>
+                {
+                    let mut packed = vec![];
+                    for item in items {
+                        if !self.encode_scalar(*scalar, item, &mut packed) {
+                            return self.mismatch(f, item);
+                        }
+                    }
+                    write_tag(f.number, 2, buf);
+                    write_varint(packed.len() as u64, buf);
+                    buf.extend_from_slice(&packed);
+                }
+                (Type::Enum(i), Label::Repeated, Value::List(items)) if f.packed => {
+                    let mut packed = vec![];
+                    for item in items {
+                        match self.enum_number(*i, item) {
+                            Some(n) => write_varint(n as i64 as u64, &mut packed),
+                            None => return self.mismatch(f, item),
+                        }
+                    }
+                    write_tag(f.number, 2, buf);
+                    write_varint(packed.len() as u64, buf);
+                    buf.extend_from_slice(&packed);
+                }
+                (ty, Label::Repeated, Value::List(items)) => {
+                    for item in items {
+                        self.encode_single(f, ty, f.number, item, buf)?;
+                    }
+                }
+                (ty, _, value) => self.encode_single(f, ty, f.number, value, buf)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn json_single(&self, ty: &Type, value: &Value) -> Result<Value> {
+        Ok(match (ty, value) {
+            (Type::Scalar(scalar), Value::Int(v)) if scalar.is_long() => Value::Str(v.to_string()),
+            (Type::Scalar(Scalar::Bytes), Value::Str(v)) => Value::Str(base64(v.as_bytes())),
+            (Type::Scalar(_), Value::Float(v)) if v.is_nan() => Value::Str("NaN".to_string()),
+            (Type::Scalar(_), Value::Float(v)) if v.is_infinite() && *v > 0.0 => {
+                Value::Str("Infinity".to_string())
+            }
+            (Type::Scalar(_), Value::Float(v)) if v.is_infinite() => {
+                Value::Str("-Infinity".to_string())
+            }
+            (Type::Message(i), value) => self.json(*i, value)?,
+            (_, value) => value.clone(),
+        })
+    }
+
+    fn json(&self, index: usize, value: &Value) -> Result<Value> {
+        let desc = &self.messages[index];
+        let entries = match value {
+            Value::Map(entries) => entries,
+            _ => {
+                return Err(Error::Template(format!(
+                    "{}: {} is not a message",
+                    desc.name, value
+                )))
+            }
+        };
+        let mut res = Vec::with_capacity(entries.len());
+        for (name, value) in entries {
+            let f = match desc.fields.iter().find(|f| &f.name == name) {
+                Some(f) => f,
+                None => {
+                    return Err(Error::Template(format!(
+                        "{}: unknown field {}",
+                        desc.name, name
+                    )))
+                }
+            };
+            let value = match (&f.ty, value) {
+                (Type::Map(_, ty), Value::Map(entries)) => Value::Map(
+                    entries
+                        .iter()
+                        .map(|(k, v)| Ok((k.clone(), self.json_single(ty, v)?)))
+                        .collect::<Result<Vec<(String, Value)>>>()?,
+                ),
+                (ty, Value::List(items)) => Value::List(
+                    items
+                        .iter()
+                        .map(|v| self.json_single(ty, v))
+                        .collect::<Result<Vec<Value>>>()?,
+                ),
+                (ty, value) => self.json_single(ty, value)?,
+            };
+            res.push((f.json_name.clone(), value));
+        }
+        Ok(Value::Map(res))
+    }
+}
+
+struct Document {
+    proto: Proto,
+    index: usize,
+    root: MessageGen,
+    binary: bool,
+    buf: Vec<u8>,
+}
+
+impl Generust for Document {
+    fn generate(&mut self, ctx: &mut Context, w: &mut dyn Write) -> Result<()> {
+        let value = self.root.value(ctx)?;
+        if self.binary {
+            self.buf.clear();
+            self.proto.encode(self.index, &value, &mut self.buf)?;
+            let mut len = Vec::with_capacity(10);
+            write_varint(self.buf.len() as u64, &mut len);
+            w.write_all(&len)?;
+            Ok(w.write_all(&self.buf)?)
+        } else {
+            self.proto.json(self.index, &value)?.write_json(w)
+        }
+    }
+
+    fn value(&mut self, ctx: &mut Context) -> Result<Value> {
+        self.root.value(ctx)
+    }
+}
+
+pub fn compile(text: &str, message: Option<&str>, binary: bool) -> Result<Composite> {
+    let proto = Proto::parse(text)?;
+    let index = match message {
+        Some(message) => proto.find(message)?,
+        None => match proto.messages.iter().position(|m| m.top) {
+            Some(i) => i,
+            None => return Err(Error::Schema("proto: no messages are found".to_string())),
+        },
+    };
+    let root = Compiler {
+        proto: &proto,
+        parser: Parser::new("\\$", ",", Escape::None)?,
+    }
+    .message(index, 0)?;
+    let mut composite = Composite::default();
+    composite.push(Box::new(Document {
+        proto,
+        index,
+        root,
+        binary,
+        buf: Vec::with_capacity(256),
+    }));
+    Ok(composite)
+}
+
+pub fn load(path: &Path, message: Option<&str>, binary: bool) -> Result<Composite> {
+    let text = std::fs::read_to_string(path)?;
+    compile(&text, message, binary)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::generust::{Context, Generust, Value};
+    use crate::proto::{base64, compile, Proto};
+
+    const PROTO: &str = r#"
+syntax = "proto3";
+package shop.v1;
+
+import "google/protobuf/any.proto";
+option java_package = "com.example.shop";
+
+/* An order
+   with nested types */
+message Order {
+  enum Status {
+    STATUS_UNSPECIFIED = 0;
+    PAID = 1 [deprecated = true];
+  }
+  message Line {
+    string sku = 1;
+    sint32 delta = 2;
+  }
+  int64 order_id = 1; // id
+  Status status = 2;
+  repeated Line lines = 3;
+  repeated int32 codes = 4;
+  map<string, int32> counts = 5 [json_name = "cnt"];
+  oneof payer {
+    string email = 6;
+    Customer customer = 7;
+  }
+  optional bytes note = 8;
+  reserved 9, 10;
+}
+
+message Customer {
+  string first_name = 1;
+  repeated Customer friends = 2;
+}
+"#;
+
+    #[test]
+    fn test_parse() {
+        let proto = Proto::parse(PROTO).unwrap();
+        let names = proto
+            .messages
+            .iter()
+            .map(|m| m.name.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(
+            vec!["shop.v1.Order", "shop.v1.Order.Line", "shop.v1.Customer"],
+            names
+        );
+        let order = &proto.messages[0];
+        assert_eq!(8, order.fields.len());
+        assert_eq!("orderId", order.fields[0].json_name);
+        assert_eq!("cnt", order.fields[4].json_name);
+        assert_eq!(Some(0), order.fields[6].oneof);
+        assert_eq!(1, order.oneofs);
+        assert_eq!(2, proto.find("Customer").unwrap());
+        assert!(Proto::parse("syntax = \"proto2\";").is_err());
+        assert!(Proto::parse("message A { B b = 1; }").is_err());
+        assert!(Proto::parse("message A { int32 a = 1 }").is_err());
+    }
+
+    #[test]
+    fn test_encode() {
+        let proto = Proto::parse(PROTO).unwrap();
+        let value = Value::Map(vec![
+            ("order_id".to_string(), Value::Int(150)),
+            ("status".to_string(), Value::Str("PAID".to_string())),
+            (
+                "lines".to_string(),
+                Value::List(vec![Value::Map(vec![
+                    ("sku".to_string(), Value::Str("ab".to_string())),
+                    ("delta".to_string(), Value::Int(-1)),
+                ])]),
+            ),
+            (
+                "codes".to_string(),
+                Value::List(vec![Value::Int(3), Value::Int(270)]),
+            ),
+            (
+                "counts".to_string(),
+                Value::Map(vec![("x".to_string(), Value::Int(1))]),
+            ),
+            ("note".to_string(), Value::Str("hi".to_string())),
+        ]);
+        let mut buf = vec![];
+        assert!(proto.encode(0, &value, &mut buf).is_ok());
+        assert_eq!(
+            vec![
+                0x08, 0x96, 0x01, 0x10, 0x01, 0x1a, 0x06, 0x0a, 0x02, b'a', b'b', 0x10, 0x01, 0x22,
+                0x03, 0x03, 0x8e, 0x02, 0x2a, 0x05, 0x0a, 0x01, b'x', 0x10, 0x01, 0x42, 0x02, b'h',
+                b'i'
+            ],
+            buf
+        );
+        let mut buf = vec![];
+        assert!(proto.json(0, &value).unwrap().write_json(&mut buf).is_ok());
+        assert_eq!(
+            r#"{"orderId":"150","status":"PAID","lines":[{"sku":"ab","delta":-1}],"codes":[3,270],"cnt":{"x":1},"note":"aGk="}"#,
+            String::from_utf8(buf).unwrap()
+        );
+        assert_eq!("TWFu", base64(b"Man"));
+        assert_eq!("TWE=", base64(b"Ma"));
+    }
+
+    #[test]
+    fn test_generate() {
+        let mut json = compile(PROTO, None, false).unwrap();
+        let mut binary = compile(PROTO, Some("shop.v1.Customer"), true).unwrap();
+        for i in 0..50 {
+            let mut buf = vec![];
+            assert!(json.generate(&mut Context::new(i), &mut buf).is_ok());
+            let v: serde_json::Value = serde_json::from_slice(&buf).expect("invalid json");
+            assert!(v["orderId"].is_string());
+            assert!(v.get("email").is_some() != v.get("customer").is_some());
+            let mut buf = vec![];
+            assert!(binary.generate(&mut Context::new(i), &mut buf).is_ok());
+            let (mut len, mut shift, mut pos) = (0, 0, 0);
+            while buf[pos] & 0x80 != 0 {
+                len |= (buf[pos] as usize & 0x7f) << shift;
+                shift += 7;
+                pos += 1;
+            }
+            len |= (buf[pos] as usize) << shift;
+            assert_eq!(buf.len(), pos + 1 + len);
+            assert_eq!(0x0a, buf[pos + 1]);
+        }
+        assert!(compile(PROTO, Some("Nope"), false).is_err());
+    }
+}
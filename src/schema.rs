@@ -0,0 +1,322 @@
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+use crate::ddl;
+use crate::generust::{truncate, Composite, Error, FieldDef, Kind, Parser, Result};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Scalar {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+}
+
+impl Display for Scalar {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Scalar::Int(v) => Display::fmt(v, f),
+            Scalar::Float(v) => Display::fmt(v, f),
+            Scalar::Bool(v) => Display::fmt(v, f),
+            Scalar::Str(v) => Display::fmt(v, f),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FieldSchema {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: Option<Kind>,
+    pub generator: Option<String>,
+    #[serde(default)]
+    pub args: Vec<Scalar>,
+    pub min: Option<Scalar>,
+    pub max: Option<Scalar>,
+    #[serde(default)]
+    pub values: Vec<Scalar>,
+    #[serde(default)]
+    pub sequential: bool,
+    #[serde(default)]
+    pub nullable: f64,
+    pub length: Option<usize>,
+    #[serde(skip)]
+    pub sql_type: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Schema {
+    pub table: Option<String>,
+    pub fields: Vec<FieldSchema>,
+}
+
+impl Schema {
+    pub fn load(path: &Path, table: Option<&str>) -> Result<Schema> {
+        let text = std::fs::read_to_string(path)?;
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        match ext {
+            "json" => serde_json::from_str(&text).map_err(|e| Error::Schema(e.to_string())),
+            "yaml" | "yml" => serde_yaml::from_str(&text).map_err(|e| Error::Schema(e.to_string())),
+            "toml" => toml::from_str(&text).map_err(|e| Error::Schema(e.to_string())),
+            "sql" => ddl::parse(&text, table),
+            _ => Err(Error::Schema(format!(
+                "{}: unknown schema format, expected json, yaml, toml or sql",
+                path.display()
+            ))),
+        }
+    }
+
+    pub fn tables(path: &Path) -> Result<Vec<String>> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("sql") => ddl::names(&std::fs::read_to_string(path)?),
+            _ => Ok(vec![]),
+        }
+    }
+
+    pub fn compile(&self, parser: &Parser) -> Result<Composite> {
+        let mut composite = Composite::default();
+        for field in &self.fields {
+            if !(0.0..=1.0).contains(&field.nullable) {
+                return Err(Error::Schema(format!(
+                    "{}: nullable must be from 0 to 1",
+                    field.name
+                )));
+            }
+            let (name, args) = field.generator()?;
+            let args = args.iter().map(|a| a.as_str()).collect::<Vec<&str>>();
+            let (kind, mut generust) = parser.create(&name, &args)?;
+            if let Some(length) = field.length {
+                generust = truncate(generust, length);
+            }
+            let def = FieldDef {
+                name: field.name.clone(),
+                kind: field.kind.unwrap_or(kind),
+                sql_type: field.sql_type.clone(),
+            };
+            composite.push_field(def, generust, 1.0 - field.nullable);
+        }
+        Ok(composite)
+    }
+}
+
+impl FieldSchema {
+    fn generator(&self) -> Result<(String, Vec<String>)> {
+        let strings = |vs: &[Scalar]| vs.iter().map(|v| v.to_string()).collect::<Vec<String>>();
+        if let Some(generator) = &self.generator {
+            return Ok((generator.clone(), strings(&self.args)));
+        }
+        let suffix = if self.sequential { "SEQ" } else { "RND" };
+        let mut range = match (&self.min, &self.max) {
+            (Some(min), Some(max)) => vec![min.clone(), max.clone()],
+            (None, Some(max)) if matches!(self.kind, Some(Kind::Int) | Some(Kind::Float)) => {
+                vec![max.clone()]
+            }
+            (None, None) => vec![],
+            _ => {
+                return Err(Error::Schema(format!(
+                    "{}: both min and max are required",
+                    self.name
+                )))
+            }
+        };
+        if !self.values.is_empty() {
+            return Ok((format!("ENUM_{}", suffix), strings(&self.values)));
+        }
+        if let Some(max) = &self.max {
+            let min = self.min.clone().unwrap_or(Scalar::Int(0));
+            match (order(&min, max), self.kind) {
+                (Some(Ordering::Greater), _) => {
+                    return Err(Error::Schema(format!(
+                        "{}: min {} is above max {}",
+                        self.name, min, max
+                    )))
+                }
+                (Some(Ordering::Equal), Some(Kind::Float)) => {
+                    return Err(Error::Schema(format!(
+                        "{}: min and max of a float field must differ",
+                        self.name
+                    )))
+                }
+                _ => {}
+            }
+        }
+        if self.kind == Some(Kind::Int) {
+            // max is inclusive, the end of INT_RND and INT_SEQ is not
+            if let Some(Scalar::Int(max)) = range.last_mut() {
+                *max = max.checked_add(1).ok_or_else(|| {
+                    Error::Schema(format!("{}: max {} is out of range", self.name, max))
+                })?;
+            }
+        }
+        let range = strings(&range);
+        match self.kind {
+            Some(Kind::Int) => Ok((format!("INT_{}", suffix), range)),
+            Some(Kind::Float) => Ok(("FLOAT_RND".to_string(), range)),
+            Some(Kind::Decimal) if range.len() == 2 => Ok(("DECIMAL_RND".to_string(), range)),
+            Some(Kind::Date) => Ok((format!("DATE_{}", suffix), range)),
+            Some(Kind::Bool) => Ok(("BOOLEAN".to_string(), vec![])),
+            Some(Kind::Timestamp) => Ok(("TIMESTAMP".to_string(), vec![])),
+            Some(Kind::Uuid) => Ok(("UUID4".to_string(), vec![])),
+            _ => Err(Error::Schema(format!(
+                "{}: generator or values are required",
+                self.name
+            ))),
+        }
+    }
+}
+
+fn order(a: &Scalar, b: &Scalar) -> Option<Ordering> {
+    let number = |v: &Scalar| match v {
+        Scalar::Int(v) => Some(*v as f64),
+        Scalar::Float(v) => Some(*v),
+        _ => None,
+    };
+    match (a, b) {
+        (Scalar::Int(a), Scalar::Int(b)) => Some(a.cmp(b)),
+        (Scalar::Str(a), Scalar::Str(b)) => {
+            Some(a.parse::<NaiveDate>().ok()?.cmp(&b.parse().ok()?))
+        }
+        (a, b) => number(a)?.partial_cmp(&number(b)?),
+    }
+}
+
+pub fn guess(name: &str) -> Option<&'static str> {
+    let name = name.to_lowercase();
+    let has = |parts: &[&str]| parts.iter().any(|p| name.contains(p));
+    if has(&["first"]) {
+        Some("FIRST_RND")
+    } else if has(&["last", "surname"]) {
+        Some("LAST_RND")
+    } else if has(&["phone", "mobile"]) {
+        Some("PHONE")
+    } else if name == "ip" || has(&["_ip", "ip_", "ipv4"]) {
+        Some("IPV4")
+    } else if has(&["country"]) {
+        Some("COUNTRY_CODE_RND")
+    } else if has(&["zone", "tz"]) {
+        Some("TIME_ZONE")
+    } else if has(&["gender", "sex"]) {
+        Some("GENDER")
+    } else if has(&["domain", "host", "site"]) {
+        Some("DOMAIN_RND")
+    } else if has(&["name"]) {
+        Some("FIRST_RND")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::escape::Escape;
+    use crate::generust::{Composite, Context, Error, Generust, Kind, Parser, Result, Value};
+    use crate::schema::Schema;
+
+    fn compile(yaml: &str) -> Result<Composite> {
+        let schema: Schema = serde_yaml::from_str(yaml).unwrap();
+        schema.compile(&Parser::new("\\$", ",", Escape::None).unwrap())
+    }
+
+    #[test]
+    fn test_compile() {
+        let mut composite = compile(
+            r"
+fields:
+  - name: id
+    generator: ROW_NUM
+    args: [1]
+  - name: age
+    type: int
+    min: 18
+    max: 99
+  - name: status
+    values: [active, inactive]
+    sequential: true
+  - name: born
+    type: date
+    min: 2000-01-01
+    max: 2010-01-01
+  - name: email
+    generator: DOMAIN_RND
+    nullable: 1
+  - name: code
+    generator: COUNTRY_CODE_SEQ
+    length: 1
+  - name: zip
+    type: string
+    generator: INT_RND
+    args: [1000, 1001]
+  - name: level
+    type: int
+    values: [1, 2]
+",
+        )
+        .unwrap();
+        let kinds = composite
+            .fields
+            .iter()
+            .map(|f| (f.name.as_str(), f.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("id", Kind::Int),
+                ("age", Kind::Int),
+                ("status", Kind::String),
+                ("born", Kind::Date),
+                ("email", Kind::String),
+                ("code", Kind::String),
+                ("zip", Kind::String),
+                ("level", Kind::Int)
+            ],
+            kinds
+        );
+        for i in 0..10 {
+            let mut ctx = Context::new(i);
+            assert!(composite.generate(&mut ctx, &mut std::io::sink()).is_ok());
+            assert_eq!(Some((i + 1).to_string().as_str()), ctx.field("id"));
+            let age = ctx.field("age").unwrap().parse::<i32>().unwrap();
+            assert!((18..=99).contains(&age));
+            let status = if i % 2 == 0 { "active" } else { "inactive" };
+            assert_eq!(Some(status), ctx.field("status"));
+            assert!(ctx.field("born").unwrap().starts_with("200"));
+            assert_eq!(None, ctx.field("email"));
+            assert_eq!(1, ctx.field("code").unwrap().len());
+            assert_eq!(Some(&Value::Str("1000".to_string())), ctx.value("zip"));
+            assert!(matches!(
+                ctx.value("level"),
+                Some(Value::Int(1)) | Some(Value::Int(2))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(compile("fields: [{name: a}]").is_err());
+        assert!(compile("fields: [{name: a, generator: NOPE}]").is_err());
+        assert!(compile("fields: [{name: a, type: date, min: 2020-01-01}]").is_err());
+        assert!(compile("fields: [{name: a, type: bool, nullable: 2}]").is_err());
+        assert!(compile("fields: [{name: a, type: int, min: 5, max: 4}]").is_err());
+        assert!(compile("fields: [{name: a, type: int, max: -1}]").is_err());
+        assert!(
+            compile("fields: [{name: a, type: date, min: 2020-01-02, max: 2020-01-01}]").is_err()
+        );
+        match compile("fields: [{name: a, type: float, min: 1, max: 1}]") {
+            Err(Error::Schema(msg)) => {
+                assert_eq!("a: min and max of a float field must differ", msg)
+            }
+            _ => panic!("expected a schema error"),
+        }
+
+        let mut composite = compile("fields: [{name: a, type: int, min: 5, max: 5}]").unwrap();
+        let mut ctx = Context::new(0);
+        assert!(composite.generate(&mut ctx, &mut std::io::sink()).is_ok());
+        assert_eq!(Some("5"), ctx.field("a"));
+    }
+}
@@ -0,0 +1,170 @@
+use rusqlite::types::Value;
+use rusqlite::Connection;
+
+use crate::generust::{Context, Error, FieldDef, Kind, Result, Value as Typed};
+use crate::output::Output;
+
+const TABLE: &str = "records";
+
+pub struct Sqlite {
+    conn: Connection,
+    fields: Vec<FieldDef>,
+    insert: String,
+    batch_size: usize,
+    count: usize,
+    values: Vec<Value>,
+}
+
+fn identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn column_type(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Int => "INTEGER",
+        Kind::Float => "REAL",
+        Kind::Bool => "BOOLEAN",
+        Kind::Date => "DATE",
+        Kind::Timestamp => "TIMESTAMP",
+        Kind::String | Kind::Decimal | Kind::Uuid => "TEXT",
+    }
+}
+
+fn value(value: Typed) -> Value {
+    match value {
+        Typed::Null => Value::Null,
+        Typed::Int(v) => Value::Integer(v),
+        Typed::Float(v) => Value::Real(v),
+        Typed::Bool(v) => Value::Integer(i64::from(v)),
+        Typed::Str(v) => Value::Text(v),
+        v => Value::Text(v.to_string()),
+    }
+}
+
+impl Sqlite {
+    pub fn new(
+        path: &str,
+        table: Option<&str>,
+        fields: &[FieldDef],
+        batch_size: usize,
+    ) -> Result<Sqlite> {
+        let table = identifier(table.unwrap_or(TABLE));
+        if fields.is_empty() {
+            return Err(Error::Template(
+                "sqlite output requires named fields".to_string(),
+            ));
+        }
+        let columns = fields
+            .iter()
+            .map(|f| format!("{} {}", identifier(&f.name), column_type(f.kind)))
+            .collect::<Vec<String>>();
+        let names = fields
+            .iter()
+            .map(|f| identifier(&f.name))
+            .collect::<Vec<String>>();
+        let params = (1..=fields.len())
+            .map(|i| format!("?{}", i))
+            .collect::<Vec<String>>();
+        let conn = Connection::open(path)?;
+        conn.execute_batch(&format!(
+            "CREATE TABLE IF NOT EXISTS {} ({}); BEGIN;",
+            table,
+            columns.join(", ")
+        ))?;
+        Ok(Sqlite {
+            conn,
+            fields: fields.to_vec(),
+            insert: format!(
+                "INSERT INTO {} ({}) VALUES ({})",
+                table,
+                names.join(", "),
+                params.join(", ")
+            ),
+            batch_size: batch_size.max(1),
+            count: 0,
+            values: Vec::with_capacity(fields.len()),
+        })
+    }
+}
+
+impl Output for Sqlite {
+    fn write(&mut self, ctx: &Context, _text: &[u8]) -> Result<()> {
+        if self.count == self.batch_size {
+            self.conn.execute_batch("COMMIT; BEGIN;")?;
+            self.count = 0;
+        }
+        self.values.clear();
+        for f in &self.fields {
+            self.values.push(ctx.typed(f).map_or(Value::Null, value));
+        }
+        let mut stmt = self.conn.prepare_cached(&self.insert)?;
+        stmt.execute(rusqlite::params_from_iter(self.values.iter()))?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        Ok(self.conn.execute_batch("COMMIT;")?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::generust::{Context, FieldDef, Kind, Value};
+    use crate::output::Output;
+    use crate::sqlite::Sqlite;
+
+    #[test]
+    fn test_sqlite() {
+        let fields = vec![
+            FieldDef {
+                name: "id".to_string(),
+                kind: Kind::Int,
+                sql_type: None,
+            },
+            FieldDef {
+                name: "name".to_string(),
+                kind: Kind::String,
+                sql_type: None,
+            },
+            FieldDef {
+                name: "active".to_string(),
+                kind: Kind::Bool,
+                sql_type: None,
+            },
+        ];
+        let mut sink = Sqlite::new(":memory:", Some("users"), &fields, 2).unwrap();
+        for i in 0..5 {
+            let mut ctx = Context::new(i);
+            ctx.set_field("id", i.to_string(), Value::Int(i64::from(i)));
+            if i % 2 == 0 {
+                let name = format!("O'Brien {}", i);
+                ctx.set_field("name", name.clone(), Value::Str(name));
+            }
+            ctx.set_field("active", "true".to_string(), Value::Bool(true));
+            assert!(sink.write(&ctx, b"").is_ok());
+        }
+        assert!(sink.finish().is_ok());
+        let row: (i64, i64, i64, String) = sink
+            .conn
+            .query_row(
+                "SELECT COUNT(*), SUM(id), COUNT(name), MAX(name) FROM users WHERE active = 1",
+                [],
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!((5, 10, 3, "O'Brien 4".to_string()), row);
+        assert!(sink.conn.is_autocommit());
+
+        let mut sink = Sqlite::new(":memory:", None, &fields, 2).unwrap();
+        let mut ctx = Context::new(0);
+        ctx.set_field("id", "7".to_string(), Value::Str("7".to_string()));
+        assert!(sink.write(&ctx, b"").is_ok());
+        let id: i64 = sink
+            .conn
+            .query_row("SELECT id FROM records", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(7, id);
+        assert!(Sqlite::new(":memory:", None, &[], 2).is_err());
+    }
+}
@@ -0,0 +1,1268 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, NaiveDate};
+
+use crate::avro;
+use crate::cbor::Cbor;
+use crate::columnar::Compression;
+use crate::config::{Toml, Yaml};
+use crate::escape::Escape;
+use crate::generust::{Context, Error, FieldDef, Kind, Result, EPOCH_DAYS_FROM_CE};
+use crate::metrics::{Influx, Prometheus};
+use crate::msgpack::MsgPack;
+
+pub trait Writer {
+    fn header(&self) -> Option<String> {
+        None
+    }
+    fn footer(&self) -> Option<String> {
+        None
+    }
+    fn binary(&self) -> bool {
+        false
+    }
+    // False for writers that emit the records in blocks or at the end, with no separator between them
+    fn separated(&self) -> bool {
+        true
+    }
+    fn write(&mut self, ctx: &Context, text: &[u8], w: &mut dyn Write) -> Result<()>;
+    fn delimit(&mut self, _w: &mut dyn Write) -> Result<()> {
+        Ok(())
+    }
+    fn finish(&mut self, _w: &mut dyn Write) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Text,
+    Csv,
+    Jsonl,
+    Sql,
+    Copy,
+    CopyBinary,
+    Avro,
+    AvroSingle,
+    MsgPack,
+    Cbor,
+    Protobuf,
+    Xml,
+    Fixed,
+    Yaml,
+    Toml,
+    Bulk,
+    Influx,
+    Prometheus,
+    OpenMetrics,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "csv" => Ok(Format::Csv),
+            "jsonl" => Ok(Format::Jsonl),
+            "sql" => Ok(Format::Sql),
+            "copy" => Ok(Format::Copy),
+            "copy-binary" => Ok(Format::CopyBinary),
+            "avro" => Ok(Format::Avro),
+            "avro-single" => Ok(Format::AvroSingle),
+            "msgpack" => Ok(Format::MsgPack),
+            "cbor" => Ok(Format::Cbor),
+            "protobuf" => Ok(Format::Protobuf),
+            "xml" => Ok(Format::Xml),
+            "fixed" => Ok(Format::Fixed),
+            "yaml" => Ok(Format::Yaml),
+            "toml" => Ok(Format::Toml),
+            "bulk" => Ok(Format::Bulk),
+            "influx" => Ok(Format::Influx),
+            "prometheus" => Ok(Format::Prometheus),
+            "openmetrics" => Ok(Format::OpenMetrics),
+            _ => Err(format!("unknown format: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dialect {
+    Postgres,
+    Mysql,
+    Sqlite,
+}
+
+impl FromStr for Dialect {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "postgres" => Ok(Dialect::Postgres),
+            "mysql" => Ok(Dialect::Mysql),
+            "sqlite" => Ok(Dialect::Sqlite),
+            _ => Err(format!("unknown dialect: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layout {
+    pub name: String,
+    pub width: usize,
+    pub align: Align,
+    pub pad: char,
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let parts = s.split(':').collect::<Vec<&str>>();
+        let invalid = || {
+            format!(
+                "{}: invalid layout, expected name:width[:left|right[:pad]]",
+                s
+            )
+        };
+        if parts.len() < 2 || parts.len() > 4 || parts[0].is_empty() {
+            return Err(invalid());
+        }
+        let width = parts[1].parse().map_err(|_| invalid())?;
+        let align = match parts.get(2) {
+            None | Some(&"") | Some(&"left") => Align::Left,
+            Some(&"right") => Align::Right,
+            _ => return Err(invalid()),
+        };
+        let mut pad = parts
+            .get(3)
+            .map(|p| p.chars())
+            .unwrap_or_else(|| " ".chars());
+        let pad = match (pad.next(), pad.next()) {
+            (Some(c), None) if c.is_ascii() => c,
+            _ => return Err(invalid()),
+        };
+        Ok(Layout {
+            name: parts[0].to_string(),
+            width,
+            align,
+            pad,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overflow {
+    Truncate,
+    Error,
+}
+
+impl FromStr for Overflow {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "truncate" => Ok(Overflow::Truncate),
+            "error" => Ok(Overflow::Error),
+            _ => Err(format!("unknown overflow: {}", s)),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Settings {
+    pub table: Option<String>,
+    pub dialect: Dialect,
+    pub batch_size: usize,
+    pub avro_schema: Option<PathBuf>,
+    pub compression: Compression,
+    pub block_size: usize,
+    pub xml_root: String,
+    pub xml_record: String,
+    pub xml_attributes: Vec<String>,
+    pub layout: Vec<Layout>,
+    pub overflow: Overflow,
+    pub index: Option<String>,
+    pub id_field: Option<String>,
+    pub measurement: Option<String>,
+    pub tags: Vec<String>,
+    pub time_field: Option<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            table: None,
+            dialect: Dialect::Postgres,
+            batch_size: 100,
+            avro_schema: None,
+            compression: Compression::Snappy,
+            block_size: 100_000,
+            xml_root: "records".to_string(),
+            xml_record: "record".to_string(),
+            xml_attributes: vec![],
+            layout: vec![],
+            overflow: Overflow::Error,
+            index: None,
+            id_field: None,
+            measurement: None,
+            tags: vec![],
+            time_field: None,
+        }
+    }
+}
+
+impl Format {
+    pub fn writer(self, fields: &[FieldDef], settings: &Settings) -> Result<Box<dyn Writer>> {
+        let fields = fields.to_vec();
+        Ok(match self {
+            Format::Text => Box::new(Text),
+            Format::Csv => Box::new(Csv { fields }),
+            Format::Jsonl => Box::new(JsonLines { fields }),
+            Format::Sql => Box::new(Insert::new(fields, settings)?),
+            Format::Copy => Box::new(Copy { fields }),
+            Format::CopyBinary => Box::new(CopyBinary::new(fields)?),
+            Format::Avro => avro::container(
+                settings.avro_schema.as_deref(),
+                settings.table.as_deref(),
+                &fields,
+                settings.compression,
+                settings.block_size,
+            )?,
+            Format::AvroSingle => avro::single_object(
+                settings.avro_schema.as_deref(),
+                settings.table.as_deref(),
+                &fields,
+            )?,
+            Format::MsgPack => Box::new(MsgPack::new(fields)),
+            Format::Cbor => Box::new(Cbor::new(fields)),
+            Format::Protobuf => Box::new(Binary),
+            Format::Xml => Box::new(Xml::new(fields, settings)?),
+            Format::Fixed => Box::new(Fixed::new(&fields, settings)?),
+            Format::Yaml => Box::new(Yaml::new(fields)?),
+            Format::Toml => Box::new(Toml::new(fields, settings.table.as_deref())?),
+            Format::Bulk => Box::new(Bulk::new(fields, settings)?),
+            Format::Influx => Box::new(Influx::new(fields, settings)?),
+            Format::Prometheus => Box::new(Prometheus::new(fields, settings, false)?),
+            Format::OpenMetrics => Box::new(Prometheus::new(fields, settings, true)?),
+        })
+    }
+}
+
+struct Text;
+
+impl Writer for Text {
+    fn write(&mut self, _ctx: &Context, text: &[u8], w: &mut dyn Write) -> Result<()> {
+        Ok(w.write_all(text)?)
+    }
+}
+
+struct Binary;
+
+impl Writer for Binary {
+    fn binary(&self) -> bool {
+        true
+    }
+
+    fn write(&mut self, _ctx: &Context, text: &[u8], w: &mut dyn Write) -> Result<()> {
+        Ok(w.write_all(text)?)
+    }
+}
+
+struct Csv {
+    fields: Vec<FieldDef>,
+}
+
+impl Writer for Csv {
+    fn header(&self) -> Option<String> {
+        let mut buf = Vec::with_capacity(256);
+        for (i, f) in self.fields.iter().enumerate() {
+            if i > 0 {
+                buf.push(b',');
+            }
+            Escape::Csv.write(f.name.as_bytes(), &mut buf).ok()?;
+        }
+        String::from_utf8(buf).ok()
+    }
+
+    fn write(&mut self, ctx: &Context, _text: &[u8], w: &mut dyn Write) -> Result<()> {
+        for (i, f) in self.fields.iter().enumerate() {
+            if i > 0 {
+                w.write_all(b",")?;
+            }
+            if let Some(value) = ctx.field(&f.name) {
+                Escape::Csv.write(value.as_bytes(), w)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct JsonLines {
+    fields: Vec<FieldDef>,
+}
+
+impl Writer for JsonLines {
+    fn write(&mut self, ctx: &Context, _text: &[u8], w: &mut dyn Write) -> Result<()> {
+        w.write_all(b"{")?;
+        for (i, f) in self.fields.iter().enumerate() {
+            if i > 0 {
+                w.write_all(b",")?;
+            }
+            w.write_all(b"\"")?;
+            Escape::Json.write(f.name.as_bytes(), w)?;
+            w.write_all(b"\":")?;
+            match ctx.typed(f) {
+                Some(value) => value.write_json(w)?,
+                None => w.write_all(b"null")?,
+            }
+        }
+        Ok(w.write_all(b"}")?)
+    }
+}
+
+struct Bulk {
+    json: JsonLines,
+    index: String,
+    id_field: Option<String>,
+}
+
+impl Bulk {
+    fn new(fields: Vec<FieldDef>, settings: &Settings) -> Result<Bulk> {
+        let index = match settings.index.as_ref().or(settings.table.as_ref()) {
+            Some(index) => index.clone(),
+            None => {
+                return Err(Error::Template(
+                    "bulk format requires an index name".to_string(),
+                ))
+            }
+        };
+        if let Some(name) = &settings.id_field {
+            if !fields.iter().any(|f| &f.name == name) {
+                return Err(Error::Template(format!("{}: unknown field", name)));
+            }
+        }
+        Ok(Bulk {
+            json: JsonLines { fields },
+            index,
+            id_field: settings.id_field.clone(),
+        })
+    }
+}
+
+impl Writer for Bulk {
+    fn write(&mut self, ctx: &Context, text: &[u8], w: &mut dyn Write) -> Result<()> {
+        w.write_all(b"{\"index\":{\"_index\":\"")?;
+        Escape::Json.write(self.index.as_bytes(), w)?;
+        w.write_all(b"\"")?;
+        if let Some(id) = self.id_field.as_ref().and_then(|f| ctx.field(f)) {
+            w.write_all(b",\"_id\":\"")?;
+            Escape::Json.write(id.as_bytes(), w)?;
+            w.write_all(b"\"")?;
+        }
+        w.write_all(b"}}\n")?;
+        self.json.write(ctx, text, w)
+    }
+}
+
+struct Xml {
+    fields: Vec<FieldDef>,
+    attributes: Vec<bool>,
+    root: String,
+    record: String,
+}
+
+fn xml_name(name: &str) -> Result<&str> {
+    let valid = name.chars().enumerate().all(|(i, c)| {
+        c.is_alphabetic() || c == '_' || (i > 0 && (c.is_alphanumeric() || c == '-' || c == '.'))
+    });
+    if valid && !name.is_empty() && !name.to_lowercase().starts_with("xml") {
+        Ok(name)
+    } else {
+        Err(Error::Template(format!("{}: invalid xml name", name)))
+    }
+}
+
+impl Xml {
+    fn new(fields: Vec<FieldDef>, settings: &Settings) -> Result<Xml> {
+        if fields.is_empty() {
+            return Err(Error::Template(
+                "xml format requires named fields".to_string(),
+            ));
+        }
+        for name in &settings.xml_attributes {
+            if !fields.iter().any(|f| &f.name == name) {
+                return Err(Error::Template(format!("{}: unknown field", name)));
+            }
+        }
+        for f in &fields {
+            xml_name(&f.name)?;
+        }
+        let attributes = fields
+            .iter()
+            .map(|f| settings.xml_attributes.contains(&f.name))
+            .collect();
+        Ok(Xml {
+            fields,
+            attributes,
+            root: xml_name(&settings.xml_root)?.to_string(),
+            record: xml_name(&settings.xml_record)?.to_string(),
+        })
+    }
+}
+
+impl Writer for Xml {
+    fn header(&self) -> Option<String> {
+        Some(format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<{}>",
+            self.root
+        ))
+    }
+
+    fn footer(&self) -> Option<String> {
+        Some(format!("</{}>", self.root))
+    }
+
+    fn write(&mut self, ctx: &Context, _text: &[u8], w: &mut dyn Write) -> Result<()> {
+        write!(w, "<{}", self.record)?;
+        for (f, _) in self
+            .fields
+            .iter()
+            .zip(&self.attributes)
+            .filter(|(_, a)| **a)
+        {
+            if let Some(value) = ctx.field(&f.name) {
+                write!(w, " {}=\"", f.name)?;
+                Escape::Xml.write(value.as_bytes(), w)?;
+                w.write_all(b"\"")?;
+            }
+        }
+        w.write_all(b">")?;
+        for (f, _) in self
+            .fields
+            .iter()
+            .zip(&self.attributes)
+            .filter(|(_, a)| !**a)
+        {
+            if let Some(value) = ctx.field(&f.name) {
+                write!(w, "<{}>", f.name)?;
+                Escape::Xml.write(value.as_bytes(), w)?;
+                write!(w, "</{}>", f.name)?;
+            }
+        }
+        Ok(write!(w, "</{}>", self.record)?)
+    }
+}
+
+struct Fixed {
+    layout: Vec<Layout>,
+    overflow: Overflow,
+    buf: String,
+}
+
+impl Fixed {
+    fn new(fields: &[FieldDef], settings: &Settings) -> Result<Fixed> {
+        if settings.layout.is_empty() {
+            return Err(Error::Template(
+                "fixed format requires a layout".to_string(),
+            ));
+        }
+        for l in &settings.layout {
+            if !fields.iter().any(|f| f.name == l.name) {
+                return Err(Error::Template(format!("{}: unknown field", l.name)));
+            }
+        }
+        Ok(Fixed {
+            layout: settings.layout.clone(),
+            overflow: settings.overflow,
+            buf: String::with_capacity(256),
+        })
+    }
+}
+
+impl Writer for Fixed {
+    fn write(&mut self, ctx: &Context, _text: &[u8], w: &mut dyn Write) -> Result<()> {
+        self.buf.clear();
+        for l in &self.layout {
+            let value = ctx.field(&l.name).unwrap_or("");
+            if value.len() > l.width {
+                if self.overflow == Overflow::Error {
+                    return Err(Error::Template(format!(
+                        "{}: {} is wider than {} bytes",
+                        l.name, value, l.width
+                    )));
+                }
+                let mut end = l.width;
+                while !value.is_char_boundary(end) {
+                    end -= 1;
+                }
+                self.buf.push_str(&value[..end]);
+                self.buf
+                    .extend(std::iter::repeat(l.pad).take(l.width - end));
+                continue;
+            }
+            let pad = std::iter::repeat(l.pad).take(l.width - value.len());
+            match (l.align, value.strip_prefix('-')) {
+                (Align::Left, _) => {
+                    self.buf.push_str(value);
+                    self.buf.extend(pad);
+                }
+                (Align::Right, Some(digits)) if l.pad == '0' => {
+                    self.buf.push('-');
+                    self.buf.extend(pad);
+                    self.buf.push_str(digits);
+                }
+                (Align::Right, _) => {
+                    self.buf.extend(pad);
+                    self.buf.push_str(value);
+                }
+            }
+        }
+        Ok(w.write_all(self.buf.as_bytes())?)
+    }
+}
+
+struct Insert {
+    fields: Vec<FieldDef>,
+    dialect: Dialect,
+    batch_size: usize,
+    statement: String,
+    count: usize,
+}
+
+impl Insert {
+    fn new(fields: Vec<FieldDef>, settings: &Settings) -> Result<Insert> {
+        let table = match &settings.table {
+            Some(table) => table,
+            None => {
+                return Err(Error::Template(
+                    "sql format requires a table name".to_string(),
+                ))
+            }
+        };
+        if fields.is_empty() {
+            return Err(Error::Template(
+                "sql format requires named fields".to_string(),
+            ));
+        }
+        let dialect = settings.dialect;
+        let columns = fields
+            .iter()
+            .map(|f| dialect.identifier(&f.name))
+            .collect::<Vec<String>>();
+        let statement = format!(
+            "INSERT INTO {} ({}) VALUES ",
+            dialect.identifier(table),
+            columns.join(", ")
+        );
+        Ok(Insert {
+            fields,
+            dialect,
+            batch_size: settings.batch_size.max(1),
+            statement,
+            count: 0,
+        })
+    }
+}
+
+impl Dialect {
+    fn identifier(self, name: &str) -> String {
+        match self {
+            Dialect::Mysql => format!("`{}`", name.replace('`', "``")),
+            Dialect::Postgres | Dialect::Sqlite => format!("\"{}\"", name.replace('"', "\"\"")),
+        }
+    }
+
+    fn literal(self, value: &str, kind: Kind, w: &mut dyn Write) -> Result<()> {
+        match kind {
+            Kind::Int | Kind::Float | Kind::Decimal => match value.parse::<f64>() {
+                Ok(v) if v.is_finite() => return Ok(w.write_all(value.as_bytes())?),
+                // NaN and infinities have no numeric literal, PostgreSQL reads them quoted
+                Ok(_) if self != Dialect::Postgres => return Ok(w.write_all(b"NULL")?),
+                _ => {}
+            },
+            Kind::Bool if value == "true" || value == "false" => {
+                let value = match (self, value) {
+                    (Dialect::Sqlite, "true") => "1",
+                    (Dialect::Sqlite, _) => "0",
+                    (_, "true") => "TRUE",
+                    _ => "FALSE",
+                };
+                return Ok(w.write_all(value.as_bytes())?);
+            }
+            _ => {}
+        }
+        w.write_all(b"'")?;
+        if self == Dialect::Mysql {
+            let value = value.as_bytes();
+            let mut start = 0;
+            for (i, b) in value.iter().enumerate() {
+                let esc: &[u8] = match b {
+                    b'\'' => b"''",
+                    b'\\' => b"\\\\",
+                    0 => b"\\0",
+                    _ => continue,
+                };
+                w.write_all(&value[start..i])?;
+                w.write_all(esc)?;
+                start = i + 1;
+            }
+            w.write_all(&value[start..])?;
+        } else {
+            Escape::Sql.write(value.as_bytes(), w)?;
+        }
+        Ok(w.write_all(b"'")?)
+    }
+}
+
+impl Writer for Insert {
+    fn write(&mut self, ctx: &Context, _text: &[u8], w: &mut dyn Write) -> Result<()> {
+        if self.count == 0 {
+            w.write_all(self.statement.as_bytes())?;
+        }
+        w.write_all(b"(")?;
+        for (i, f) in self.fields.iter().enumerate() {
+            if i > 0 {
+                w.write_all(b", ")?;
+            }
+            match ctx.field(&f.name) {
+                Some(value) => self.dialect.literal(value, f.kind, w)?,
+                None => w.write_all(b"NULL")?,
+            }
+        }
+        self.count += 1;
+        Ok(w.write_all(b")")?)
+    }
+
+    fn delimit(&mut self, w: &mut dyn Write) -> Result<()> {
+        if self.count == self.batch_size {
+            self.count = 0;
+            Ok(w.write_all(b";")?)
+        } else {
+            Ok(w.write_all(b",")?)
+        }
+    }
+
+    fn finish(&mut self, w: &mut dyn Write) -> Result<()> {
+        if self.count > 0 {
+            w.write_all(b";")?;
+        }
+        Ok(())
+    }
+}
+
+struct Copy {
+    fields: Vec<FieldDef>,
+}
+
+impl Writer for Copy {
+    fn write(&mut self, ctx: &Context, _text: &[u8], w: &mut dyn Write) -> Result<()> {
+        for (i, f) in self.fields.iter().enumerate() {
+            if i > 0 {
+                w.write_all(b"\t")?;
+            }
+            match ctx.field(&f.name) {
+                Some(value) => Escape::Copy.write(value.as_bytes(), w)?,
+                None => w.write_all(b"\\N")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+const PG_EPOCH_DAYS: i64 = 10_957;
+const PG_EPOCH_SECONDS: i64 = 946_684_800;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PgType {
+    Int2,
+    Int4,
+    Int8,
+    Float4,
+    Float8,
+    Numeric,
+    Bool,
+    Date,
+    Timestamp,
+    Uuid,
+    Text,
+}
+
+impl PgType {
+    fn of(f: &FieldDef) -> Result<PgType> {
+        let ty = match &f.sql_type {
+            Some(ty) => ty.as_str(),
+            None => {
+                return Ok(match f.kind {
+                    Kind::Int => PgType::Int8,
+                    Kind::Float => PgType::Float8,
+                    Kind::Bool => PgType::Bool,
+                    Kind::Date => PgType::Date,
+                    Kind::Timestamp => PgType::Timestamp,
+                    Kind::Decimal => PgType::Numeric,
+                    Kind::Uuid => PgType::Uuid,
+                    Kind::String => PgType::Text,
+                })
+            }
+        };
+        let is = |types: &[&str]| types.iter().any(|t| ty.starts_with(t));
+        let pg = if is(&["INTERVAL"]) {
+            None
+        } else if is(&["SMALLINT", "SMALLSERIAL", "INT2", "SERIAL2", "TINYINT"]) {
+            Some(PgType::Int2)
+        } else if is(&["BIGINT", "BIGSERIAL", "INT8", "SERIAL8"]) {
+            Some(PgType::Int8)
+        } else if is(&["INT", "SERIAL", "MEDIUMINT"]) {
+            Some(PgType::Int4)
+        } else if is(&["REAL", "FLOAT4"]) {
+            Some(PgType::Float4)
+        } else if is(&["DOUBLE", "FLOAT"]) {
+            Some(PgType::Float8)
+        } else if is(&["DECIMAL", "NUMERIC"]) {
+            Some(PgType::Numeric)
+        } else if is(&["BOOL"]) {
+            Some(PgType::Bool)
+        } else if ty == "DATE" {
+            Some(PgType::Date)
+        } else if is(&["TIMESTAMP"]) {
+            Some(PgType::Timestamp)
+        } else if ty == "UUID" {
+            Some(PgType::Uuid)
+        } else if is(&["CHAR", "VARCHAR", "CHARACTER", "TEXT", "CITEXT", "NAME"]) || ty == "JSON" {
+            Some(PgType::Text)
+        } else {
+            None
+        };
+        pg.ok_or_else(|| {
+            Error::Template(format!(
+                "{}: {} columns are not supported by the copy-binary format",
+                f.name, ty
+            ))
+        })
+    }
+}
+
+struct CopyBinary {
+    fields: Vec<(FieldDef, PgType)>,
+    started: bool,
+    buf: Vec<u8>,
+}
+
+impl CopyBinary {
+    fn new(fields: Vec<FieldDef>) -> Result<CopyBinary> {
+        let fields = fields
+            .into_iter()
+            .map(|f| PgType::of(&f).map(|ty| (f, ty)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(CopyBinary {
+            fields,
+            started: false,
+            buf: Vec::with_capacity(64),
+        })
+    }
+
+    fn start(&mut self, w: &mut dyn Write) -> Result<()> {
+        if !self.started {
+            self.started = true;
+            w.write_all(b"PGCOPY\n\xff\r\n\0")?;
+            w.write_all(&0i32.to_be_bytes())?;
+            w.write_all(&0i32.to_be_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+fn numeric(value: &str, buf: &mut Vec<u8>) -> Option<()> {
+    if value == "NaN" {
+        buf.extend_from_slice(&[0, 0, 0, 0, 0xc0, 0, 0, 0]);
+        return Some(());
+    }
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(value) => (true, value),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let (int, frac) = value.split_once('.').unwrap_or((value, ""));
+    if int.is_empty() && frac.is_empty()
+        || !int.bytes().chain(frac.bytes()).all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+    let int = int.trim_start_matches('0');
+    let mut digits = String::with_capacity(int.len() + frac.len() + 6);
+    digits.push_str(&"0".repeat((4 - int.len() % 4) % 4));
+    digits.push_str(int);
+    digits.push_str(frac);
+    digits.push_str(&"0".repeat((4 - frac.len() % 4) % 4));
+    let mut groups = digits
+        .as_bytes()
+        .chunks(4)
+        .map(|c| c.iter().fold(0i16, |n, d| n * 10 + i16::from(d - b'0')))
+        .collect::<Vec<i16>>();
+    let mut weight = (int.len() as i16 + 3) / 4 - 1;
+    while groups.first() == Some(&0) {
+        groups.remove(0);
+        weight -= 1;
+    }
+    while groups.last() == Some(&0) {
+        groups.pop();
+    }
+    if groups.is_empty() {
+        weight = 0;
+    }
+    let sign: u16 = if negative && !groups.is_empty() {
+        0x4000
+    } else {
+        0
+    };
+    buf.extend_from_slice(&(groups.len() as i16).to_be_bytes());
+    buf.extend_from_slice(&weight.to_be_bytes());
+    buf.extend_from_slice(&sign.to_be_bytes());
+    buf.extend_from_slice(&(frac.len() as i16).to_be_bytes());
+    for g in groups {
+        buf.extend_from_slice(&g.to_be_bytes());
+    }
+    Some(())
+}
+
+fn copy_binary(f: &FieldDef, ty: PgType, value: &str, buf: &mut Vec<u8>) -> Result<()> {
+    let invalid = || Error::Template(format!("{}: {} is not a valid {:?}", f.name, value, ty));
+    match ty {
+        PgType::Int2 => {
+            let v = value.parse::<i16>().map_err(|_| invalid())?;
+            buf.extend_from_slice(&v.to_be_bytes());
+        }
+        PgType::Int4 => {
+            let v = value.parse::<i32>().map_err(|_| invalid())?;
+            buf.extend_from_slice(&v.to_be_bytes());
+        }
+        PgType::Int8 => {
+            let v = value.parse::<i64>().map_err(|_| invalid())?;
+            buf.extend_from_slice(&v.to_be_bytes());
+        }
+        PgType::Float4 => {
+            let v = value.parse::<f32>().map_err(|_| invalid())?;
+            buf.extend_from_slice(&v.to_bits().to_be_bytes());
+        }
+        PgType::Float8 => {
+            let v = value.parse::<f64>().map_err(|_| invalid())?;
+            buf.extend_from_slice(&v.to_bits().to_be_bytes());
+        }
+        PgType::Numeric => numeric(value, buf).ok_or_else(invalid)?,
+        PgType::Bool => match value {
+            "true" => buf.push(1),
+            "false" => buf.push(0),
+            _ => return Err(invalid()),
+        },
+        PgType::Date => {
+            let date = value.parse::<NaiveDate>().map_err(|_| invalid())?;
+            let days = i64::from(date.num_days_from_ce() - EPOCH_DAYS_FROM_CE);
+            buf.extend_from_slice(&((days - PG_EPOCH_DAYS) as i32).to_be_bytes());
+        }
+        PgType::Timestamp => {
+            let ts = DateTime::parse_from_rfc3339(value).map_err(|_| invalid())?;
+            let micros = (ts.timestamp() - PG_EPOCH_SECONDS) * 1_000_000
+                + i64::from(ts.timestamp_subsec_micros());
+            buf.extend_from_slice(&micros.to_be_bytes());
+        }
+        PgType::Uuid => {
+            let v = uuid::Uuid::parse_str(value).map_err(|_| invalid())?;
+            buf.extend_from_slice(v.as_bytes());
+        }
+        PgType::Text => buf.extend_from_slice(value.as_bytes()),
+    }
+    Ok(())
+}
+
+impl Writer for CopyBinary {
+    fn binary(&self) -> bool {
+        true
+    }
+
+    fn write(&mut self, ctx: &Context, _text: &[u8], w: &mut dyn Write) -> Result<()> {
+        self.start(w)?;
+        w.write_all(&(self.fields.len() as i16).to_be_bytes())?;
+        for (f, ty) in &self.fields {
+            match ctx.field(&f.name) {
+                Some(value) => {
+                    self.buf.clear();
+                    copy_binary(f, *ty, value, &mut self.buf)?;
+                    w.write_all(&(self.buf.len() as i32).to_be_bytes())?;
+                    w.write_all(&self.buf)?;
+                }
+                None => w.write_all(&(-1i32).to_be_bytes())?,
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, w: &mut dyn Write) -> Result<()> {
+        self.start(w)?;
+        Ok(w.write_all(&(-1i16).to_be_bytes())?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use crate::escape::Escape;
+    use crate::generust::{Context, FieldDef, Generust, Kind, Parser, Value};
+    use crate::schema::Schema;
+    use crate::writer::{
+        copy_binary, numeric, Dialect, Format, Layout, Overflow, PgType, Settings,
+    };
+
+    fn fields() -> Vec<FieldDef> {
+        vec![
+            FieldDef {
+                name: "id".to_string(),
+                kind: Kind::Int,
+                sql_type: None,
+            },
+            FieldDef {
+                name: "name".to_string(),
+                kind: Kind::String,
+                sql_type: None,
+            },
+            FieldDef {
+                name: "active".to_string(),
+                kind: Kind::Bool,
+                sql_type: None,
+            },
+        ]
+    }
+
+    fn write(format: Format, ctx: &Context) -> String {
+        let mut buf = Vec::with_capacity(128);
+        let mut writer = format.writer(&fields(), &Settings::default()).unwrap();
+        assert!(writer.write(ctx, b"text", &mut buf).is_ok());
+        String::from_utf8(buf).expect("invalid utf8")
+    }
+
+    fn context() -> Context {
+        let mut ctx = Context::new(0);
+        ctx.set_field("id", "7".to_string(), Value::Int(7));
+        let name = "O'Brien, \"Jr\"".to_string();
+        ctx.set_field("name", name.clone(), Value::Str(name));
+        ctx
+    }
+
+    #[test]
+    fn test_text() {
+        assert_eq!("text", write(Format::Text, &context()));
+    }
+
+    #[test]
+    fn test_csv() {
+        let writer = Format::Csv.writer(&fields(), &Settings::default()).unwrap();
+        assert_eq!(Some("id,name,active".to_string()), writer.header());
+        assert_eq!("7,\"O'Brien, \"\"Jr\"\"\",", write(Format::Csv, &context()));
+    }
+
+    #[test]
+    fn test_jsonl() {
+        assert_eq!(
+            r#"{"id":7,"name":"O'Brien, \"Jr\"","active":null}"#,
+            write(Format::Jsonl, &context())
+        );
+    }
+
+    fn insert(dialect: Dialect, batch_size: usize, count: i32) -> String {
+        let settings = Settings {
+            table: Some("users".to_string()),
+            dialect,
+            batch_size,
+            ..Settings::default()
+        };
+        let mut writer = Format::Sql.writer(&fields(), &settings).unwrap();
+        let mut buf = Vec::with_capacity(256);
+        for i in 0..count {
+            if i > 0 {
+                assert!(writer.delimit(&mut buf).is_ok());
+                buf.push(b'\n');
+            }
+            let mut ctx = context();
+            ctx.fields
+                .push(("active".to_string(), (i % 2 == 0).to_string()));
+            assert!(writer.write(&ctx, b"text", &mut buf).is_ok());
+        }
+        assert!(writer.finish(&mut buf).is_ok());
+        String::from_utf8(buf).expect("invalid utf8")
+    }
+
+    #[test]
+    fn test_sql() {
+        assert_eq!(
+            "INSERT INTO \"users\" (\"id\", \"name\", \"active\") VALUES (7, 'O''Brien, \"Jr\"', TRUE),\n\
+             (7, 'O''Brien, \"Jr\"', FALSE);\n\
+             INSERT INTO \"users\" (\"id\", \"name\", \"active\") VALUES (7, 'O''Brien, \"Jr\"', TRUE);",
+            insert(Dialect::Postgres, 2, 3)
+        );
+        assert_eq!(
+            "INSERT INTO `users` (`id`, `name`, `active`) VALUES (7, 'O''Brien, \"Jr\"', TRUE);",
+            insert(Dialect::Mysql, 2, 1)
+        );
+        assert_eq!(
+            "INSERT INTO \"users\" (\"id\", \"name\", \"active\") VALUES (7, 'O''Brien, \"Jr\"', 1);",
+            insert(Dialect::Sqlite, 2, 1)
+        );
+        assert!(Format::Sql.writer(&fields(), &Settings::default()).is_err());
+    }
+
+    #[test]
+    fn test_sql_literal() {
+        let literal = |dialect: Dialect, value: &str, kind: Kind| {
+            let mut buf = Vec::with_capacity(64);
+            assert!(dialect.literal(value, kind, &mut buf).is_ok());
+            String::from_utf8(buf).expect("invalid utf8")
+        };
+        assert_eq!("-1.5", literal(Dialect::Postgres, "-1.5", Kind::Float));
+        assert_eq!("'n/a'", literal(Dialect::Postgres, "n/a", Kind::Int));
+        assert_eq!("'NaN'", literal(Dialect::Postgres, "NaN", Kind::Float));
+        assert_eq!("'-inf'", literal(Dialect::Postgres, "-inf", Kind::Float));
+        assert_eq!("NULL", literal(Dialect::Mysql, "inf", Kind::Float));
+        assert_eq!("NULL", literal(Dialect::Sqlite, "infinity", Kind::Decimal));
+        assert_eq!(
+            "'2020-01-01'",
+            literal(Dialect::Sqlite, "2020-01-01", Kind::Date)
+        );
+        assert_eq!(r"'a\b'", literal(Dialect::Postgres, r"a\b", Kind::String));
+        assert_eq!(
+            r"'a\\b''\0'",
+            literal(Dialect::Mysql, "a\\b'\0", Kind::String)
+        );
+    }
+
+    #[test]
+    fn test_xml() {
+        let settings = Settings {
+            xml_root: "users".to_string(),
+            xml_record: "user".to_string(),
+            xml_attributes: vec!["id".to_string()],
+            ..Settings::default()
+        };
+        let mut writer = Format::Xml.writer(&fields(), &settings).unwrap();
+        assert_eq!(
+            Some("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<users>".to_string()),
+            writer.header()
+        );
+        assert_eq!(Some("</users>".to_string()), writer.footer());
+        let mut buf = Vec::with_capacity(128);
+        assert!(writer.write(&context(), b"text", &mut buf).is_ok());
+        assert_eq!(
+            "<user id=\"7\"><name>O&apos;Brien, &quot;Jr&quot;</name></user>",
+            String::from_utf8(buf).unwrap()
+        );
+        let settings = |root: &str, attribute: &str| Settings {
+            xml_root: root.to_string(),
+            xml_attributes: vec![attribute.to_string()],
+            ..Settings::default()
+        };
+        assert!(Format::Xml
+            .writer(&fields(), &settings("a b", "id"))
+            .is_err());
+        assert!(Format::Xml
+            .writer(&fields(), &settings("a", "nope"))
+            .is_err());
+        assert!(Format::Xml.writer(&[], &Settings::default()).is_err());
+    }
+
+    #[test]
+    fn test_bulk() {
+        let settings = Settings {
+            index: Some("people".to_string()),
+            id_field: Some("id".to_string()),
+            ..Settings::default()
+        };
+        let mut writer = Format::Bulk.writer(&fields(), &settings).unwrap();
+        let mut buf = Vec::with_capacity(128);
+        assert!(writer.write(&context(), b"text", &mut buf).is_ok());
+        assert_eq!(
+            "{\"index\":{\"_index\":\"people\",\"_id\":\"7\"}}\n\
+             {\"id\":7,\"name\":\"O'Brien, \\\"Jr\\\"\",\"active\":null}",
+            String::from_utf8(buf).unwrap()
+        );
+        let settings = Settings {
+            table: Some("users".to_string()),
+            id_field: Some("active".to_string()),
+            ..Settings::default()
+        };
+        let mut writer = Format::Bulk.writer(&fields(), &settings).unwrap();
+        let mut buf = Vec::with_capacity(128);
+        assert!(writer.write(&context(), b"text", &mut buf).is_ok());
+        assert!(buf.starts_with(b"{\"index\":{\"_index\":\"users\"}}\n{"));
+        assert!(Format::Bulk
+            .writer(&fields(), &Settings::default())
+            .is_err());
+        let settings = Settings {
+            index: Some("people".to_string()),
+            id_field: Some("nope".to_string()),
+            ..Settings::default()
+        };
+        assert!(Format::Bulk.writer(&fields(), &settings).is_err());
+    }
+
+    #[test]
+    fn test_fixed() {
+        let layout = |spec: &str| {
+            spec.split(',')
+                .map(|l| l.parse::<Layout>())
+                .collect::<std::result::Result<Vec<Layout>, String>>()
+        };
+        let fixed =
+            |spec: &str, overflow: Overflow, ctx: &Context| -> crate::generust::Result<String> {
+                let settings = Settings {
+                    layout: layout(spec).unwrap(),
+                    overflow,
+                    ..Settings::default()
+                };
+                let mut writer = Format::Fixed.writer(&fields(), &settings)?;
+                let mut buf = Vec::with_capacity(128);
+                writer.write(ctx, b"text", &mut buf)?;
+                Ok(String::from_utf8(buf).unwrap())
+            };
+        let mut ctx = context();
+        assert_eq!(
+            "00007O'Brien, \"Jr\"    ---",
+            fixed("id:5:right:0,name:17,active:3::-", Overflow::Error, &ctx).unwrap()
+        );
+        assert_eq!(
+            "O'Brien,   7",
+            fixed("name:8,id:4:right", Overflow::Truncate, &ctx).unwrap()
+        );
+        assert!(fixed("name:8", Overflow::Error, &ctx).is_err());
+        assert!(fixed("nope:8", Overflow::Error, &ctx).is_err());
+        ctx.fields[0].1 = "-42".to_string();
+        assert_eq!(
+            "-0042",
+            fixed("id:5:right:0", Overflow::Error, &ctx).unwrap()
+        );
+        assert!(layout("id").is_err());
+        assert!(layout("id:x").is_err());
+        assert!(layout("id:1:center").is_err());
+        assert!(layout("id:1:left:ab").is_err());
+        assert!(layout("id:1:left:é").is_err());
+        ctx.fields[1].1 = "Zoë".to_string();
+        assert_eq!(
+            "Zoë  ZoZo|",
+            fixed("name:6,name:2,name:3:left:|", Overflow::Truncate, &ctx).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_copy() {
+        let mut ctx = context();
+        ctx.fields.push(("name".to_string(), "a\tb\\c".to_string()));
+        ctx.fields.remove(1);
+        assert_eq!("7\ta\\tb\\\\c\t\\N", write(Format::Copy, &ctx));
+    }
+
+    #[test]
+    fn test_copy_binary() {
+        let mut writer = Format::CopyBinary
+            .writer(&fields(), &Settings::default())
+            .unwrap();
+        let mut buf = Vec::with_capacity(128);
+        let mut ctx = context();
+        ctx.fields.push(("active".to_string(), "true".to_string()));
+        assert!(writer.write(&ctx, b"text", &mut buf).is_ok());
+        assert!(writer.finish(&mut buf).is_ok());
+        let mut expected = b"PGCOPY\n\xff\r\n\0\0\0\0\0\0\0\0\0\0\x03".to_vec();
+        expected.extend_from_slice(b"\0\0\0\x08\0\0\0\0\0\0\0\x07");
+        expected.extend_from_slice(b"\0\0\0\x0dO'Brien, \"Jr\"");
+        expected.extend_from_slice(b"\0\0\0\x01\x01\xff\xff");
+        assert_eq!(expected, buf);
+
+        let mut buf = Vec::with_capacity(128);
+        let mut ctx = Context::new(0);
+        ctx.set_field("id", "x".to_string(), Value::Str("x".to_string()));
+        assert!(writer.write(&ctx, b"text", &mut buf).is_err());
+    }
+
+    #[test]
+    fn test_copy_binary_time() {
+        let fields = fields();
+        let mut buf = vec![];
+        assert!(copy_binary(&fields[0], PgType::Date, "2000-01-02", &mut buf).is_ok());
+        assert!(copy_binary(
+            &fields[0],
+            PgType::Timestamp,
+            "1999-12-31T23:59:59.5Z",
+            &mut buf
+        )
+        .is_ok());
+        let mut expected = 1i32.to_be_bytes().to_vec();
+        expected.extend_from_slice(&(-500_000i64).to_be_bytes());
+        assert_eq!(expected, buf);
+    }
+
+    #[test]
+    fn test_numeric() {
+        let encode = |v: &str| {
+            let mut buf = vec![];
+            numeric(v, &mut buf).map(|_| buf)
+        };
+        let words = |ws: &[i16]| ws.iter().flat_map(|w| w.to_be_bytes()).collect::<Vec<u8>>();
+        assert_eq!(Some(words(&[2, 0, 0, 1, 12, 5000])), encode("12.5"));
+        assert_eq!(Some(words(&[1, 1, 0x4000, 0, 1])), encode("-10000"));
+        assert_eq!(Some(words(&[1, -1, 0, 4, 1])), encode("0.0001"));
+        assert_eq!(Some(words(&[0, 0, 0, 2])), encode("-0.00"));
+        assert_eq!(
+            Some(words(&[4, 2, 0, 6, 1, 2345, 6789, 1])),
+            encode("123456789.000100")
+        );
+        assert_eq!(Some(words(&[0, 0, -0x4000, 0])), encode("NaN"));
+        assert_eq!(None, encode("1e3"));
+        assert_eq!(None, encode("."));
+    }
+
+    #[test]
+    fn test_copy_binary_schema() {
+        let schema = Schema::load(Path::new("examples/schema.sql"), None).unwrap();
+        let mut composite = schema
+            .compile(&Parser::new("\\$", ",", Escape::None).unwrap())
+            .unwrap();
+        let mut writer = Format::CopyBinary
+            .writer(&composite.fields, &Settings::default())
+            .unwrap();
+        let mut ctx = Context::new(0);
+        assert!(composite.generate(&mut ctx, &mut std::io::sink()).is_ok());
+        let mut buf = vec![];
+        assert!(writer.write(&ctx, b"", &mut buf).is_ok());
+        let mut rest = &buf[21..];
+        let mut sizes = vec![];
+        for f in &composite.fields {
+            let size = i32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]);
+            rest = &rest[4 + size.max(0) as usize..];
+            if f.kind != Kind::String || f.name == "uuid" {
+                sizes.push((f.name.as_str(), size));
+            }
+        }
+        assert!(rest.is_empty());
+        assert_eq!(
+            vec![
+                ("id", 4),
+                ("uuid", 16),
+                ("age", 4),
+                ("verified", 1),
+                ("born", 4),
+                ("created_at", 8)
+            ],
+            sizes
+        );
+
+        let mut fields = fields();
+        fields[1].sql_type = Some("INTERVAL".to_string());
+        assert!(Format::CopyBinary
+            .writer(&fields, &Settings::default())
+            .is_err());
+    }
+}
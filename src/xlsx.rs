@@ -0,0 +1,210 @@
+use rust_xlsxwriter::{ExcelDateTime, Format, Workbook, Worksheet};
+
+use crate::generust::{Context, Error, FieldDef, Result, Value};
+use crate::output::Output;
+
+/// Rows of an Excel worksheet, the first one holds the header.
+const MAX_ROWS: u32 = 1_048_576;
+
+pub struct Xlsx {
+    path: String,
+    workbook: Workbook,
+    fields: Vec<FieldDef>,
+    sheet: usize,
+    row: u32,
+    header: Format,
+    date: Format,
+    timestamp: Format,
+}
+
+impl Xlsx {
+    pub fn new(path: &str) -> Xlsx {
+        Xlsx {
+            path: path.to_string(),
+            workbook: Workbook::new(),
+            fields: vec![],
+            sheet: 0,
+            row: 0,
+            header: Format::new().set_bold(),
+            date: Format::new().set_num_format("yyyy-mm-dd"),
+            timestamp: Format::new().set_num_format("yyyy-mm-dd hh:mm:ss.000"),
+        }
+    }
+
+    pub fn sheet(&mut self, name: &str, fields: &[FieldDef], count: i32) -> Result<()> {
+        if fields.is_empty() {
+            return Err(Error::Template(
+                "xlsx output requires named fields".to_string(),
+            ));
+        }
+        if count as i64 >= MAX_ROWS as i64 {
+            return Err(Error::Template(format!(
+                "{}: {} records do not fit into a worksheet of {} rows",
+                name, count, MAX_ROWS
+            )));
+        }
+        // A throwaway worksheet to validate the name before adding it to the workbook
+        Worksheet::new().set_name(name)?;
+        let worksheet = self.workbook.add_worksheet_with_constant_memory();
+        worksheet.set_name(name)?;
+        for (col, f) in fields.iter().enumerate() {
+            worksheet.write_string_with_format(0, col as u16, &f.name, &self.header)?;
+        }
+        self.sheet = self.workbook.worksheets().len() - 1;
+        self.fields = fields.to_vec();
+        self.row = 1;
+        Ok(())
+    }
+}
+
+fn write_cell(
+    worksheet: &mut Worksheet,
+    row: u32,
+    col: u16,
+    value: &Value,
+    date: &Format,
+    timestamp: &Format,
+) -> Result<()> {
+    match value {
+        Value::Null => {}
+        Value::Bool(v) => {
+            worksheet.write_boolean(row, col, *v)?;
+        }
+        Value::Int(_) | Value::Float(_) | Value::Decimal(..) => {
+            worksheet.write_number(row, col, value.as_f64().unwrap_or(f64::NAN))?;
+        }
+        Value::Date(v) => {
+            use chrono::Datelike;
+            match ExcelDateTime::from_ymd(v.year() as u16, v.month() as u8, v.day() as u8) {
+                Ok(v) => worksheet.write_datetime_with_format(row, col, v, date)?,
+                Err(_) => worksheet.write_string(row, col, value.to_string())?,
+            };
+        }
+        Value::Timestamp(v) => {
+            use chrono::{Datelike, Timelike};
+            let sec = v.second() as f64 + v.nanosecond() as f64 / 1e9;
+            match ExcelDateTime::from_ymd(v.year() as u16, v.month() as u8, v.day() as u8)
+                .and_then(|d| d.and_hms(v.hour() as u16, v.minute() as u8, sec))
+            {
+                Ok(v) => worksheet.write_datetime_with_format(row, col, v, timestamp)?,
+                Err(_) => worksheet.write_string(row, col, value.to_string())?,
+            };
+        }
+        Value::Str(v) => {
+            worksheet.write_string(row, col, v)?;
+        }
+        Value::Uuid(_) | Value::List(_) | Value::Map(_) => {
+            worksheet.write_string(row, col, value.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+impl Output for Xlsx {
+    fn write(&mut self, ctx: &Context, _text: &[u8]) -> Result<()> {
+        let worksheet = self.workbook.worksheet_from_index(self.sheet)?;
+        for (col, f) in self.fields.iter().enumerate() {
+            if let Some(value) = ctx.typed(f) {
+                write_cell(
+                    worksheet,
+                    self.row,
+                    col as u16,
+                    &value,
+                    &self.date,
+                    &self.timestamp,
+                )?;
+            }
+        }
+        self.row += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        Ok(self.workbook.save(&self.path)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+
+    use chrono::{NaiveDate, TimeZone, Utc};
+
+    use crate::generust::{Context, FieldDef, Kind, Value};
+    use crate::output::Output;
+    use crate::xlsx::Xlsx;
+
+    fn read(path: &std::path::Path, name: &str) -> String {
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(path).unwrap()).unwrap();
+        let mut text = String::new();
+        archive
+            .by_name(name)
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    }
+
+    #[test]
+    fn test_xlsx() {
+        let path = std::env::temp_dir().join(format!("generust-{}.xlsx", std::process::id()));
+        let fields = vec![
+            FieldDef {
+                name: "id".to_string(),
+                kind: Kind::Int,
+                sql_type: None,
+            },
+            FieldDef {
+                name: "born".to_string(),
+                kind: Kind::Date,
+                sql_type: None,
+            },
+            FieldDef {
+                name: "active".to_string(),
+                kind: Kind::Bool,
+                sql_type: None,
+            },
+            FieldDef {
+                name: "name".to_string(),
+                kind: Kind::String,
+                sql_type: None,
+            },
+            FieldDef {
+                name: "seen".to_string(),
+                kind: Kind::Timestamp,
+                sql_type: None,
+            },
+        ];
+        let mut xlsx = Xlsx::new(path.to_str().unwrap());
+        assert!(xlsx.sheet("users", &fields, 1).is_ok());
+        let mut ctx = Context::new(0);
+        ctx.set_field("id", "7".to_string(), Value::Int(7));
+        let born = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        ctx.set_field("born", born.to_string(), Value::Date(born));
+        ctx.set_field("active", "true".to_string(), Value::Bool(true));
+        let seen = Utc.timestamp_opt(946_684_801, 500_000_000).unwrap();
+        ctx.set_field("seen", seen.to_rfc3339(), Value::Timestamp(seen));
+        assert!(xlsx.write(&ctx, b"").is_ok());
+        assert!(xlsx.sheet("orders", &fields[..1], 1).is_ok());
+        assert!(xlsx.write(&ctx, b"").is_ok());
+        assert!(xlsx.sheet("bad[name]", &fields, 1).is_err());
+        assert!(xlsx.sheet("empty", &[], 1).is_err());
+        assert!(xlsx.sheet("huge", &fields, 1_048_575).is_ok());
+        assert!(xlsx.sheet("huger", &fields, 1_048_576).is_err());
+        assert!(xlsx.finish().is_ok());
+
+        let workbook = read(&path, "xl/workbook.xml");
+        assert!(workbook.contains(r#"<sheet name="users""#));
+        assert!(workbook.contains(r#"<sheet name="orders""#));
+        let users = read(&path, "xl/worksheets/sheet1.xml");
+        assert!(users.contains(r#"<c r="A1" s="1" t="inlineStr"><is><t>id</t></is></c>"#));
+        assert!(users.contains(r#"<c r="A2"><v>7</v></c>"#));
+        assert!(users.contains(r#"<c r="B2" s="2"><v>36526</v></c>"#));
+        assert!(users.contains(r#"<c r="C2" t="b"><v>1</v></c>"#));
+        assert!(!users.contains(r#"r="D2""#));
+        assert!(users.contains(r#"<c r="E2" s="3"><v>36526.00001736111</v></c>"#));
+        let orders = read(&path, "xl/worksheets/sheet2.xml");
+        assert!(orders.contains(r#"<c r="A2"><v>7</v></c>"#));
+        std::fs::remove_file(path).unwrap();
+    }
+}